@@ -6,7 +6,22 @@ import io.github.kdroidfilter.rodio.native.destroyPlayer
 import io.github.kdroidfilter.rodio.native.playerClear
 import io.github.kdroidfilter.rodio.native.playerClearCallback
 import io.github.kdroidfilter.rodio.native.playerGetDurationMs
+import io.github.kdroidfilter.rodio.native.playerGetNowPlaying
 import io.github.kdroidfilter.rodio.native.playerGetPositionMs
+import io.github.kdroidfilter.rodio.native.playerGetWallClockMs
+import io.github.kdroidfilter.rodio.native.playerHlsGetCurrentRendition
+import io.github.kdroidfilter.rodio.native.playerHlsGetCurrentVariant
+import io.github.kdroidfilter.rodio.native.playerHlsGetLiveStatus
+import io.github.kdroidfilter.rodio.native.playerHlsGetRenditions
+import io.github.kdroidfilter.rodio.native.playerHlsGetVariants
+import io.github.kdroidfilter.rodio.native.playerHlsGoLive
+import io.github.kdroidfilter.rodio.native.playerHlsSeekLive
+import io.github.kdroidfilter.rodio.native.playerHlsSelectRendition
+import io.github.kdroidfilter.rodio.native.playerHlsSelectVariant
+import io.github.kdroidfilter.rodio.native.playerHlsSetLiveEdgeSegments
+import io.github.kdroidfilter.rodio.native.playerHlsSetMaxBandwidth
+import io.github.kdroidfilter.rodio.native.playerHlsSetPrefetchSegments
+import io.github.kdroidfilter.rodio.native.playerHlsSetTargetLatencyMs
 import io.github.kdroidfilter.rodio.native.playerIsEmpty
 import io.github.kdroidfilter.rodio.native.playerIsPaused
 import io.github.kdroidfilter.rodio.native.playerPause
@@ -18,6 +33,9 @@ import io.github.kdroidfilter.rodio.native.playerPlayUrl
 import io.github.kdroidfilter.rodio.native.playerIsSeekable
 import io.github.kdroidfilter.rodio.native.playerSeekPositionMs
 import io.github.kdroidfilter.rodio.native.playerSetCallback
+import io.github.kdroidfilter.rodio.native.playerSetMirrorTimeoutMs
+import io.github.kdroidfilter.rodio.native.playerSetNetworkProfile
+import io.github.kdroidfilter.rodio.native.playerSetReconnectPolicy
 import io.github.kdroidfilter.rodio.native.playerSetVolume
 import io.github.kdroidfilter.rodio.native.playerStop
 import kotlinx.coroutines.Dispatchers
@@ -25,6 +43,16 @@ import kotlinx.coroutines.withContext
 
 typealias PlaybackCallback = io.github.kdroidfilter.rodio.native.PlaybackCallback
 typealias PlaybackEvent = io.github.kdroidfilter.rodio.native.PlaybackEvent
+typealias ReconnectPolicy = io.github.kdroidfilter.rodio.native.ReconnectPolicy
+typealias HttpRequestOptions = io.github.kdroidfilter.rodio.native.HttpRequestOptions
+typealias HttpHeader = io.github.kdroidfilter.rodio.native.HttpHeader
+typealias HttpCookie = io.github.kdroidfilter.rodio.native.HttpCookie
+typealias HttpAuth = io.github.kdroidfilter.rodio.native.HttpAuth
+typealias HlsVariant = io.github.kdroidfilter.rodio.native.HlsVariant
+typealias HlsLiveStatus = io.github.kdroidfilter.rodio.native.HlsLiveStatus
+typealias HlsRendition = io.github.kdroidfilter.rodio.native.HlsRendition
+typealias PlaylistEntry = io.github.kdroidfilter.rodio.native.PlaylistEntry
+typealias NowPlaying = io.github.kdroidfilter.rodio.native.NowPlaying
 
 class RodioPlayer(
     /** Optional output buffer size in frames. Larger values raise latency but reduce underruns. */
@@ -56,25 +84,39 @@ class RodioPlayer(
         }
     }
 
-    fun playUrl(url: String, loop: Boolean = false, callback: PlaybackCallback? = null) {
+    fun playUrl(
+        url: String,
+        loop: Boolean = false,
+        callback: PlaybackCallback? = null,
+        options: HttpRequestOptions? = null,
+    ) {
         if (callback != null) setCallback(callback)
-        playerPlayUrl(requireHandle(), url, loop)
+        playerPlayUrl(requireHandle(), url, loop, options)
     }
 
-    suspend fun playUrlAsync(url: String, loop: Boolean = false, callback: PlaybackCallback? = null) {
+    suspend fun playUrlAsync(
+        url: String,
+        loop: Boolean = false,
+        callback: PlaybackCallback? = null,
+        options: HttpRequestOptions? = null,
+    ) {
         withContext(playbackDispatcher) {
-            playUrl(url, loop, callback)
+            playUrl(url, loop, callback, options)
         }
     }
 
-    fun playRadio(url: String, callback: PlaybackCallback? = null) {
+    fun playRadio(url: String, callback: PlaybackCallback? = null, options: HttpRequestOptions? = null) {
         if (callback != null) setCallback(callback)
-        playerPlayRadio(requireHandle(), url)
+        playerPlayRadio(requireHandle(), url, options)
     }
 
-    suspend fun playRadioAsync(url: String, callback: PlaybackCallback? = null) {
+    suspend fun playRadioAsync(
+        url: String,
+        callback: PlaybackCallback? = null,
+        options: HttpRequestOptions? = null,
+    ) {
         withContext(playbackDispatcher) {
-            playRadio(url, callback)
+            playRadio(url, callback, options)
         }
     }
 
@@ -103,6 +145,12 @@ class RodioPlayer(
 
     fun getDurationMs(): Long? = playerGetDurationMs(requireHandle())?.toLong()
 
+    /** Wall-clock time (epoch ms) of what is playing, from HLS `EXT-X-PROGRAM-DATE-TIME`; `null` when undated. */
+    fun getWallClockMs(): Long? = playerGetWallClockMs(requireHandle())?.toLong()
+
+    /** What the current source says is playing, as last passed to `PlaybackCallback.onNowPlaying`. */
+    fun getNowPlaying(): NowPlaying = playerGetNowPlaying(requireHandle())
+
     fun seekToMs(positionMs: Long) {
         require(positionMs >= 0) { "positionMs must be >= 0" }
         playerSeekPositionMs(requireHandle(), positionMs.toULong())
@@ -114,6 +162,80 @@ class RodioPlayer(
         playerSetVolume(requireHandle(), volume)
     }
 
+    /** Retry policy for the initial connection and for live streams that drop mid-playback. */
+    fun setReconnectPolicy(policy: ReconnectPolicy) {
+        playerSetReconnectPolicy(requireHandle(), policy)
+    }
+
+    /** How long each mirror listed in a radio playlist may take to answer before the next is tried. */
+    fun setMirrorTimeoutMs(timeoutMs: Long) {
+        require(timeoutMs > 0) { "timeoutMs must be > 0" }
+        playerSetMirrorTimeoutMs(requireHandle(), timeoutMs.toULong())
+    }
+
+    /** Uses a profile created with [RodioHttp.createProfile]; `null` restores the global HTTP settings. */
+    fun setNetworkProfile(profile: String?) {
+        playerSetNetworkProfile(requireHandle(), profile)
+    }
+
+    /** Variants of the HLS master playlist being played; empty for other sources. */
+    fun getHlsVariants(): List<HlsVariant> = playerHlsGetVariants(requireHandle())
+
+    fun getHlsCurrentVariant(): Int? = playerHlsGetCurrentVariant(requireHandle())?.toInt()
+
+    /** Pins the HLS stream to [HlsVariant.index]; `null` returns to automatic bitrate selection. */
+    fun selectHlsVariant(index: Int?) {
+        require(index == null || index >= 0) { "index must be >= 0" }
+        playerHlsSelectVariant(requireHandle(), index?.toUInt())
+    }
+
+    /** Caps automatic HLS variant selection in bits per second; `null` removes the cap. */
+    fun setHlsMaxBandwidth(bitsPerSecond: Long?) {
+        require(bitsPerSecond == null || bitsPerSecond > 0) { "bitsPerSecond must be > 0" }
+        playerHlsSetMaxBandwidth(requireHandle(), bitsPerSecond?.toULong())
+    }
+
+    /** Audio renditions (`EXT-X-MEDIA`) of the HLS master playlist being played; empty for other sources. */
+    fun getHlsRenditions(): List<HlsRendition> = playerHlsGetRenditions(requireHandle())
+
+    fun getHlsCurrentRendition(): HlsRendition? = playerHlsGetCurrentRendition(requireHandle())
+
+    /** Switches to another audio rendition, e.g. a language; playback continues from the current position. */
+    fun selectHlsRendition(groupId: String, name: String) {
+        playerHlsSelectRendition(requireHandle(), groupId, name)
+    }
+
+    /** Window and latency behind the live edge for live HLS; `null` for other sources. */
+    fun getHlsLiveStatus(): HlsLiveStatus? = playerHlsGetLiveStatus(requireHandle())
+
+    /** Time-shifts a live HLS stream to [behindLiveMs] behind the live edge, within the playlist window. */
+    fun seekLive(behindLiveMs: Long) {
+        require(behindLiveMs >= 0) { "behindLiveMs must be >= 0" }
+        playerHlsSeekLive(requireHandle(), behindLiveMs.toULong())
+    }
+
+    fun goLive() {
+        playerHlsGoLive(requireHandle())
+    }
+
+    /** Segments kept between the live edge and where live HLS and DASH playback starts (default 3). */
+    fun setHlsLiveEdgeSegments(segments: Int) {
+        require(segments >= 0) { "segments must be >= 0" }
+        playerHlsSetLiveEdgeSegments(requireHandle(), segments.toUInt())
+    }
+
+    /** Distance from the live edge for low-latency HLS; `null` uses the playlist's `PART-HOLD-BACK`, also the minimum. Encrypted streams ignore it; see [HlsLiveStatus.partialSegments]. */
+    fun setHlsTargetLatencyMs(latencyMs: Long?) {
+        require(latencyMs == null || latencyMs >= 0) { "latencyMs must be >= 0" }
+        playerHlsSetTargetLatencyMs(requireHandle(), latencyMs?.toULong())
+    }
+
+    /** Upcoming HLS and DASH segments downloaded in the background (default 2; 0 disables prefetching). */
+    fun setHlsPrefetchSegments(segments: Int) {
+        require(segments >= 0) { "segments must be >= 0" }
+        playerHlsSetPrefetchSegments(requireHandle(), segments.toUInt())
+    }
+
     fun setCallback(callback: PlaybackCallback?) {
         if (callback == null) {
             playerClearCallback(requireHandle())
@@ -1,19 +1,120 @@
 package io.github.kdroidfilter.rodio
 
+import io.github.kdroidfilter.rodio.native.httpAddPin
 import io.github.kdroidfilter.rodio.native.httpAddRootCertPem
+import io.github.kdroidfilter.rodio.native.httpClearClientIdentity
+import io.github.kdroidfilter.rodio.native.httpClearDefaultHeaders
+import io.github.kdroidfilter.rodio.native.httpClearPins
+import io.github.kdroidfilter.rodio.native.httpClearProxy
 import io.github.kdroidfilter.rodio.native.httpClearRootCerts
+import io.github.kdroidfilter.rodio.native.httpCreateProfile
+import io.github.kdroidfilter.rodio.native.httpRemoveProfile
 import io.github.kdroidfilter.rodio.native.httpSetAllowInvalidCerts
+import io.github.kdroidfilter.rodio.native.httpSetClientIdentityPem
+import io.github.kdroidfilter.rodio.native.httpSetClientIdentityPkcs12
+import io.github.kdroidfilter.rodio.native.httpSetConnectTimeoutMs
+import io.github.kdroidfilter.rodio.native.httpSetDefaultHeader
+import io.github.kdroidfilter.rodio.native.httpSetIpFamily
+import io.github.kdroidfilter.rodio.native.httpSetMaxRedirects
+import io.github.kdroidfilter.rodio.native.httpSetProxy
+import io.github.kdroidfilter.rodio.native.httpSetReadTimeoutMs
+import io.github.kdroidfilter.rodio.native.httpSetUserAgent
 
+typealias IpFamily = io.github.kdroidfilter.rodio.native.IpFamily
+
+/**
+ * HTTP settings. Every setter takes an optional [profile]: `null` changes the global
+ * defaults, a name changes a profile created with [createProfile] and assigned with
+ * [RodioPlayer.setNetworkProfile].
+ */
 object RodioHttp {
-    fun setAllowInvalidCerts(allow: Boolean) {
-        httpSetAllowInvalidCerts(allow)
+    /** Creates (or resets) a named profile from the current global defaults. */
+    fun createProfile(name: String) {
+        httpCreateProfile(name)
+    }
+
+    fun removeProfile(name: String) {
+        httpRemoveProfile(name)
+    }
+
+    fun setAllowInvalidCerts(allow: Boolean, profile: String? = null) {
+        httpSetAllowInvalidCerts(allow, profile)
+    }
+
+    fun addRootCertPem(pem: String, profile: String? = null) {
+        httpAddRootCertPem(pem, profile)
+    }
+
+    fun clearRootCerts(profile: String? = null) {
+        httpClearRootCerts(profile)
+    }
+
+    /** Client certificate for mutual TLS: the certificate chain (leaf first) followed by the private key. */
+    fun setClientIdentityPem(pem: String, profile: String? = null) {
+        httpSetClientIdentityPem(pem, profile)
+    }
+
+    fun setClientIdentityPkcs12(pkcs12: ByteArray, password: String, profile: String? = null) {
+        httpSetClientIdentityPkcs12(pkcs12, password, profile)
+    }
+
+    fun clearClientIdentity(profile: String? = null) {
+        httpClearClientIdentity(profile)
+    }
+
+    /**
+     * Pins [host] to a SHA-256 SubjectPublicKeyInfo hash (`sha256//<base64>`, base64 or hex).
+     * Connections whose certificate chain has none of the host's pins fail with
+     * `RodioError.CertificatePinMismatch`.
+     */
+    fun addPin(host: String, sha256: String, profile: String? = null) {
+        httpAddPin(host, sha256, profile)
+    }
+
+    fun clearPins(profile: String? = null) {
+        httpClearPins(profile)
+    }
+
+    /** `null` restores the default (no connect timeout). */
+    fun setConnectTimeoutMs(timeoutMs: Long?, profile: String? = null) {
+        require(timeoutMs == null || timeoutMs > 0) { "timeoutMs must be > 0" }
+        httpSetConnectTimeoutMs(timeoutMs?.toULong(), profile)
+    }
+
+    /** Covers waiting for response headers and every body read. `null` restores the default (no read timeout). */
+    fun setReadTimeoutMs(timeoutMs: Long?, profile: String? = null) {
+        require(timeoutMs == null || timeoutMs > 0) { "timeoutMs must be > 0" }
+        httpSetReadTimeoutMs(timeoutMs?.toULong(), profile)
+    }
+
+    /** Supports `http://`, `https://`, `socks5://` and `socks5h://` proxy URLs. */
+    fun setProxy(url: String, username: String? = null, password: String? = null, profile: String? = null) {
+        httpSetProxy(url, username, password, profile)
+    }
+
+    fun clearProxy(profile: String? = null) {
+        httpClearProxy(profile)
+    }
+
+    fun setUserAgent(userAgent: String, profile: String? = null) {
+        httpSetUserAgent(userAgent, profile)
+    }
+
+    fun setDefaultHeader(name: String, value: String, profile: String? = null) {
+        httpSetDefaultHeader(name, value, profile)
+    }
+
+    fun clearDefaultHeaders(profile: String? = null) {
+        httpClearDefaultHeaders(profile)
     }
 
-    fun addRootCertPem(pem: String) {
-        httpAddRootCertPem(pem)
+    /** `0` disables redirects. */
+    fun setMaxRedirects(maxRedirects: Int, profile: String? = null) {
+        require(maxRedirects >= 0) { "maxRedirects must be >= 0" }
+        httpSetMaxRedirects(maxRedirects.toUInt(), profile)
     }
 
-    fun clearRootCerts() {
-        httpClearRootCerts()
+    fun setIpFamily(family: IpFamily, profile: String? = null) {
+        httpSetIpFamily(family, profile)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,20 @@
+package io.github.kdroidfilter.rodio
+
+import io.github.kdroidfilter.rodio.native.parsePlaylist
+import kotlinx.coroutines.Dispatchers
+import kotlinx.coroutines.withContext
+
+/** Reads M3U / EXTM3U, PLS, XSPF and ASX playlists. */
+object RodioPlaylist {
+    /**
+     * Entries of the playlist at [urlOrPath], an HTTP(S) URL or a local file, in playlist
+     * order. Relative entry locations are resolved against the playlist's own location.
+     */
+    fun parse(urlOrPath: String, options: HttpRequestOptions? = null): List<PlaylistEntry> =
+        parsePlaylist(urlOrPath, options)
+
+    suspend fun parseAsync(urlOrPath: String, options: HttpRequestOptions? = null): List<PlaylistEntry> =
+        withContext(Dispatchers.IO) {
+            parse(urlOrPath, options)
+        }
+}
@@ -0,0 +1,352 @@
+//! Adaptive bitrate selection between HLS variant streams.
+
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::error::RodioError;
+use crate::pcm::BufferLevel;
+
+const FAST_HALF_LIFE_SECS: f64 = 2.0;
+const SLOW_HALF_LIFE_SECS: f64 = 5.0;
+/// Downloads this small mostly measure latency, not bandwidth.
+const MIN_SAMPLE_BYTES: usize = 4 * 1024;
+/// Share of the estimated throughput a variant's bandwidth may use.
+const BANDWIDTH_SAFETY: f64 = 0.8;
+/// With less audio buffered than this, the budget is halved to refill quickly.
+const LOW_BUFFER: Duration = Duration::from_millis(1000);
+/// Switching to a higher variant waits until this much audio is buffered.
+const UPSWITCH_BUFFER: Duration = Duration::from_millis(2500);
+
+/// A playable `EXT-X-STREAM-INF` entry of an HLS master playlist, in playlist order.
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct HlsVariant {
+    pub index: u32,
+    /// Peak bit rate in bits per second (`BANDWIDTH`).
+    pub bandwidth: u64,
+    /// Average bit rate in bits per second (`AVERAGE-BANDWIDTH`), when advertised.
+    pub average_bandwidth: Option<u64>,
+    pub codecs: Option<String>,
+    pub url: String,
+}
+
+/// Exponentially weighted moving average, weighted by download time.
+struct Ewma {
+    alpha_per_sec: f64,
+    estimate: f64,
+    total_weight: f64,
+}
+
+impl Ewma {
+    fn new(half_life_secs: f64) -> Self {
+        Self {
+            alpha_per_sec: 0.5f64.powf(1.0 / half_life_secs),
+            estimate: 0.0,
+            total_weight: 0.0,
+        }
+    }
+
+    fn sample(&mut self, weight: f64, value: f64) {
+        let alpha = self.alpha_per_sec.powf(weight);
+        self.estimate = value * (1.0 - alpha) + alpha * self.estimate;
+        self.total_weight += weight;
+    }
+
+    fn value(&self) -> f64 {
+        // Undo the bias towards the zero the average started from.
+        let zero_factor = 1.0 - self.alpha_per_sec.powf(self.total_weight);
+        self.estimate / zero_factor
+    }
+}
+
+/// Network throughput estimated from segment download timings. The slower of a
+/// fast and a slow average is used, so drops are followed quickly and
+/// recoveries cautiously.
+pub struct ThroughputEstimator {
+    fast: Ewma,
+    slow: Ewma,
+}
+
+impl ThroughputEstimator {
+    pub fn new() -> Self {
+        Self {
+            fast: Ewma::new(FAST_HALF_LIFE_SECS),
+            slow: Ewma::new(SLOW_HALF_LIFE_SECS),
+        }
+    }
+
+    pub fn add_sample(&mut self, bytes: usize, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if bytes < MIN_SAMPLE_BYTES || secs <= 0.0 {
+            return;
+        }
+        let bits_per_sec = bytes as f64 * 8.0 / secs;
+        self.fast.sample(secs, bits_per_sec);
+        self.slow.sample(secs, bits_per_sec);
+    }
+
+    /// Bits per second, once at least one segment has been measured.
+    pub fn estimate(&self) -> Option<f64> {
+        if self.fast.total_weight == 0.0 {
+            return None;
+        }
+        Some(self.fast.value().min(self.slow.value()))
+    }
+}
+
+/// Variant choice of one HLS playback, shared between the segment reader and
+/// the player API.
+pub struct HlsSession {
+    variants: Vec<HlsVariant>,
+    state: Mutex<AbrState>,
+    buffer: OnceLock<BufferLevel>,
+}
+
+struct AbrState {
+    current: usize,
+    /// Manual override; `None` lets the estimator decide.
+    selected: Option<usize>,
+    max_bandwidth: Option<u64>,
+}
+
+impl HlsSession {
+    pub fn new(variants: Vec<HlsVariant>, max_bandwidth: Option<u64>) -> Self {
+        // Players start with the first listed variant, as the HLS spec suggests.
+        let current = choose_variant(&variants, 0, None, None, max_bandwidth);
+        Self {
+            variants,
+            state: Mutex::new(AbrState {
+                current,
+                selected: None,
+                max_bandwidth,
+            }),
+            buffer: OnceLock::new(),
+        }
+    }
+
+    pub fn variants(&self) -> Vec<HlsVariant> {
+        self.variants.clone()
+    }
+
+    pub fn current(&self) -> Option<u32> {
+        if self.variants.is_empty() {
+            return None;
+        }
+        let state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        u32::try_from(state.current).ok()
+    }
+
+    /// Pins playback to one variant; `None` returns to automatic selection.
+    pub fn select(&self, index: Option<u32>) -> Result<(), RodioError> {
+        let selected = match index {
+            Some(index) => {
+                let position = usize::try_from(index).unwrap_or(usize::MAX);
+                if position >= self.variants.len() {
+                    return Err(RodioError::HlsVariantNotFound(index));
+                }
+                Some(position)
+            }
+            None => None,
+        };
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.selected = selected;
+        Ok(())
+    }
+
+    pub fn set_max_bandwidth(&self, max_bandwidth: Option<u64>) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.max_bandwidth = max_bandwidth;
+    }
+
+    /// Lets buffer occupancy take part in the choice once the decoder worker runs.
+    pub fn attach_buffer(&self, buffer: BufferLevel) {
+        let _ = self.buffer.set(buffer);
+    }
+
+    /// Decoded audio waiting to be played, once the decoder worker runs.
+    pub fn buffered(&self) -> Option<Duration> {
+        self.buffer.get().map(BufferLevel::buffered)
+    }
+
+    /// Decides the variant for the next segment and records it as current.
+    pub fn next_variant(&self, throughput: Option<f64>) -> usize {
+        let buffered = self.buffered();
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.current = match state.selected {
+            Some(selected) => selected,
+            None => choose_variant(
+                &self.variants,
+                state.current,
+                throughput,
+                buffered,
+                state.max_bandwidth,
+            ),
+        };
+        state.current
+    }
+}
+
+/// Picks the highest variant the throughput budget affords, within the
+/// bandwidth cap. Without a measurement the current variant is kept if allowed.
+fn choose_variant(
+    variants: &[HlsVariant],
+    current: usize,
+    throughput: Option<f64>,
+    buffered: Option<Duration>,
+    max_bandwidth: Option<u64>,
+) -> usize {
+    if variants.is_empty() {
+        return 0;
+    }
+    let allowed = |variant: &&HlsVariant| max_bandwidth.is_none_or(|max| variant.bandwidth <= max);
+    let lowest = variants
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, variant)| variant.bandwidth)
+        .map(|(index, _)| index)
+        .unwrap_or(0);
+    let current_allowed = variants
+        .get(current)
+        .is_some_and(|variant| allowed(&variant));
+    let budget = match throughput {
+        Some(throughput) => {
+            let low = buffered.is_some_and(|buffered| buffered < LOW_BUFFER);
+            throughput * BANDWIDTH_SAFETY * if low { 0.5 } else { 1.0 }
+        }
+        None if current_allowed => return current,
+        None => f64::INFINITY,
+    };
+    let target = variants
+        .iter()
+        .enumerate()
+        .filter(|(_, variant)| allowed(variant) && (variant.bandwidth as f64) <= budget)
+        .max_by_key(|(_, variant)| variant.bandwidth)
+        .map(|(index, _)| index)
+        .unwrap_or(lowest);
+    let upswitch = variants[target].bandwidth > variants.get(current).map_or(0, |v| v.bandwidth);
+    let buffer_short = buffered.is_some_and(|buffered| buffered < UPSWITCH_BUFFER);
+    if upswitch && buffer_short && current_allowed {
+        current
+    } else {
+        target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variants(bandwidths: &[u64]) -> Vec<HlsVariant> {
+        bandwidths
+            .iter()
+            .enumerate()
+            .map(|(index, &bandwidth)| HlsVariant {
+                index: index as u32,
+                bandwidth,
+                average_bandwidth: None,
+                codecs: None,
+                url: format!("https://example.com/{index}.m3u8"),
+            })
+            .collect()
+    }
+
+    fn secs(secs: u64) -> Option<Duration> {
+        Some(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn starts_with_the_first_listed_variant() {
+        let variants = variants(&[256_000, 64_000, 128_000]);
+        let session = HlsSession::new(variants.clone(), None);
+        assert_eq!(session.current(), Some(0));
+        // Unless the cap excludes it; then the best allowed one.
+        let capped = HlsSession::new(variants, Some(200_000));
+        assert_eq!(capped.current(), Some(2));
+        assert_eq!(HlsSession::new(Vec::new(), None).current(), None);
+    }
+
+    #[test]
+    fn follows_the_throughput_budget() {
+        let variants = variants(&[256_000, 64_000, 128_000]);
+        // 80% of 200 kbit/s affords the 128 kbit/s variant.
+        assert_eq!(
+            choose_variant(&variants, 0, Some(200_000.0), secs(10), None),
+            2
+        );
+        assert_eq!(
+            choose_variant(&variants, 0, Some(1000.0), secs(10), None),
+            1
+        );
+        // A nearly empty buffer halves the budget.
+        let low = Some(Duration::from_millis(500));
+        assert_eq!(choose_variant(&variants, 0, Some(300_000.0), low, None), 1);
+        assert_eq!(
+            choose_variant(&variants, 0, Some(300_000.0), secs(10), None),
+            2
+        );
+        assert_eq!(choose_variant(&variants, 2, None, secs(10), None), 2);
+        assert_eq!(
+            choose_variant(&variants, 0, Some(1e9), secs(10), Some(150_000)),
+            2
+        );
+    }
+
+    #[test]
+    fn switches_up_only_with_enough_buffer() {
+        let variants = variants(&[256_000, 64_000, 128_000]);
+        let fast = Some(1e9);
+        let short = Some(Duration::from_millis(1500));
+        assert_eq!(choose_variant(&variants, 1, fast, short, None), 1);
+        assert_eq!(choose_variant(&variants, 1, fast, secs(3), None), 0);
+        assert_eq!(choose_variant(&variants, 1, fast, None, None), 0);
+        // A current variant over the cap is left even with a short buffer.
+        assert_eq!(choose_variant(&variants, 0, fast, short, Some(100_000)), 1);
+    }
+
+    #[test]
+    fn single_variant_is_always_chosen() {
+        let single = variants(&[128_000]);
+        for throughput in [None, Some(1.0), Some(1e9)] {
+            assert_eq!(choose_variant(&single, 0, throughput, secs(0), None), 0);
+            assert_eq!(choose_variant(&single, 0, throughput, secs(9), Some(1)), 0);
+        }
+        assert_eq!(choose_variant(&[], 3, Some(1e9), None, None), 0);
+    }
+
+    #[test]
+    fn manual_selection_overrides_the_estimate() {
+        let session = HlsSession::new(variants(&[256_000, 64_000, 128_000]), None);
+        session.select(Some(1)).unwrap();
+        assert_eq!(session.next_variant(Some(1e9)), 1);
+        assert!(matches!(
+            session.select(Some(3)),
+            Err(RodioError::HlsVariantNotFound(3))
+        ));
+        session.select(None).unwrap();
+        assert_eq!(session.next_variant(Some(1e9)), 0);
+        assert_eq!(session.current(), Some(0));
+    }
+
+    #[test]
+    fn estimates_throughput_from_downloads() {
+        let mut estimator = ThroughputEstimator::new();
+        assert_eq!(estimator.estimate(), None);
+        estimator.add_sample(MIN_SAMPLE_BYTES - 1, Duration::from_millis(10));
+        estimator.add_sample(64 * 1024, Duration::ZERO);
+        assert_eq!(estimator.estimate(), None);
+
+        // One sample is taken as it is, without the bias towards zero.
+        estimator.add_sample(64 * 1024, Duration::from_millis(500));
+        let first = estimator.estimate().unwrap();
+        assert!((first - 1_048_576.0).abs() < 1.0, "{first}");
+
+        for _ in 0..30 {
+            estimator.add_sample(1_000_000, Duration::from_secs(1));
+        }
+        let steady = estimator.estimate().unwrap();
+        assert!((steady - 8e6).abs() < 8e4, "{steady}");
+        // A drop is followed by the fast average.
+        estimator.add_sample(125_000, Duration::from_secs(1));
+        let dropped = estimator.estimate().unwrap();
+        assert!((1e6..6.5e6).contains(&dropped), "{dropped}");
+    }
+}
@@ -0,0 +1,315 @@
+//! Metadata delivered when the audio it belongs to is played.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::RodioError;
+use crate::pcm::PlaybackClock;
+
+const CUE_POLL: Duration = Duration::from_millis(50);
+/// Cues waiting on playback that is stalled or far behind are dropped oldest first.
+const MAX_PENDING_CUES: usize = 64;
+/// How far a stream's declared bitrate may place metadata from where the
+/// decoder actually is before the declared bitrate is ignored.
+const MAX_BITRATE_DRIFT: Duration = Duration::from_secs(1);
+/// Decoded audio needed before a stream's byte rate is estimated from it.
+const MIN_RATE_HISTORY: Duration = Duration::from_secs(2);
+
+pub type MetadataEntries = Vec<(String, String)>;
+
+/// Metadata queued by stream time, measured from the start of the decoded stream.
+#[derive(Default)]
+pub struct MetadataCues {
+    queue: Mutex<VecDeque<(Duration, MetadataEntries)>>,
+}
+
+impl MetadataCues {
+    pub fn push(&self, at: Duration, entries: MetadataEntries) {
+        if entries.is_empty() {
+            return;
+        }
+        let mut queue = self.queue.lock().unwrap_or_else(|err| err.into_inner());
+        if queue.len() >= MAX_PENDING_CUES {
+            queue.pop_front();
+        }
+        // Cues arrive almost in order; keep the queue sorted for the dispatcher.
+        let index = queue.partition_point(|(time, _)| *time <= at);
+        queue.insert(index, (at, entries));
+    }
+
+    fn take_due(&self, played: Duration) -> MetadataEntries {
+        let mut queue = self.queue.lock().unwrap_or_else(|err| err.into_inner());
+        let mut due = Vec::new();
+        while queue.front().is_some_and(|(time, _)| *time <= played) {
+            if let Some((_, entries)) = queue.pop_front() {
+                due.extend(entries);
+            }
+        }
+        due
+    }
+}
+
+/// Where metadata found in one segment goes: the shared queue, offset by the
+/// stream time at which the segment starts.
+#[derive(Clone)]
+pub struct SegmentCues {
+    pub cues: Arc<MetadataCues>,
+    pub start: Duration,
+}
+
+impl SegmentCues {
+    pub fn push(&self, offset: Duration, entries: MetadataEntries) {
+        self.cues.push(self.start + offset, entries);
+    }
+}
+
+/// Where metadata read in-band from a stream goes: the shared queue, timed by
+/// the decoder worker's clock. Metadata read before the clock is attached is
+/// due at once.
+#[derive(Clone, Default)]
+pub struct DecodedCues {
+    pub cues: Arc<MetadataCues>,
+    clock: Arc<OnceLock<PlaybackClock>>,
+}
+
+impl DecodedCues {
+    pub fn attach_clock(&self, clock: PlaybackClock) {
+        let _ = self.clock.set(clock);
+    }
+
+    fn decoded(&self) -> Option<Duration> {
+        self.clock.get().map(PlaybackClock::decoded)
+    }
+}
+
+/// Times the metadata found in one connection's byte stream by the byte
+/// offset it sits at. Symphonia only reads from the stream once it has taken
+/// everything read before, so at each read the decoder has consumed every
+/// byte returned so far: offsets are turned into stream time from that
+/// position, or from the stream's declared bitrate while the two agree.
+pub struct ByteCues {
+    cues: DecodedCues,
+    bytes_per_sec: Option<u64>,
+    /// Bytes consumed and stream time decoded when the clock was first seen.
+    origin: Option<(u64, Duration)>,
+    consumed: u64,
+    /// Metadata at offsets the decoder has not consumed yet.
+    pending: VecDeque<(u64, MetadataEntries)>,
+}
+
+impl ByteCues {
+    pub fn new(cues: DecodedCues, bitrate_kbps: Option<u32>) -> Self {
+        Self {
+            cues,
+            bytes_per_sec: bitrate_kbps.map(|kbps| u64::from(kbps) * 1000 / 8),
+            origin: None,
+            consumed: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Queues metadata found `offset` bytes into the stream.
+    pub fn push(&mut self, offset: u64, entries: MetadataEntries) {
+        if offset <= self.consumed {
+            self.resolve(offset, entries);
+        } else {
+            self.pending.push_back((offset, entries));
+        }
+    }
+
+    /// Records that the decoder has consumed the first `consumed` bytes.
+    pub fn consumed(&mut self, consumed: u64) {
+        self.consumed = consumed;
+        if self.origin.is_none() {
+            self.origin = self.cues.decoded().map(|decoded| (consumed, decoded));
+        }
+        while self
+            .pending
+            .front()
+            .is_some_and(|(offset, _)| *offset <= consumed)
+        {
+            if let Some((offset, entries)) = self.pending.pop_front() {
+                self.resolve(offset, entries);
+            }
+        }
+    }
+
+    fn resolve(&self, offset: u64, entries: MetadataEntries) {
+        let Some(decoded) = self.cues.decoded() else {
+            self.cues.cues.push(Duration::ZERO, entries);
+            return;
+        };
+        let rate = self.bytes_per_sec.or_else(|| self.measured_rate(decoded));
+        let behind = rate.map_or(Duration::ZERO, |rate| {
+            bytes_to_time(self.consumed.saturating_sub(offset), rate)
+        });
+        let by_consumption = decoded.saturating_sub(behind);
+        let by_bitrate = self
+            .bytes_per_sec
+            .zip(self.origin)
+            .map(|(rate, (origin_bytes, origin_time))| {
+                origin_time + bytes_to_time(offset.saturating_sub(origin_bytes), rate)
+            })
+            .filter(|at| at.abs_diff(by_consumption) <= MAX_BITRATE_DRIFT);
+        self.cues
+            .cues
+            .push(by_bitrate.unwrap_or(by_consumption), entries);
+    }
+
+    /// Bytes per second decoded since the origin, once there is enough history.
+    fn measured_rate(&self, decoded: Duration) -> Option<u64> {
+        let (origin_bytes, origin_time) = self.origin?;
+        let elapsed = decoded.checked_sub(origin_time)?;
+        if elapsed < MIN_RATE_HISTORY {
+            return None;
+        }
+        let bytes = self.consumed.saturating_sub(origin_bytes);
+        Some((bytes as f64 / elapsed.as_secs_f64()) as u64).filter(|rate| *rate > 0)
+    }
+}
+
+fn bytes_to_time(bytes: u64, bytes_per_sec: u64) -> Duration {
+    Duration::from_secs_f64(bytes as f64 / bytes_per_sec.max(1) as f64)
+}
+
+/// Fires cues once `start` plus the played time reaches them. Runs on its own
+/// thread so callbacks never block the audio or decoder threads; it exits when
+/// the source is dropped.
+pub fn spawn_cue_dispatcher<F>(
+    cues: Arc<MetadataCues>,
+    clock: PlaybackClock,
+    start: Duration,
+    notify: F,
+) -> Result<(), RodioError>
+where
+    F: Fn(MetadataEntries) + Send + 'static,
+{
+    thread::Builder::new()
+        .name("rodio-kt-metadata".to_string())
+        .spawn(move || {
+            while !clock.is_stopped() {
+                let due = cues.take_due(start + clock.played());
+                if !due.is_empty() {
+                    notify(due);
+                }
+                thread::sleep(CUE_POLL);
+            }
+        })
+        .map(|_| ())
+        .map_err(|err| RodioError::Internal(format!("failed to spawn metadata thread: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(title: &str) -> MetadataEntries {
+        vec![("StreamTitle".to_string(), title.to_string())]
+    }
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    /// Byte cues of a stream whose clock the test moves.
+    fn byte_cues(bitrate_kbps: Option<u32>) -> (ByteCues, Arc<MetadataCues>, PlaybackClock) {
+        let decoded = DecodedCues::default();
+        let clock = PlaybackClock::manual();
+        decoded.attach_clock(clock.clone());
+        let cues = decoded.cues.clone();
+        (ByteCues::new(decoded, bitrate_kbps), cues, clock)
+    }
+
+    #[test]
+    fn cues_fire_in_order_once_due() {
+        let cues = MetadataCues::default();
+        cues.push(secs(2.0), entries("b"));
+        cues.push(secs(1.0), entries("a"));
+        cues.push(secs(3.0), Vec::new());
+        cues.push(secs(2.0), entries("c"));
+        assert!(cues.take_due(secs(0.999)).is_empty());
+        assert_eq!(cues.take_due(secs(1.0)), entries("a"));
+        assert_eq!(
+            cues.take_due(secs(60.0)),
+            [entries("b"), entries("c")].concat()
+        );
+        assert!(cues.take_due(secs(60.0)).is_empty());
+
+        for index in 0..MAX_PENDING_CUES + 2 {
+            cues.push(secs(index as f64), entries(&index.to_string()));
+        }
+        let due = cues.take_due(Duration::MAX);
+        assert_eq!(due.len(), MAX_PENDING_CUES);
+        assert_eq!(due[0].1, "2");
+    }
+
+    #[test]
+    fn converts_bytes_to_time() {
+        assert_eq!(bytes_to_time(16_000, 16_000), secs(1.0));
+        assert_eq!(bytes_to_time(8_000, 16_000), secs(0.5));
+        assert_eq!(bytes_to_time(1, 0), secs(1.0));
+        assert_eq!(bytes_to_time(0, 16_000), Duration::ZERO);
+    }
+
+    #[test]
+    fn metadata_without_a_clock_is_due_at_once() {
+        let decoded = DecodedCues::default();
+        let cues = decoded.cues.clone();
+        let mut byte_cues = ByteCues::new(decoded, Some(128));
+        byte_cues.push(100, entries("a"));
+        assert!(cues.take_due(Duration::ZERO).is_empty());
+        byte_cues.consumed(100);
+        assert_eq!(cues.take_due(Duration::ZERO), entries("a"));
+    }
+
+    #[test]
+    fn times_metadata_by_the_declared_bitrate() {
+        // 128 kbit/s is 16000 bytes a second.
+        let (mut byte_cues, cues, clock) = byte_cues(Some(128));
+        byte_cues.consumed(0);
+        byte_cues.push(32_000, entries("a"));
+        byte_cues.consumed(31_999);
+        assert!(cues.take_due(Duration::MAX).is_empty());
+
+        // The decoder ran half a second past the title: the bitrate places it exactly.
+        clock.advance_to(secs(2.0), Duration::ZERO);
+        byte_cues.consumed(40_000);
+        assert!(cues.take_due(secs(1.999)).is_empty());
+        assert_eq!(cues.take_due(secs(2.0)), entries("a"));
+
+        // Metadata the decoder has already consumed is timed straight away.
+        byte_cues.push(40_000, entries("b"));
+        assert_eq!(cues.take_due(secs(2.5)), entries("b"));
+    }
+
+    #[test]
+    fn ignores_a_declared_bitrate_that_drifts() {
+        // Declares 320 kbit/s but actually plays 128 kbit/s.
+        let (mut byte_cues, cues, clock) = byte_cues(Some(320));
+        byte_cues.consumed(0);
+        clock.advance_to(secs(10.0), Duration::ZERO);
+        byte_cues.consumed(160_000);
+        byte_cues.push(160_000, entries("a"));
+        assert!(cues.take_due(secs(9.999)).is_empty());
+        assert_eq!(cues.take_due(secs(10.0)), entries("a"));
+    }
+
+    #[test]
+    fn measures_the_rate_without_a_bitrate() {
+        let (mut byte_cues, cues, clock) = byte_cues(None);
+        byte_cues.consumed(0);
+        // Too little history to measure: timed at the decoder's position.
+        clock.advance_to(secs(1.0), Duration::ZERO);
+        byte_cues.consumed(16_000);
+        byte_cues.push(8_000, entries("a"));
+        assert_eq!(cues.take_due(secs(1.0)), entries("a"));
+
+        clock.advance_to(secs(4.0), Duration::ZERO);
+        byte_cues.consumed(64_000);
+        byte_cues.push(48_000, entries("b"));
+        assert!(cues.take_due(secs(2.999)).is_empty());
+        assert_eq!(cues.take_due(secs(3.0)), entries("b"));
+    }
+}
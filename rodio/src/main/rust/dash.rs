@@ -0,0 +1,1454 @@
+//! MPEG-DASH audio: the MPD manifest and the segment reader that feeds the
+//! decoder with one audio Representation's fragmented MP4.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use roxmltree::{Document, Node};
+
+use crate::error::RodioError;
+use crate::http::{request_range, request_stream, HttpContext};
+use crate::prefetch::SegmentPrefetcher;
+use crate::reconnect::{retry, sleep_unless_cancelled};
+use crate::section::{seek_in_place, SectionedStream};
+use crate::wallclock::parse_date_time;
+
+/// Codecs the decoder handles inside fragmented MP4.
+const SUPPORTED_CODECS: [&str; 2] = ["mp4a", "flac"];
+/// How often a dynamic manifest without `minimumUpdatePeriod` is checked for new segments.
+const DEFAULT_UPDATE_PERIOD: Duration = Duration::from_secs(2);
+/// Consecutive segments that may fail and be skipped before the stream gives up.
+const MAX_SKIPPED_SEGMENTS: u32 = 3;
+/// Segments listed for one period at most; a broken manifest can declare
+/// endless repeats. A day of one-second segments fits.
+const MAX_PERIOD_SEGMENTS: usize = 100_000;
+/// Digits of the widest `u64`; wider template padding is cut to this.
+const MAX_TEMPLATE_WIDTH: usize = 20;
+
+type SegmentReader = Box<dyn Read + Send + Sync>;
+
+/// A whole resource, or a byte range of one.
+#[derive(Clone, PartialEq, Eq)]
+struct DashSource {
+    url: reqwest::Url,
+    range: Option<Range<u64>>,
+}
+
+#[derive(Clone)]
+struct DashSegment {
+    source: DashSource,
+    number: u64,
+    duration: Duration,
+}
+
+/// A `$Number$` template of a dynamic manifest without a timeline: segments
+/// exist once wall-clock time has passed their end.
+#[derive(Clone)]
+struct LiveTemplate {
+    base: reqwest::Url,
+    media: String,
+    representation_id: String,
+    bandwidth: u64,
+    start_number: u64,
+    timescale: u64,
+    segment_ticks: u64,
+    presentation_offset: u64,
+    /// Epoch ms at which the period's first segment starts.
+    period_start_ms: u64,
+}
+
+impl LiveTemplate {
+    fn duration(&self) -> Duration {
+        ticks_to_duration(self.segment_ticks, self.timescale)
+    }
+
+    /// Epoch ms at which segment `number` has been fully produced.
+    fn available_at(&self, number: u64) -> u64 {
+        let segments = number.saturating_sub(self.start_number).saturating_add(1);
+        let elapsed = segments
+            .saturating_mul(self.segment_ticks)
+            .saturating_mul(1000)
+            / self.timescale;
+        self.period_start_ms.saturating_add(elapsed)
+    }
+
+    /// The newest segment available at `now_ms`.
+    fn latest(&self, now_ms: u64) -> Option<u64> {
+        let elapsed = now_ms.checked_sub(self.period_start_ms)?;
+        let produced = elapsed.saturating_mul(self.timescale) / 1000 / self.segment_ticks.max(1);
+        produced
+            .checked_sub(1)
+            .map(|offset| self.start_number.saturating_add(offset))
+    }
+
+    fn segment(&self, number: u64) -> Result<DashSegment, RodioError> {
+        let time = number
+            .saturating_sub(self.start_number)
+            .saturating_mul(self.segment_ticks)
+            .saturating_add(self.presentation_offset);
+        let path = fill_template(
+            &self.media,
+            &self.representation_id,
+            self.bandwidth,
+            number,
+            time,
+        );
+        Ok(DashSegment {
+            source: DashSource {
+                url: join_url(&self.base, &path)?,
+                range: None,
+            },
+            number,
+            duration: self.duration(),
+        })
+    }
+}
+
+struct DashPeriod {
+    id: String,
+    init: Option<DashSource>,
+    segments: Vec<DashSegment>,
+    live: Option<LiveTemplate>,
+}
+
+impl DashPeriod {
+    fn first_number(&self) -> Option<u64> {
+        match &self.live {
+            Some(live) => Some(live.start_number),
+            None => self.segments.first().map(|segment| segment.number),
+        }
+    }
+
+    fn last_number(&self, now_ms: u64) -> Option<u64> {
+        match &self.live {
+            Some(live) => live.latest(now_ms),
+            None => self.segments.last().map(|segment| segment.number),
+        }
+    }
+
+    fn segment(&self, number: u64, now_ms: u64) -> Result<Option<DashSegment>, RodioError> {
+        match &self.live {
+            Some(live) if live.latest(now_ms).is_some_and(|latest| number <= latest) => {
+                live.segment(number).map(Some)
+            }
+            Some(_) => Ok(None),
+            None => Ok(self
+                .segments
+                .iter()
+                .find(|segment| segment.number == number)
+                .cloned()),
+        }
+    }
+}
+
+struct DashManifest {
+    dynamic: bool,
+    duration: Option<Duration>,
+    update_period: Option<Duration>,
+    presentation_delay: Option<Duration>,
+    periods: Vec<DashPeriod>,
+    /// `id` of the Representation played, kept across reloads.
+    representation_id: String,
+}
+
+/// The next segment to read and the initialization section it needs.
+struct NextSegment {
+    segment: DashSegment,
+    init: Option<DashSource>,
+    /// First segment of a new period, which gets a decoder of its own.
+    boundary: bool,
+}
+
+pub struct DashStreamReader {
+    mpd_url: reqwest::Url,
+    http: HttpContext,
+    manifest: DashManifest,
+    loaded_at: Instant,
+    /// Period and segment number of the next segment to read.
+    period: String,
+    next_number: u64,
+    period_changed: bool,
+    /// Initialization section last written to the byte stream.
+    current_init: Option<DashSource>,
+    current_segment: Option<SegmentReader>,
+    prefetcher: SegmentPrefetcher,
+    /// First segment of a period, held back until the next decoder reads.
+    pending_segment: Option<NextSegment>,
+    section_started: bool,
+    at_boundary: bool,
+    failed_segments: u32,
+    ended: bool,
+    pos: u64,
+}
+
+impl DashStreamReader {
+    /// Loads the manifest at `url` and starts at the beginning of a static
+    /// presentation, or `live_edge_segments` behind the live edge of a dynamic
+    /// one, downloading `prefetch_segments` ahead of the one being read.
+    /// Returns the reader and the presentation's duration when it is static.
+    pub fn new(
+        url: &str,
+        http: HttpContext,
+        live_edge_segments: usize,
+        prefetch_segments: usize,
+    ) -> Result<(Self, Option<Duration>), RodioError> {
+        let mpd_url =
+            reqwest::Url::parse(url).map_err(|_| RodioError::InvalidUrl(url.to_string()))?;
+        let (manifest, mpd_url) = load_manifest(&mpd_url, &http, None)?;
+        let now = now_ms();
+        let start = if manifest.dynamic {
+            manifest.periods.last()
+        } else {
+            manifest.periods.first()
+        };
+        let Some(start) = start else {
+            return Err(RodioError::Playlist(
+                "dash manifest has no playable period".to_string(),
+            ));
+        };
+        let period = start.id.clone();
+        let next_number = if manifest.dynamic {
+            live_start_number(start, manifest.presentation_delay, live_edge_segments, now)
+        } else {
+            start.first_number().unwrap_or(0)
+        };
+        let duration = if manifest.dynamic {
+            None
+        } else {
+            manifest.duration.or_else(|| {
+                Some(
+                    manifest
+                        .periods
+                        .iter()
+                        .flat_map(|period| &period.segments)
+                        .fold(Duration::ZERO, |total, segment| {
+                            total.saturating_add(segment.duration)
+                        }),
+                )
+            })
+        };
+        let reader = Self {
+            mpd_url,
+            http,
+            manifest,
+            loaded_at: Instant::now(),
+            period,
+            next_number,
+            period_changed: false,
+            current_init: None,
+            current_segment: None,
+            prefetcher: SegmentPrefetcher::new(prefetch_segments),
+            pending_segment: None,
+            section_started: false,
+            at_boundary: false,
+            failed_segments: 0,
+            ended: false,
+            pos: 0,
+        };
+        Ok((reader, duration))
+    }
+
+    /// URL whose extension tells the decoder the container: the first
+    /// period's initialization section, or its first segment.
+    pub fn hint_url(&self) -> Option<String> {
+        let period = self.manifest.periods.first()?;
+        let source = match (&period.init, period.segments.first()) {
+            (Some(init), _) => &init.url,
+            (None, Some(segment)) => &segment.source.url,
+            (None, None) => return Some(period.live.as_ref()?.base.to_string()),
+        };
+        Some(source.to_string())
+    }
+
+    fn next_segment(&mut self) -> Result<Option<NextSegment>, RodioError> {
+        loop {
+            if self.ended {
+                return Ok(None);
+            }
+            let now = now_ms();
+            let Some(index) = self
+                .manifest
+                .periods
+                .iter()
+                .position(|period| period.id == self.period)
+            else {
+                // The period left the manifest; continue with the oldest one listed.
+                let Some(first) = self.manifest.periods.first() else {
+                    if !self.manifest.dynamic {
+                        return Ok(None);
+                    }
+                    self.wait_for_update()?;
+                    continue;
+                };
+                self.period = first.id.clone();
+                self.next_number = first.first_number().unwrap_or(0);
+                self.period_changed = true;
+                continue;
+            };
+            let period = &self.manifest.periods[index];
+            if let Some(segment) = period.segment(self.next_number, now)? {
+                self.next_number += 1;
+                return Ok(Some(NextSegment {
+                    segment,
+                    init: period.init.clone(),
+                    boundary: std::mem::take(&mut self.period_changed),
+                }));
+            }
+            let first = period.first_number();
+            if first.is_some_and(|first| self.next_number < first) {
+                // Fell out of the time-shift window.
+                self.next_number = first.unwrap_or(self.next_number);
+                continue;
+            }
+            if let Some(next) = self.manifest.periods.get(index + 1) {
+                self.period = next.id.clone();
+                self.next_number = next.first_number().unwrap_or(0);
+                self.period_changed = true;
+                continue;
+            }
+            if !self.manifest.dynamic {
+                return Ok(None);
+            }
+            match &period.live {
+                // Template segments appear on their own schedule; the manifest
+                // is only reloaded for new periods.
+                Some(live) if self.loaded_at.elapsed() < self.update_period() => {
+                    let wait = live.available_at(self.next_number).saturating_sub(now);
+                    let wait = Duration::from_millis(wait).min(self.update_period());
+                    if !sleep_unless_cancelled(wait, &self.http.cancelled) {
+                        return Ok(None);
+                    }
+                }
+                _ => {
+                    if !self.wait_for_update()? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_period(&self) -> Duration {
+        self.manifest.update_period.unwrap_or(DEFAULT_UPDATE_PERIOD)
+    }
+
+    /// Reloads a dynamic manifest once its update period has passed, keeping
+    /// the Representation being played. Returns `false` if playback was stopped
+    /// while waiting.
+    fn wait_for_update(&mut self) -> Result<bool, RodioError> {
+        let wait = self
+            .update_period()
+            .saturating_sub(self.loaded_at.elapsed());
+        if !sleep_unless_cancelled(wait, &self.http.cancelled) {
+            return Ok(false);
+        }
+        let representation = self.manifest.representation_id.clone();
+        let (manifest, mpd_url) = retry(&self.http.reconnect, &self.http.cancelled, || {
+            load_manifest(&self.mpd_url, &self.http, Some(&representation))
+        })?;
+        self.manifest = manifest;
+        self.mpd_url = mpd_url;
+        self.loaded_at = Instant::now();
+        Ok(true)
+    }
+
+    /// Starts downloading the listed segments after the one being fetched.
+    /// Byte-range segments are fetched when they are read.
+    fn prefetch_upcoming(&mut self) {
+        let Some(period) = self
+            .manifest
+            .periods
+            .iter()
+            .find(|period| period.id == self.period)
+        else {
+            return;
+        };
+        let urls: Vec<reqwest::Url> = period
+            .segments
+            .iter()
+            .filter(|segment| segment.number >= self.next_number)
+            .take(self.prefetcher.depth())
+            .filter(|segment| segment.source.range.is_none())
+            .map(|segment| segment.source.url.clone())
+            .collect();
+        for url in urls {
+            self.prefetcher.start(url, &self.http);
+        }
+    }
+
+    /// Opens `next`, preceded by its initialization section when that differs
+    /// from the one already written or a new period restarts the decoder.
+    fn open_segment(&mut self, next: NextSegment) -> Result<SegmentReader, RodioError> {
+        let source = next.segment.source;
+        let mut prefetched = source
+            .range
+            .is_none()
+            .then(|| self.prefetcher.take(&source.url))
+            .flatten();
+        self.prefetch_upcoming();
+        let policy = self.http.reconnect;
+        let segment = retry(&policy, &self.http.cancelled, || match prefetched.take() {
+            Some(fetched) => Ok(Box::new(io::Cursor::new(fetched?.bytes)) as SegmentReader),
+            None => open_source(&source, &self.http),
+        })?;
+        let init = match &next.init {
+            Some(init) if next.boundary || self.current_init.as_ref() != Some(init) => {
+                Some(retry(&policy, &self.http.cancelled, || {
+                    open_source(init, &self.http)
+                })?)
+            }
+            _ => None,
+        };
+        self.current_init = next.init;
+        Ok(match init {
+            Some(init) => Box::new(init.chain(segment)),
+            None => segment,
+        })
+    }
+
+    /// Records a segment that could not be fetched or read, so the stream can
+    /// continue with the next one; too many in a row, or a server failing its
+    /// certificate pin, end the stream.
+    fn skip_failed_segment(&mut self, error: RodioError) -> io::Result<()> {
+        self.failed_segments += 1;
+        if self.failed_segments > MAX_SKIPPED_SEGMENTS
+            || matches!(error, RodioError::CertificatePinMismatch(_))
+        {
+            self.ended = true;
+            return Err(io::Error::other(error));
+        }
+        Ok(())
+    }
+}
+
+impl Read for DashStreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.at_boundary {
+                return Ok(0);
+            }
+            if let Some(segment) = &mut self.current_segment {
+                match segment.read(buf) {
+                    Ok(0) => self.current_segment = None,
+                    Ok(read) => {
+                        self.pos = self.pos.saturating_add(read as u64);
+                        return Ok(read);
+                    }
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    // The rest of a segment that breaks off mid-stream is dropped.
+                    Err(error) => {
+                        self.current_segment = None;
+                        self.skip_failed_segment(error.into())?;
+                    }
+                }
+                continue;
+            }
+
+            if self.ended {
+                return Ok(0);
+            }
+
+            let next = match self.pending_segment.take() {
+                Some(next) => Some(next),
+                None => self.next_segment().map_err(io::Error::other)?,
+            };
+            let Some(next) = next else {
+                self.ended = true;
+                return Ok(0);
+            };
+            // The decoder of this period ends here; the next one starts fresh.
+            if next.boundary && self.section_started {
+                self.pending_segment = Some(next);
+                self.section_started = false;
+                self.at_boundary = true;
+                return Ok(0);
+            }
+            match self.open_segment(next) {
+                Ok(reader) => {
+                    self.failed_segments = 0;
+                    self.section_started = true;
+                    self.current_segment = Some(reader);
+                }
+                Err(error) => self.skip_failed_segment(error)?,
+            }
+        }
+    }
+}
+
+impl Seek for DashStreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_in_place(self.pos, pos)
+    }
+}
+
+/// Sections end at each period boundary.
+impl SectionedStream for DashStreamReader {
+    fn take_boundary(&mut self) -> bool {
+        std::mem::take(&mut self.at_boundary)
+    }
+
+    fn restart_section(&mut self) -> bool {
+        self.current_segment = None;
+        self.current_init = None;
+        self.section_started = false;
+        !self.ended
+    }
+}
+
+fn open_source(source: &DashSource, http: &HttpContext) -> Result<SegmentReader, RodioError> {
+    match &source.range {
+        Some(range) => {
+            let response = request_range(
+                source.url.as_str(),
+                range.start,
+                Some(range.end.saturating_sub(1)),
+                http,
+            )?;
+            Ok(Box::new(response.take(range.end - range.start)))
+        }
+        None => Ok(Box::new(request_stream(source.url.as_str(), false, http)?)),
+    }
+}
+
+/// Where a dynamic presentation starts: `presentation_delay` behind the live
+/// edge when the manifest suggests one, otherwise `live_edge_segments` before it.
+fn live_start_number(
+    period: &DashPeriod,
+    presentation_delay: Option<Duration>,
+    live_edge_segments: usize,
+    now: u64,
+) -> u64 {
+    let first = period.first_number().unwrap_or(0);
+    let Some(last) = period.last_number(now) else {
+        return first;
+    };
+    let segment_duration = match &period.live {
+        Some(live) => live.duration(),
+        None => period
+            .segments
+            .last()
+            .map_or(Duration::ZERO, |segment| segment.duration),
+    };
+    let behind = match presentation_delay {
+        Some(delay) if !segment_duration.is_zero() => delay
+            .as_millis()
+            .div_ceil(segment_duration.as_millis().max(1))
+            as u64,
+        _ => live_edge_segments as u64,
+    };
+    (last + 1).saturating_sub(behind.max(1)).max(first)
+}
+
+/// Fetches and parses the manifest, returning it with the URL it was served
+/// from, which relative `BaseURL`s resolve against.
+fn load_manifest(
+    url: &reqwest::Url,
+    http: &HttpContext,
+    representation: Option<&str>,
+) -> Result<(DashManifest, reqwest::Url), RodioError> {
+    let response = request_stream(url.as_str(), false, http)?;
+    let final_url = response.url().clone();
+    let body = response.text()?;
+    let manifest = parse_manifest(&body, &final_url, http, representation)?;
+    Ok((manifest, final_url))
+}
+
+fn parse_manifest(
+    body: &str,
+    mpd_url: &reqwest::Url,
+    http: &HttpContext,
+    representation: Option<&str>,
+) -> Result<DashManifest, RodioError> {
+    let document = Document::parse(body)
+        .map_err(|err| RodioError::Playlist(format!("dash manifest parse failed: {err}")))?;
+    let mpd = document.root_element();
+    if mpd.tag_name().name() != "MPD" {
+        return Err(RodioError::Playlist(
+            "dash manifest has no MPD element".to_string(),
+        ));
+    }
+    let dynamic = mpd.attribute("type") == Some("dynamic");
+    let duration = mpd
+        .attribute("mediaPresentationDuration")
+        .and_then(parse_duration);
+    let availability_start = mpd
+        .attribute("availabilityStartTime")
+        .and_then(parse_date_time)
+        .unwrap_or(0);
+    let now = now_ms();
+    let base = base_url(mpd_url, mpd);
+
+    let period_nodes: Vec<Node> = children(mpd, "Period").collect();
+    let mut periods = Vec::new();
+    let mut representation_id = representation.map(str::to_string);
+    let mut period_start = Duration::ZERO;
+    for (index, node) in period_nodes.iter().enumerate() {
+        let start = node
+            .attribute("start")
+            .and_then(parse_duration)
+            .unwrap_or(period_start);
+        let end = match node.attribute("duration").and_then(parse_duration) {
+            Some(duration) => Some(start.checked_add(duration).ok_or_else(|| {
+                RodioError::Playlist("dash period ends past the supported time range".to_string())
+            })?),
+            None => period_nodes
+                .get(index + 1)
+                .and_then(|next| next.attribute("start"))
+                .and_then(parse_duration),
+        }
+        .or(if dynamic { None } else { duration });
+        period_start = end.unwrap_or(start);
+
+        let start_ms = availability_start.saturating_add(start.as_millis() as u64);
+        if dynamic && start_ms > now {
+            break;
+        }
+        let Some((set, rep)) = select_representation(*node, representation_id.as_deref()) else {
+            continue;
+        };
+        let id = rep.attribute("id").unwrap_or_default().to_string();
+        let timing = PeriodTiming {
+            start_ms,
+            duration: end.map(|end| end.saturating_sub(start)),
+            dynamic,
+            now,
+        };
+        let levels = [*node, set, rep];
+        let base = levels
+            .iter()
+            .fold(base.clone(), |base, level| base_url(&base, *level));
+        let mut period = period_segments(&levels, &base, &id, &timing, http)?;
+        period.id = node
+            .attribute("id")
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("period-{}", start.as_millis()));
+        representation_id = Some(id);
+        periods.push(period);
+    }
+    if periods.is_empty() {
+        return Err(RodioError::Playlist(
+            "dash manifest has no supported audio representation".to_string(),
+        ));
+    }
+    Ok(DashManifest {
+        dynamic,
+        duration,
+        update_period: mpd
+            .attribute("minimumUpdatePeriod")
+            .and_then(parse_duration),
+        presentation_delay: mpd
+            .attribute("suggestedPresentationDelay")
+            .and_then(parse_duration),
+        periods,
+        representation_id: representation_id.unwrap_or_default(),
+    })
+}
+
+struct PeriodTiming {
+    /// Epoch ms at which the period starts; only meaningful for dynamic manifests.
+    start_ms: u64,
+    duration: Option<Duration>,
+    dynamic: bool,
+    now: u64,
+}
+
+/// Picks an audio AdaptationSet, preferring the `main` role, and in it the
+/// Representation `preferred` if it is still there, otherwise the highest
+/// bandwidth one the decoder supports.
+fn select_representation<'a, 'input>(
+    period: Node<'a, 'input>,
+    preferred: Option<&str>,
+) -> Option<(Node<'a, 'input>, Node<'a, 'input>)> {
+    let mut sets: Vec<(Node, Vec<Node>)> = children(period, "AdaptationSet")
+        .filter(|set| is_audio(*set))
+        .map(|set| {
+            let reps = children(set, "Representation")
+                .filter(|rep| is_supported(set, *rep))
+                .collect();
+            (set, reps)
+        })
+        .filter(|(_, reps): &(Node, Vec<Node>)| !reps.is_empty())
+        .collect();
+    if let Some(preferred) = preferred {
+        for (set, reps) in &sets {
+            if let Some(rep) = reps
+                .iter()
+                .find(|rep| rep.attribute("id") == Some(preferred))
+            {
+                return Some((*set, *rep));
+            }
+        }
+    }
+    sets.sort_by_key(|(set, _)| {
+        !children(*set, "Role").any(|role| role.attribute("value") == Some("main"))
+    });
+    let (set, reps) = sets.into_iter().next()?;
+    let rep = reps.into_iter().max_by_key(|rep| {
+        rep.attribute("bandwidth")
+            .and_then(|b| b.parse::<u64>().ok())
+    })?;
+    Some((set, rep))
+}
+
+fn is_audio(set: Node) -> bool {
+    set.attribute("contentType") == Some("audio")
+        || set
+            .attribute("mimeType")
+            .is_some_and(|mime| mime.starts_with("audio/"))
+        || children(set, "Representation").any(|rep| {
+            rep.attribute("mimeType")
+                .is_some_and(|mime| mime.starts_with("audio/"))
+        })
+        || children(set, "ContentComponent")
+            .any(|component| component.attribute("contentType") == Some("audio"))
+}
+
+/// Unprotected fragmented MP4 with a codec the decoder handles; a missing
+/// `codecs` is given the benefit of the doubt.
+fn is_supported(set: Node, rep: Node) -> bool {
+    if child(set, "ContentProtection").is_some() || child(rep, "ContentProtection").is_some() {
+        return false;
+    }
+    let mime = rep.attribute("mimeType").or(set.attribute("mimeType"));
+    if mime.is_some_and(|mime| !mime.contains("mp4")) {
+        return false;
+    }
+    match rep.attribute("codecs").or(set.attribute("codecs")) {
+        Some(codecs) => {
+            let codecs = codecs.to_ascii_lowercase();
+            SUPPORTED_CODECS
+                .iter()
+                .any(|supported| codecs.starts_with(supported))
+        }
+        None => true,
+    }
+}
+
+/// Segments of the Representation at the end of `levels` (Period, AdaptationSet,
+/// Representation), whose segment information is inherited downwards.
+fn period_segments(
+    levels: &[Node],
+    base: &reqwest::Url,
+    representation_id: &str,
+    timing: &PeriodTiming,
+    http: &HttpContext,
+) -> Result<DashPeriod, RodioError> {
+    let rep = levels[levels.len() - 1];
+    let bandwidth = rep
+        .attribute("bandwidth")
+        .and_then(|b| b.parse().ok())
+        .unwrap_or(0);
+    let templates = inherited(levels, "SegmentTemplate");
+    if !templates.is_empty() {
+        return template_segments(&templates, base, representation_id, bandwidth, timing);
+    }
+    let lists = inherited(levels, "SegmentList");
+    if !lists.is_empty() {
+        return list_segments(&lists, base, timing);
+    }
+    let bases = inherited(levels, "SegmentBase");
+    if let Some(index_range) = attribute(&bases, "indexRange").and_then(parse_range) {
+        let init = bases
+            .iter()
+            .find_map(|node| child(*node, "Initialization"))
+            .and_then(|init| init.attribute("range"))
+            .and_then(parse_range)
+            .map(|range| DashSource {
+                url: base.clone(),
+                range: Some(range),
+            });
+        let mut index = Vec::new();
+        let length = index_range.end - index_range.start;
+        request_range(
+            base.as_str(),
+            index_range.start,
+            Some(index_range.end - 1),
+            http,
+        )?
+        .take(length)
+        .read_to_end(&mut index)?;
+        let segments = parse_sidx(&index, index_range.start)?
+            .into_iter()
+            .zip(1..)
+            .map(|((range, duration), number)| DashSegment {
+                source: DashSource {
+                    url: base.clone(),
+                    range: Some(range),
+                },
+                number,
+                duration,
+            })
+            .collect();
+        return Ok(DashPeriod {
+            id: String::new(),
+            init,
+            segments,
+            live: None,
+        });
+    }
+    // A single self-initializing resource.
+    Ok(DashPeriod {
+        id: String::new(),
+        init: None,
+        segments: vec![DashSegment {
+            source: DashSource {
+                url: base.clone(),
+                range: None,
+            },
+            number: 1,
+            duration: timing.duration.unwrap_or_default(),
+        }],
+        live: None,
+    })
+}
+
+fn template_segments(
+    templates: &[Node],
+    base: &reqwest::Url,
+    representation_id: &str,
+    bandwidth: u64,
+    timing: &PeriodTiming,
+) -> Result<DashPeriod, RodioError> {
+    let media = attribute(templates, "media").ok_or_else(|| {
+        RodioError::Playlist("dash SegmentTemplate has no media attribute".to_string())
+    })?;
+    let start_number = number_attribute(templates, "startNumber").unwrap_or(1);
+    let timescale = number_attribute(templates, "timescale").unwrap_or(1).max(1);
+    let presentation_offset = number_attribute(templates, "presentationTimeOffset").unwrap_or(0);
+    let init = match attribute(templates, "initialization") {
+        Some(init) => Some(DashSource {
+            url: join_url(
+                base,
+                &fill_template(init, representation_id, bandwidth, start_number, 0),
+            )?,
+            range: None,
+        }),
+        None => None,
+    };
+    let period_ticks = timing.duration.map(|duration| {
+        ((duration.as_secs_f64() * timescale as f64) as u64).saturating_add(presentation_offset)
+    });
+
+    let mut segments = Vec::new();
+    let mut live = None;
+    if let Some(timeline) = templates
+        .iter()
+        .find_map(|template| child(*template, "SegmentTimeline"))
+    {
+        // Open-ended repeats run to the end of the period, or of what has been
+        // produced so far in a dynamic one.
+        let end_ticks = match period_ticks {
+            Some(ticks) => ticks,
+            None => {
+                let elapsed = timing.now.saturating_sub(timing.start_ms);
+                (elapsed.saturating_mul(timescale) / 1000).saturating_add(presentation_offset)
+            }
+        };
+        let entries: Vec<Node> = children(timeline, "S").collect();
+        let mut time: u64 = 0;
+        let mut number = start_number;
+        for (index, entry) in entries.iter().enumerate() {
+            if let Some(start) = entry.attribute("t").and_then(|t| t.parse().ok()) {
+                time = start;
+            }
+            let Some(duration) = entry
+                .attribute("d")
+                .and_then(|d| d.parse::<u64>().ok())
+                .filter(|d| *d > 0)
+            else {
+                continue;
+            };
+            let repeat: i64 = entry
+                .attribute("r")
+                .and_then(|r| r.parse().ok())
+                .unwrap_or(0);
+            let until = entries
+                .get(index + 1)
+                .and_then(|next| next.attribute("t"))
+                .and_then(|t| t.parse::<u64>().ok())
+                .unwrap_or(end_ticks);
+            let mut remaining = repeat;
+            while segments.len() < MAX_PERIOD_SEGMENTS {
+                let Some(end) = time.checked_add(duration) else {
+                    break;
+                };
+                if repeat < 0 && end > until {
+                    break;
+                }
+                let path = fill_template(media, representation_id, bandwidth, number, time);
+                segments.push(DashSegment {
+                    source: DashSource {
+                        url: join_url(base, &path)?,
+                        range: None,
+                    },
+                    number,
+                    duration: ticks_to_duration(duration, timescale),
+                });
+                time = end;
+                number = number.saturating_add(1);
+                if repeat >= 0 {
+                    if remaining == 0 {
+                        break;
+                    }
+                    remaining -= 1;
+                }
+            }
+        }
+    } else {
+        let segment_ticks = number_attribute(templates, "duration")
+            .filter(|duration| *duration > 0)
+            .ok_or_else(|| {
+                RodioError::Playlist(
+                    "dash SegmentTemplate has neither a duration nor a timeline".to_string(),
+                )
+            })?;
+        let template = LiveTemplate {
+            base: base.clone(),
+            media: media.to_string(),
+            representation_id: representation_id.to_string(),
+            bandwidth,
+            start_number,
+            timescale,
+            segment_ticks,
+            presentation_offset,
+            period_start_ms: timing.start_ms,
+        };
+        match period_ticks.filter(|_| !timing.dynamic) {
+            Some(period_ticks) => {
+                let count = (period_ticks - presentation_offset).div_ceil(segment_ticks);
+                let count = count.min(MAX_PERIOD_SEGMENTS as u64);
+                for index in 0..count {
+                    segments.push(template.segment(start_number.saturating_add(index))?);
+                }
+            }
+            None => live = Some(template),
+        }
+    }
+    Ok(DashPeriod {
+        id: String::new(),
+        init,
+        segments,
+        live,
+    })
+}
+
+fn list_segments(
+    lists: &[Node],
+    base: &reqwest::Url,
+    timing: &PeriodTiming,
+) -> Result<DashPeriod, RodioError> {
+    let timescale = number_attribute(lists, "timescale").unwrap_or(1).max(1);
+    let start_number = number_attribute(lists, "startNumber").unwrap_or(1);
+    let init = match lists.iter().find_map(|list| child(*list, "Initialization")) {
+        Some(init) => Some(DashSource {
+            url: match init.attribute("sourceURL") {
+                Some(path) => join_url(base, path)?,
+                None => base.clone(),
+            },
+            range: init.attribute("range").and_then(parse_range),
+        }),
+        None => None,
+    };
+    let urls: Vec<Node> = lists
+        .iter()
+        .map(|list| children(*list, "SegmentURL").collect::<Vec<_>>())
+        .find(|urls| !urls.is_empty())
+        .unwrap_or_default();
+    let duration = match number_attribute(lists, "duration") {
+        Some(ticks) => ticks_to_duration(ticks, timescale),
+        None => timing
+            .duration
+            .map(|duration| duration / urls.len().max(1) as u32)
+            .unwrap_or_default(),
+    };
+    let mut segments = Vec::with_capacity(urls.len());
+    for (index, node) in urls.iter().enumerate() {
+        let number = start_number.saturating_add(index as u64);
+        segments.push(DashSegment {
+            source: DashSource {
+                url: match node.attribute("media") {
+                    Some(path) => join_url(base, path)?,
+                    None => base.clone(),
+                },
+                range: node.attribute("mediaRange").and_then(parse_range),
+            },
+            number,
+            duration,
+        });
+    }
+    Ok(DashPeriod {
+        id: String::new(),
+        init,
+        segments,
+        live: None,
+    })
+}
+
+/// Subsegment byte ranges and durations from the `sidx` box in `index`, which
+/// was read from byte `offset` of the resource.
+fn parse_sidx(index: &[u8], offset: u64) -> Result<Vec<(Range<u64>, Duration)>, RodioError> {
+    let invalid = || RodioError::Playlist("dash segment index is invalid".to_string());
+    let mut position = 0;
+    while position + 8 <= index.len() {
+        let size = be_u32(index, position).ok_or_else(invalid)? as usize;
+        if size < 8 {
+            return Err(invalid());
+        }
+        if &index[position + 4..position + 8] != b"sidx" {
+            position += size;
+            continue;
+        }
+        let sidx = index.get(position..position + size).ok_or_else(invalid)?;
+        // Offsets count from the first byte after the box.
+        let anchor = offset
+            .checked_add((position + size) as u64)
+            .ok_or_else(invalid)?;
+        let version = *sidx.get(8).ok_or_else(invalid)?;
+        let timescale = u64::from(be_u32(sidx, 16).ok_or_else(invalid)?).max(1);
+        let (first_offset, mut cursor) = if version == 0 {
+            (u64::from(be_u32(sidx, 24).ok_or_else(invalid)?), 28)
+        } else {
+            let high = u64::from(be_u32(sidx, 28).ok_or_else(invalid)?);
+            let low = u64::from(be_u32(sidx, 32).ok_or_else(invalid)?);
+            ((high << 32) | low, 36)
+        };
+        let count = be_u32(sidx, cursor).ok_or_else(invalid)? & 0xffff;
+        cursor += 4;
+        let mut start = anchor.checked_add(first_offset).ok_or_else(invalid)?;
+        let mut references = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let reference = be_u32(sidx, cursor).ok_or_else(invalid)?;
+            let duration = be_u32(sidx, cursor + 4).ok_or_else(invalid)?;
+            if reference >> 31 == 1 {
+                return Err(RodioError::Playlist(
+                    "dash hierarchical segment indexes are not supported".to_string(),
+                ));
+            }
+            let size = u64::from(reference & 0x7fff_ffff);
+            let end = start.checked_add(size).ok_or_else(invalid)?;
+            references.push((
+                start..end,
+                ticks_to_duration(u64::from(duration), timescale),
+            ));
+            start = end;
+            cursor += 12;
+        }
+        return Ok(references);
+    }
+    Err(RodioError::Playlist(
+        "dash index range has no sidx box".to_string(),
+    ))
+}
+
+fn be_u32(bytes: &[u8], position: usize) -> Option<u32> {
+    let bytes = bytes.get(position..position + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Expands `$RepresentationID$`, `$Number$`, `$Bandwidth$` and `$Time$`, with
+/// optional `%0<width>d` padding, and `$$`.
+fn fill_template(template: &str, id: &str, bandwidth: u64, number: u64, time: u64) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            filled.push_str(&rest[start..]);
+            return filled;
+        };
+        let token = &after[..end];
+        let (name, format) = token.split_once('%').unwrap_or((token, ""));
+        let width = format
+            .strip_suffix('d')
+            .and_then(|width| width.parse::<usize>().ok())
+            .unwrap_or(0)
+            .min(MAX_TEMPLATE_WIDTH);
+        match name {
+            "" => filled.push('$'),
+            "RepresentationID" => filled.push_str(id),
+            "Number" => filled.push_str(&format!("{number:0width$}")),
+            "Bandwidth" => filled.push_str(&format!("{bandwidth:0width$}")),
+            "Time" => filled.push_str(&format!("{time:0width$}")),
+            _ => {
+                filled.push('$');
+                filled.push_str(token);
+                filled.push('$');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// An `xs:duration` such as `PT1H2M3.5S`. Years and months count as 365 and 30 days.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim().strip_prefix('P')?;
+    let (date, time) = value.split_once('T').unwrap_or((value, ""));
+    let units: [(&str, &[(char, f64)]); 2] = [
+        (
+            date,
+            &[
+                ('Y', 31_536_000.0),
+                ('M', 2_592_000.0),
+                ('W', 604_800.0),
+                ('D', 86_400.0),
+            ],
+        ),
+        (time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)]),
+    ];
+    let mut seconds = 0.0;
+    for (part, designators) in units {
+        let mut number = String::new();
+        for ch in part.chars() {
+            if ch.is_ascii_digit() || ch == '.' {
+                number.push(ch);
+                continue;
+            }
+            let (_, scale) = designators.iter().find(|(unit, _)| *unit == ch)?;
+            seconds += number.parse::<f64>().ok()? * scale;
+            number.clear();
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// `first-last`, inclusive, as a half-open range.
+fn parse_range(value: &str) -> Option<Range<u64>> {
+    let (first, last) = value.trim().split_once('-')?;
+    let first: u64 = first.parse().ok()?;
+    let last: u64 = last.parse().ok()?;
+    (last >= first).then_some(first..last.checked_add(1)?)
+}
+
+fn ticks_to_duration(ticks: u64, timescale: u64) -> Duration {
+    Duration::try_from_secs_f64(ticks as f64 / timescale.max(1) as f64).unwrap_or(Duration::MAX)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// `name` elements of `levels`, innermost first, so attribute lookups let a
+/// Representation override its AdaptationSet and Period.
+fn inherited<'a, 'input: 'a>(levels: &[Node<'a, 'input>], name: &'a str) -> Vec<Node<'a, 'input>> {
+    levels
+        .iter()
+        .rev()
+        .filter_map(|level| child(*level, name))
+        .collect()
+}
+
+fn attribute<'a>(elements: &[Node<'a, '_>], name: &str) -> Option<&'a str> {
+    elements.iter().find_map(|element| element.attribute(name))
+}
+
+fn number_attribute(elements: &[Node], name: &str) -> Option<u64> {
+    attribute(elements, name).and_then(|value| value.trim().parse().ok())
+}
+
+fn base_url(base: &reqwest::Url, node: Node) -> reqwest::Url {
+    child(node, "BaseURL")
+        .and_then(|element| element.text())
+        .and_then(|path| base.join(path.trim()).ok())
+        .unwrap_or_else(|| base.clone())
+}
+
+fn join_url(base: &reqwest::Url, path: &str) -> Result<reqwest::Url, RodioError> {
+    base.join(path)
+        .map_err(|_| RodioError::InvalidUrl(path.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(body: &str) -> Result<DashManifest, RodioError> {
+        let url = reqwest::Url::parse("https://example.com/live/stream.mpd").unwrap();
+        parse_manifest(body, &url, &HttpContext::default(), None)
+    }
+
+    fn paths(period: &DashPeriod) -> Vec<&str> {
+        period
+            .segments
+            .iter()
+            .map(|segment| segment.source.url.path())
+            .collect()
+    }
+
+    /// A `sidx` box of `version` referencing subsegments of `(size, ticks)`.
+    fn sidx(version: u8, first_offset: u64, references: &[(u32, u32)]) -> Vec<u8> {
+        let mut payload = vec![version, 0, 0, 0];
+        payload.extend_from_slice(&1u32.to_be_bytes());
+        payload.extend_from_slice(&1000u32.to_be_bytes());
+        if version == 0 {
+            payload.extend_from_slice(&0u32.to_be_bytes());
+            payload.extend_from_slice(&(first_offset as u32).to_be_bytes());
+        } else {
+            payload.extend_from_slice(&0u64.to_be_bytes());
+            payload.extend_from_slice(&first_offset.to_be_bytes());
+        }
+        payload.extend_from_slice(&[0, 0]);
+        payload.extend_from_slice(&(references.len() as u16).to_be_bytes());
+        for (size, ticks) in references {
+            payload.extend_from_slice(&size.to_be_bytes());
+            payload.extend_from_slice(&ticks.to_be_bytes());
+            payload.extend_from_slice(&0x9000_0000u32.to_be_bytes());
+        }
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(b"sidx");
+        data.extend(payload);
+        data
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(
+            parse_duration("PT1H2M3.5S"),
+            Some(Duration::from_secs_f64(3723.5))
+        );
+        assert_eq!(
+            parse_duration(" P1DT12H "),
+            Some(Duration::from_secs(129_600))
+        );
+        assert_eq!(parse_duration("P1W"), Some(Duration::from_secs(604_800)));
+        assert_eq!(parse_duration("PT0S"), Some(Duration::ZERO));
+        assert_eq!(parse_duration("P"), Some(Duration::ZERO));
+        for value in [
+            "", "1H", "PT1X", "PT1.2.3S", "PT5", "P-1D", "PTS", "P1e400Y",
+        ] {
+            assert_eq!(parse_duration(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn fills_templates() {
+        let template = "$RepresentationID$/$Bandwidth$/seg-$Number%05d$-$Time$.m4s?$$";
+        assert_eq!(
+            fill_template(template, "audio", 128_000, 42, 90_000),
+            "audio/128000/seg-00042-90000.m4s?$"
+        );
+        assert_eq!(
+            fill_template("$Unknown$-$Number$", "a", 0, 7, 0),
+            "$Unknown$-7"
+        );
+        assert_eq!(fill_template("seg-$Number", "a", 0, 7, 0), "seg-$Number");
+        assert_eq!(fill_template("$Number%x$", "a", 0, 7, 0), "7");
+        assert_eq!(
+            fill_template("$Number%0999999999999d$", "a", 0, 7, 0).len(),
+            MAX_TEMPLATE_WIDTH
+        );
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(parse_range("0-99"), Some(0..100));
+        assert_eq!(parse_range(" 5-5 "), Some(5..6));
+        assert_eq!(parse_range("9-5"), None);
+        assert_eq!(parse_range("5"), None);
+        assert_eq!(parse_range("-5"), None);
+        assert_eq!(parse_range("0-18446744073709551615"), None);
+    }
+
+    #[test]
+    fn parses_segment_indexes() {
+        let mut index = vec![0, 0, 0, 8];
+        index.extend_from_slice(b"styp");
+        let box_ = sidx(0, 10, &[(100, 2000), (50, 500)]);
+        let box_end = 1000 + (index.len() + box_.len()) as u64;
+        index.extend(box_);
+        assert_eq!(
+            parse_sidx(&index, 1000).unwrap(),
+            vec![
+                (box_end + 10..box_end + 110, Duration::from_secs(2)),
+                (box_end + 110..box_end + 160, Duration::from_millis(500)),
+            ]
+        );
+        let box_ = sidx(1, 0, &[(7, 1000)]);
+        let len = box_.len() as u64;
+        assert_eq!(
+            parse_sidx(&box_, 0).unwrap(),
+            vec![(len..len + 7, Duration::from_secs(1))]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_segment_indexes() {
+        let valid = sidx(0, 0, &[(100, 2000), (50, 500)]);
+        for len in 0..valid.len() {
+            assert!(parse_sidx(&valid[..len], 0).is_err());
+        }
+        let mut hierarchical = valid.clone();
+        hierarchical[32] |= 0x80;
+        assert!(parse_sidx(&hierarchical, 0).is_err());
+        assert!(parse_sidx(&[0, 0, 0, 4, b's', b'i', b'd', b'x'], 0).is_err());
+        assert!(parse_sidx(&sidx(1, u64::MAX, &[(1, 1)]), 0).is_err());
+        assert!(parse_sidx(&valid, u64::MAX - 10).is_err());
+    }
+
+    #[test]
+    fn reads_number_templates_of_static_manifests() {
+        let manifest = manifest(
+            r#"<MPD type="static" mediaPresentationDuration="PT5S"><Period>
+            <AdaptationSet contentType="video"><Representation id="v" mimeType="video/mp4" bandwidth="900000"/></AdaptationSet>
+            <AdaptationSet mimeType="audio/mp4">
+              <SegmentTemplate initialization="init-$RepresentationID$.mp4" media="$RepresentationID$-$Number%03d$.m4s" duration="2000" timescale="1000"/>
+              <Representation id="a" codecs="mp4a.40.2" bandwidth="64000"/>
+              <Representation id="b" codecs="mp4a.40.2" bandwidth="128000"/>
+              <Representation id="c" codecs="ec-3" bandwidth="384000"/>
+            </AdaptationSet></Period></MPD>"#,
+        )
+        .unwrap();
+        assert!(!manifest.dynamic);
+        assert_eq!(manifest.duration, Some(Duration::from_secs(5)));
+        assert_eq!(manifest.representation_id, "b");
+        let period = &manifest.periods[0];
+        assert_eq!(period.init.as_ref().unwrap().url.path(), "/live/init-b.mp4");
+        assert_eq!(
+            paths(period),
+            ["/live/b-001.m4s", "/live/b-002.m4s", "/live/b-003.m4s"]
+        );
+    }
+
+    #[test]
+    fn reads_timelines_and_periods() {
+        let period = |id: &str| {
+            format!(
+                r#"<Period id="{id}" duration="PT6S"><BaseURL>{id}/</BaseURL>
+                <AdaptationSet mimeType="audio/mp4" codecs="mp4a.40.2"><Representation id="r" bandwidth="1">
+                <SegmentTemplate timescale="10" media="t$Time$.m4s"><SegmentTimeline>
+                <S t="0" d="20"/><S d="10" r="-1"/></SegmentTimeline></SegmentTemplate>
+                </Representation></AdaptationSet></Period>"#
+            )
+        };
+        let manifest = manifest(&format!(
+            r#"<MPD type="static" mediaPresentationDuration="PT12S">{}{}</MPD>"#,
+            period("p1"),
+            period("p2")
+        ))
+        .unwrap();
+        assert_eq!(manifest.periods.len(), 2);
+        assert_eq!(manifest.periods[1].id, "p2");
+        let period = &manifest.periods[1];
+        assert_eq!(period.segments.len(), 5);
+        assert_eq!(paths(period)[..2], ["/live/p2/t0.m4s", "/live/p2/t20.m4s"]);
+        assert_eq!(period.segments[0].duration, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn reads_segment_lists() {
+        let manifest = manifest(
+            r#"<MPD type="static" mediaPresentationDuration="PT4S"><Period><AdaptationSet mimeType="audio/mp4">
+            <Representation id="l" bandwidth="1"><BaseURL>list.mp4</BaseURL>
+            <SegmentList duration="2" timescale="1"><Initialization range="0-3"/>
+            <SegmentURL mediaRange="4-7"/><SegmentURL mediaRange="8-13"/></SegmentList>
+            </Representation></AdaptationSet></Period></MPD>"#,
+        )
+        .unwrap();
+        let period = &manifest.periods[0];
+        assert_eq!(period.init.as_ref().unwrap().range, Some(0..4));
+        let ranges: Vec<_> = period
+            .segments
+            .iter()
+            .map(|segment| segment.source.range.clone())
+            .collect();
+        assert_eq!(ranges, [Some(4..8), Some(8..14)]);
+    }
+
+    #[test]
+    fn follows_the_live_edge_of_dynamic_templates() {
+        let manifest = manifest(
+            r#"<MPD type="dynamic" availabilityStartTime="2024-01-01T00:00:00Z" minimumUpdatePeriod="PT10S">
+            <Period id="0" start="PT0S"><AdaptationSet mimeType="audio/mp4" codecs="mp4a.40.2">
+            <Representation id="x" bandwidth="1"><SegmentTemplate media="n$Number$.m4s" duration="2" startNumber="1"/>
+            </Representation></AdaptationSet></Period></MPD>"#,
+        )
+        .unwrap();
+        assert!(manifest.dynamic);
+        assert_eq!(manifest.update_period, Some(Duration::from_secs(10)));
+        let period = &manifest.periods[0];
+        let live = period.live.as_ref().unwrap();
+        let start = live.period_start_ms;
+        assert_eq!(live.latest(start + 1999), None);
+        assert_eq!(live.latest(start + 2000), Some(1));
+        assert_eq!(live.available_at(3), start + 6000);
+        let now = start + 60_000;
+        assert_eq!(live_start_number(period, None, 3, now), 28);
+        let delay = Some(Duration::from_secs(9));
+        assert_eq!(live_start_number(period, delay, 3, now), 26);
+        assert!(period.segment(31, now).unwrap().is_none());
+        let segment = period.segment(30, now).unwrap().unwrap();
+        assert_eq!(segment.source.url.path(), "/live/n30.m4s");
+    }
+
+    #[test]
+    fn rejects_manifests_without_playable_audio() {
+        assert!(manifest("").is_err());
+        assert!(manifest("<MPD").is_err());
+        assert!(manifest("<Playlist/>").is_err());
+        assert!(manifest(
+            r#"<MPD><Period><AdaptationSet mimeType="audio/mp4"><ContentProtection/>
+            <Representation id="a"/></AdaptationSet></Period></MPD>"#
+        )
+        .is_err());
+        assert!(manifest(
+            r#"<MPD><Period><AdaptationSet mimeType="audio/mp4"><Representation id="a">
+            <SegmentTemplate media="$Number$"/></Representation></AdaptationSet></Period></MPD>"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn malformed_manifests_do_not_panic() {
+        let set = |template: &str| {
+            format!(
+                r#"<MPD type="static" mediaPresentationDuration="P9999Y"><Period>
+                <AdaptationSet mimeType="audio/mp4"><Representation id="a">{template}
+                </Representation></AdaptationSet></Period></MPD>"#
+            )
+        };
+        for template in [
+            r#"<SegmentTemplate media="$Number$" duration="1" timescale="18446744073709551615"/>"#,
+            r#"<SegmentTemplate media="$Number$" duration="1" presentationTimeOffset="18446744073709551615"/>"#,
+            r#"<SegmentTemplate media="$Number$" duration="1" startNumber="18446744073709551615"/>"#,
+            r#"<SegmentTemplate media="$Time$"><SegmentTimeline><S d="1" r="-1"/></SegmentTimeline></SegmentTemplate>"#,
+            r#"<SegmentTemplate media="$Time$"><SegmentTimeline><S t="18446744073709551615" d="9" r="3"/></SegmentTimeline></SegmentTemplate>"#,
+            r#"<SegmentTemplate media="$Time$"><SegmentTimeline><S d="1" r="9223372036854775807"/></SegmentTimeline></SegmentTemplate>"#,
+            r#"<SegmentList duration="1"><SegmentURL media="a"/></SegmentList>"#,
+            r#"<SegmentList duration="18446744073709551615"><SegmentURL media="a"/><SegmentURL media="b"/></SegmentList>"#,
+            r#"<SegmentTemplate media="$Time$"><SegmentTimeline><S d="18446744073709551615"/></SegmentTimeline></SegmentTemplate>"#,
+            r#"<SegmentList startNumber="18446744073709551615"><SegmentURL/><SegmentURL/></SegmentList>"#,
+            r#"<SegmentBase indexRange="x"/>"#,
+        ] {
+            if let Ok(manifest) = manifest(&set(template)) {
+                assert!(manifest.periods[0].segments.len() <= MAX_PERIOD_SEGMENTS);
+            }
+        }
+        assert!(manifest(
+            r#"<MPD><Period start="P500000000000Y" duration="P500000000000Y">
+            <AdaptationSet mimeType="audio/mp4"><Representation id="a">
+            <SegmentList duration="1"><SegmentURL media="a"/></SegmentList>
+            </Representation></AdaptationSet></Period></MPD>"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! AES-128 decryption for encrypted HLS segments.
+
+use std::io::{self, Read};
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecryptMut, KeyIvInit};
+
+use crate::error::RodioError;
+use crate::id3;
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+pub const AES_BLOCK_LEN: usize = 16;
+const READ_CHUNK: usize = 16 * 1024;
+const ADTS_HEADER_LEN: usize = 7;
+
+/// Decrypts a whole-segment AES-128-CBC stream (`METHOD=AES-128`) while it downloads.
+pub struct Aes128CbcReader<R> {
+    inner: R,
+    cipher: Aes128CbcDec,
+    encrypted: Vec<u8>,
+    plain: Vec<u8>,
+    plain_pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> Aes128CbcReader<R> {
+    pub fn new(inner: R, key: &[u8; AES_BLOCK_LEN], iv: &[u8; AES_BLOCK_LEN]) -> Self {
+        Self {
+            inner,
+            cipher: Aes128CbcDec::new(key.into(), iv.into()),
+            encrypted: Vec::new(),
+            plain: Vec::new(),
+            plain_pos: 0,
+            eof: false,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        while self.plain_pos == self.plain.len() && !self.eof {
+            let start = self.encrypted.len();
+            self.encrypted.resize(start + READ_CHUNK, 0);
+            let read = match self.inner.read(&mut self.encrypted[start..]) {
+                Ok(read) => read,
+                Err(err) => {
+                    self.encrypted.truncate(start);
+                    return Err(err);
+                }
+            };
+            self.encrypted.truncate(start + read);
+            let decrypt_len = if read == 0 {
+                self.eof = true;
+                if !self.encrypted.len().is_multiple_of(AES_BLOCK_LEN) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "encrypted hls segment is not a whole number of blocks",
+                    ));
+                }
+                self.encrypted.len()
+            } else {
+                // Hold back the last block until EOF: it carries the padding.
+                self.encrypted.len().saturating_sub(1) / AES_BLOCK_LEN * AES_BLOCK_LEN
+            };
+            let mut plain: Vec<u8> = self.encrypted.drain(..decrypt_len).collect();
+            for block in plain.chunks_exact_mut(AES_BLOCK_LEN) {
+                self.cipher
+                    .decrypt_block_mut(GenericArray::from_mut_slice(block));
+            }
+            if self.eof {
+                strip_pkcs7_padding(&mut plain);
+            }
+            self.plain = plain;
+            self.plain_pos = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Aes128CbcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill()?;
+        let available = &self.plain[self.plain_pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.plain_pos += len;
+        Ok(len)
+    }
+}
+
+/// Some packagers pad incorrectly; leave the data untouched rather than failing the segment.
+fn strip_pkcs7_padding(data: &mut Vec<u8>) {
+    let Some(&pad) = data.last() else {
+        return;
+    };
+    let pad = usize::from(pad);
+    if (1..=AES_BLOCK_LEN).contains(&pad)
+        && pad <= data.len()
+        && data[data.len() - pad..]
+            .iter()
+            .all(|&b| usize::from(b) == pad)
+    {
+        data.truncate(data.len() - pad);
+    }
+}
+
+/// Decrypts a `METHOD=SAMPLE-AES` packed audio segment in place: optional ID3
+/// tags followed by ADTS frames. Only AAC (ADTS) payloads are supported.
+pub fn decrypt_sample_aes_adts(
+    data: &mut [u8],
+    key: &[u8; AES_BLOCK_LEN],
+    iv: &[u8; AES_BLOCK_LEN],
+) -> Result<(), RodioError> {
+    let mut offset = 0;
+    while let Some(len) = id3::tag_len(&data[offset..]) {
+        offset += len;
+    }
+    while offset + ADTS_HEADER_LEN <= data.len() {
+        let header = &data[offset..offset + ADTS_HEADER_LEN];
+        if header[0] != 0xFF || header[1] & 0xF6 != 0xF0 {
+            return Err(RodioError::Decoder(
+                "SAMPLE-AES is only supported for packed AAC (ADTS) segments".to_string(),
+            ));
+        }
+        let header_len = if header[1] & 0x01 == 1 { 7 } else { 9 };
+        let frame_len = (usize::from(header[3] & 0x03) << 11)
+            | (usize::from(header[4]) << 3)
+            | (usize::from(header[5]) >> 5);
+        if frame_len < header_len || offset + frame_len > data.len() {
+            // A truncated trailing frame is left for the decoder to drop.
+            break;
+        }
+        decrypt_sample_aes_frame(&mut data[offset + header_len..offset + frame_len], key, iv);
+        offset += frame_len;
+    }
+    Ok(())
+}
+
+/// SAMPLE-AES keeps a 16-byte clear leader and a clear tail shorter than one block;
+/// the blocks in between are CBC-encrypted with the chain restarting at every frame.
+pub fn decrypt_sample_aes_frame(
+    payload: &mut [u8],
+    key: &[u8; AES_BLOCK_LEN],
+    iv: &[u8; AES_BLOCK_LEN],
+) {
+    if payload.len() <= AES_BLOCK_LEN {
+        return;
+    }
+    let encrypted = &mut payload[AES_BLOCK_LEN..];
+    let len = encrypted.len() / AES_BLOCK_LEN * AES_BLOCK_LEN;
+    let mut cipher = Aes128CbcDec::new(key.into(), iv.into());
+    for block in encrypted[..len].chunks_exact_mut(AES_BLOCK_LEN) {
+        cipher.decrypt_block_mut(GenericArray::from_mut_slice(block));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::BlockEncryptMut;
+
+    use super::*;
+
+    type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+
+    const KEY: [u8; AES_BLOCK_LEN] = *b"0123456789abcdef";
+    const IV: [u8; AES_BLOCK_LEN] = *b"fedcba9876543210";
+
+    /// CBC-encrypts `data`, which must be a whole number of blocks.
+    fn encrypt(data: &mut [u8]) {
+        let mut cipher = Aes128CbcEnc::new(&KEY.into(), &IV.into());
+        for block in data.chunks_exact_mut(AES_BLOCK_LEN) {
+            cipher.encrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+    }
+
+    fn pkcs7_encrypted(plain: &[u8]) -> Vec<u8> {
+        let pad = AES_BLOCK_LEN - plain.len() % AES_BLOCK_LEN;
+        let mut data = plain.to_vec();
+        data.resize(plain.len() + pad, pad as u8);
+        encrypt(&mut data);
+        data
+    }
+
+    /// Returns at most `chunk` bytes per read.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn decrypt_all(encrypted: &[u8], inner_chunk: usize, buf_len: usize) -> io::Result<Vec<u8>> {
+        let inner = Trickle {
+            data: encrypted,
+            chunk: inner_chunk,
+        };
+        let mut reader = Aes128CbcReader::new(inner, &KEY, &IV);
+        let mut out = Vec::new();
+        let mut buf = vec![0u8; buf_len];
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                return Ok(out);
+            }
+            out.extend_from_slice(&buf[..read]);
+        }
+    }
+
+    #[test]
+    fn decrypts_and_strips_padding_across_reads() {
+        let plain: Vec<u8> = (0..100u8).collect();
+        for len in [0, 1, 15, 16, 17, 32, 100] {
+            let encrypted = pkcs7_encrypted(&plain[..len]);
+            for (inner_chunk, buf_len) in [(1, 1), (7, 5), (16, 33), (17, 16), (READ_CHUNK, 1)] {
+                assert_eq!(
+                    decrypt_all(&encrypted, inner_chunk, buf_len).unwrap(),
+                    &plain[..len],
+                    "length {len}, reads of {inner_chunk} into {buf_len}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_data_with_bad_padding() {
+        let mut plain = [7u8; 32];
+        for last in [0, 5, 17] {
+            plain[31] = last;
+            let mut encrypted = plain;
+            encrypt(&mut encrypted);
+            assert_eq!(decrypt_all(&encrypted, 3, 1).unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn rejects_partial_blocks() {
+        let mut encrypted = pkcs7_encrypted(b"segment");
+        encrypted.pop();
+        let err = decrypt_all(&encrypted, 4, 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// A SAMPLE-AES payload: clear leader, encrypted whole blocks, clear tail.
+    fn sample_aes_payload(plain: &[u8]) -> Vec<u8> {
+        let mut payload = plain.to_vec();
+        if payload.len() > AES_BLOCK_LEN {
+            let len = (payload.len() - AES_BLOCK_LEN) / AES_BLOCK_LEN * AES_BLOCK_LEN;
+            encrypt(&mut payload[AES_BLOCK_LEN..AES_BLOCK_LEN + len]);
+        }
+        payload
+    }
+
+    #[test]
+    fn sample_aes_frames_keep_the_leader_and_tail_clear() {
+        let plain: Vec<u8> = (0..=255u8).collect();
+        for len in [0, 16, 17, 31, 32, 53, 256] {
+            let mut payload = sample_aes_payload(&plain[..len]);
+            if len >= 2 * AES_BLOCK_LEN {
+                assert_eq!(payload[..AES_BLOCK_LEN], plain[..AES_BLOCK_LEN]);
+                assert_ne!(payload[..len], plain[..len]);
+            } else {
+                assert_eq!(payload, &plain[..len]);
+            }
+            decrypt_sample_aes_frame(&mut payload, &KEY, &IV);
+            assert_eq!(payload, &plain[..len], "length {len}");
+        }
+    }
+
+    fn adts_frame(payload: &[u8]) -> Vec<u8> {
+        let len = ADTS_HEADER_LEN + payload.len();
+        let mut frame = vec![
+            0xFF,
+            0xF1,
+            0x50,
+            0x80 | (len >> 11) as u8,
+            (len >> 3) as u8,
+            ((len & 0x07) << 5) as u8 | 0x1F,
+            0xFC,
+        ];
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn decrypts_packed_adts_frame_by_frame() {
+        let first: Vec<u8> = (0..70u8).collect();
+        let second: Vec<u8> = (100..140u8).collect();
+        let mut plain = b"ID3\x04\0\0\0\0\0\x02ab".to_vec();
+        plain.extend(adts_frame(&first));
+        plain.extend(adts_frame(&second));
+        let mut data = b"ID3\x04\0\0\0\0\0\x02ab".to_vec();
+        data.extend(adts_frame(&sample_aes_payload(&first)));
+        data.extend(adts_frame(&sample_aes_payload(&second)));
+        // A truncated trailing frame stays as it is.
+        data.extend_from_slice(&adts_frame(&first)[..20]);
+        plain.extend_from_slice(&adts_frame(&first)[..20]);
+
+        decrypt_sample_aes_adts(&mut data, &KEY, &IV).unwrap();
+        assert_eq!(data, plain);
+
+        let mut not_adts = vec![0x47; 188];
+        assert!(decrypt_sample_aes_adts(&mut not_adts, &KEY, &IV).is_err());
+    }
+}
@@ -2,6 +2,8 @@
 
 use std::error::Error as _;
 
+use crate::tls::pin_mismatch_host;
+
 /// Errors that can occur when working with Rodio.
 #[derive(Debug, thiserror::Error, uniffi::Error)]
 pub enum RodioError {
@@ -38,6 +40,24 @@ pub enum RodioError {
     #[error("invalid url: {0}")]
     InvalidUrl(String),
 
+    #[error("invalid header: {0}")]
+    InvalidHeader(String),
+
+    #[error("tls error: {0}")]
+    Tls(String),
+
+    #[error("certificate pin mismatch for {0}")]
+    CertificatePinMismatch(String),
+
+    #[error("network profile {0} not found")]
+    NetworkProfileNotFound(String),
+
+    #[error("hls variant {0} not found")]
+    HlsVariantNotFound(u32),
+
+    #[error("hls rendition {0} not found")]
+    HlsRenditionNotFound(String),
+
     #[error("playlist error: {0}")]
     Playlist(String),
 
@@ -50,7 +70,15 @@ pub enum RodioError {
 
 impl From<std::io::Error> for RodioError {
     fn from(error: std::io::Error) -> Self {
-        RodioError::Io(error.to_string())
+        // Stream readers pass their own errors up through `io::Error`.
+        let message = error.to_string();
+        match error
+            .into_inner()
+            .map(|inner| inner.downcast::<RodioError>())
+        {
+            Some(Ok(error)) => *error,
+            _ => RodioError::Io(message),
+        }
     }
 }
 
@@ -74,6 +102,9 @@ impl From<rodio::StreamError> for RodioError {
 
 impl From<reqwest::Error> for RodioError {
     fn from(error: reqwest::Error) -> Self {
+        if let Some(host) = pin_mismatch_host(&error) {
+            return RodioError::CertificatePinMismatch(host);
+        }
         RodioError::Http(format_reqwest_error(&error))
     }
 }
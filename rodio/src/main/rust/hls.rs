@@ -0,0 +1,1654 @@
+//! HLS playlist handling and the segment reader that feeds the decoder.
+
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hls_m3u8::tags::{ExtXMap, VariantStream};
+use hls_m3u8::types::{ByteRange, DecryptionKey, EncryptionMethod, KeyFormat, MediaType};
+use hls_m3u8::{Decryptable, MasterPlaylist, MediaPlaylist, MediaSegment};
+
+use crate::abr::{HlsSession, HlsVariant, ThroughputEstimator};
+use crate::cue::{MetadataCues, SegmentCues};
+use crate::decrypt::{decrypt_sample_aes_adts, Aes128CbcReader, AES_BLOCK_LEN};
+use crate::error::RodioError;
+use crate::http::{download_bytes, request_range, request_stream, HttpContext};
+use crate::id3;
+use crate::llhls::{parse_low_latency, LowLatency, PartialSegment};
+use crate::prefetch::{PlaylistRefresher, SegmentPrefetcher};
+use crate::reconnect::retry;
+use crate::section::{seek_in_place, SectionedStream};
+use crate::ts::{is_ts, TsDemuxer, PROBE_LEN};
+use crate::wallclock::{parse_date_time, ProgramDates};
+
+/// Segments kept between the live edge and the starting point of a live stream.
+pub const DEFAULT_LIVE_EDGE_SEGMENTS: u32 = 3;
+/// Segments downloaded in the background ahead of the one being read.
+pub const DEFAULT_PREFETCH_SEGMENTS: u32 = 2;
+
+/// Live streams rotate keys; forget old ones instead of growing without bound.
+const MAX_CACHED_KEYS: usize = 16;
+/// Consecutive segments that may fail and be skipped before the stream gives up.
+const MAX_SKIPPED_SEGMENTS: u32 = 3;
+/// Size of an ID3v2 tag header.
+const ID3_PROBE_LEN: usize = 10;
+
+type SegmentReader = Box<dyn Read + Send + Sync>;
+
+/// A whole resource, or an `EXT-X-BYTERANGE` sub-range of one.
+#[derive(Clone, PartialEq, Eq)]
+struct SegmentSource {
+    url: reqwest::Url,
+    range: Option<Range<u64>>,
+}
+
+#[derive(Clone)]
+struct HlsSegment {
+    source: SegmentSource,
+    encryption: Option<SegmentEncryption>,
+    /// `EXT-X-MAP` section to emit before this segment.
+    init: Option<InitSection>,
+    /// Stream time at which the segment's audio starts.
+    start: Duration,
+    /// Preceded by `EXT-X-DISCONTINUITY`: timestamps, and possibly the codec, change here.
+    discontinuity: bool,
+}
+
+#[derive(Clone)]
+struct InitSection {
+    source: SegmentSource,
+    encryption: Option<SegmentEncryption>,
+}
+
+/// An open ranged response that consecutive sub-ranges of the same resource
+/// keep reading from instead of issuing a request per segment.
+struct RangeConnection {
+    url: reqwest::Url,
+    /// Offset of the next byte the response will yield.
+    offset: u64,
+    response: reqwest::blocking::Response,
+}
+
+type SharedRangeConnection = Arc<Mutex<Option<RangeConnection>>>;
+
+struct ByteRangeReader {
+    connection: SharedRangeConnection,
+    http: HttpContext,
+    url: reqwest::Url,
+    offset: u64,
+    end: u64,
+}
+
+impl ByteRangeReader {
+    fn open_connection(&self) -> io::Result<RangeConnection> {
+        let response = request_range(self.url.as_str(), self.offset, None, &self.http)
+            .map_err(io::Error::other)?;
+        Ok(RangeConnection {
+            url: self.url.clone(),
+            offset: self.offset,
+            response,
+        })
+    }
+}
+
+impl Read for ByteRangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset >= self.end || buf.is_empty() {
+            return Ok(0);
+        }
+        let mut guard = self
+            .connection
+            .lock()
+            .map_err(|_| io::Error::other("hls range connection lock poisoned"))?;
+        let reusable = guard
+            .as_ref()
+            .is_some_and(|connection| connection.url == self.url && connection.offset == self.offset);
+        let mut reopened = !reusable;
+        if reopened {
+            *guard = Some(self.open_connection()?);
+        }
+        let len = (self.end - self.offset).min(buf.len() as u64) as usize;
+        loop {
+            let connection = guard
+                .as_mut()
+                .ok_or_else(|| io::Error::other("hls range connection missing"))?;
+            let read = connection.response.read(&mut buf[..len])?;
+            if read > 0 {
+                connection.offset += read as u64;
+                self.offset += read as u64;
+                return Ok(read);
+            }
+            // A reused response may stop short of a resource that is still growing.
+            if reopened {
+                *guard = None;
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "hls byte range ended early",
+                ));
+            }
+            *guard = Some(self.open_connection()?);
+            reopened = true;
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SegmentEncryption {
+    method: EncryptionMethod,
+    key_url: reqwest::Url,
+    iv: [u8; AES_BLOCK_LEN],
+}
+
+/// Position of live HLS playback within the playlist's sliding window.
+#[derive(Clone, Copy, Debug, uniffi::Record)]
+pub struct HlsLiveStatus {
+    /// Length of the window that can be time-shifted into.
+    pub window_ms: u64,
+    /// Estimated distance behind the live edge.
+    pub latency_ms: u64,
+    /// Whether low-latency partial segments (`EXT-X-PART`) are read. Stays `false`
+    /// for encrypted low-latency playlists, which play whole segments instead.
+    pub partial_segments: bool,
+}
+
+/// An `EXT-X-MEDIA` audio rendition of an HLS master playlist, in playlist order.
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct HlsRendition {
+    pub group_id: String,
+    pub name: String,
+    pub language: Option<String>,
+    pub is_default: bool,
+    pub autoselect: bool,
+    /// Media playlist of the rendition; `None` when the audio is part of the variant streams.
+    pub url: Option<String>,
+}
+
+/// Audio renditions of a master playlist and the one chosen by the user.
+pub struct HlsRenditions {
+    renditions: Vec<HlsRendition>,
+    urls: Vec<Option<reqwest::Url>>,
+    /// `AUDIO` group of each variant, by variant index.
+    variant_groups: Vec<Option<String>>,
+    /// Group and name of the user's choice; renditions of other groups with
+    /// the same name, or else the same language, are used when the variant changes.
+    selected: Mutex<Option<(String, String)>>,
+}
+
+impl HlsRenditions {
+    fn none() -> Self {
+        Self {
+            renditions: Vec::new(),
+            urls: Vec::new(),
+            variant_groups: Vec::new(),
+            selected: Mutex::new(None),
+        }
+    }
+
+    pub fn renditions(&self) -> Vec<HlsRendition> {
+        self.renditions.clone()
+    }
+
+    pub fn select(&self, group_id: &str, name: &str) -> Result<(), RodioError> {
+        if !self
+            .renditions
+            .iter()
+            .any(|rendition| rendition.group_id == group_id && rendition.name == name)
+        {
+            return Err(RodioError::HlsRenditionNotFound(format!(
+                "{name} in group {group_id}"
+            )));
+        }
+        *self.selected.lock().unwrap_or_else(|err| err.into_inner()) =
+            Some((group_id.to_string(), name.to_string()));
+        Ok(())
+    }
+
+    /// Rendition played with `variant`: the user's choice or the group's
+    /// rendition matching it, else the group's default, else its first
+    /// autoselect rendition, else its first one.
+    pub fn current(&self, variant: usize) -> Option<HlsRendition> {
+        self.current_index(variant)
+            .map(|index| self.renditions[index].clone())
+    }
+
+    fn current_index(&self, variant: usize) -> Option<usize> {
+        let group = self.variant_groups.get(variant)?.as_deref()?;
+        let in_group = || {
+            self.renditions
+                .iter()
+                .enumerate()
+                .filter(move |(_, rendition)| rendition.group_id == group)
+        };
+        let selected = self.selected.lock().unwrap_or_else(|err| err.into_inner());
+        let chosen = selected.as_ref().and_then(|(selected_group, name)| {
+            in_group()
+                .find(|(_, rendition)| {
+                    rendition.group_id == *selected_group && rendition.name == *name
+                })
+                .or_else(|| in_group().find(|(_, rendition)| rendition.name == *name))
+                .or_else(|| {
+                    let language = self
+                        .renditions
+                        .iter()
+                        .find(|rendition| {
+                            rendition.group_id == *selected_group && rendition.name == *name
+                        })?
+                        .language
+                        .as_deref()?;
+                    in_group().find(|(_, rendition)| {
+                        rendition
+                            .language
+                            .as_deref()
+                            .is_some_and(|other| other.eq_ignore_ascii_case(language))
+                    })
+                })
+        });
+        chosen
+            .or_else(|| in_group().find(|(_, rendition)| rendition.is_default))
+            .or_else(|| in_group().find(|(_, rendition)| rendition.autoselect))
+            .or_else(|| in_group().next())
+            .map(|(index, _)| index)
+    }
+
+    /// Media playlist of the rendition played with `variant`, if it has its own.
+    fn url(&self, variant: usize) -> Option<reqwest::Url> {
+        self.urls.get(self.current_index(variant)?)?.clone()
+    }
+}
+
+/// What a player keeps of the HLS stream it is playing.
+#[derive(Clone)]
+pub struct HlsPlayback {
+    pub session: Arc<HlsSession>,
+    pub seeker: Arc<HlsSeeker>,
+    pub renditions: Arc<HlsRenditions>,
+    /// Timed ID3 metadata of the segments read so far.
+    pub cues: Arc<MetadataCues>,
+    /// `EXT-X-PROGRAM-DATE-TIME` of the segments read so far.
+    pub dates: Arc<ProgramDates>,
+}
+
+/// Segment durations of the latest media playlist and how far the reader has got.
+struct HlsTimeline {
+    media_sequence: usize,
+    durations: Vec<Duration>,
+    /// Sequence number of the next segment the reader will fetch.
+    next_sequence: usize,
+    low_latency: LowLatency,
+}
+
+/// Restarts a stream at another segment: anywhere in a VOD playlist
+/// (`EXT-X-ENDLIST`), or within the sliding window of a live one.
+/// Variants and renditions are expected to share segment timing.
+pub struct HlsSeeker {
+    http: HttpContext,
+    session: Arc<HlsSession>,
+    renditions: Arc<HlsRenditions>,
+    variant_urls: Vec<reqwest::Url>,
+    playlist_url: reqwest::Url,
+    hint_url: Option<String>,
+    live: bool,
+    prefetch_segments: usize,
+    timeline: Mutex<HlsTimeline>,
+}
+
+impl HlsSeeker {
+    pub fn is_live(&self) -> bool {
+        self.live
+    }
+
+    pub fn hint_url(&self) -> Option<&str> {
+        self.hint_url.as_deref()
+    }
+
+    /// Whether the playlist's partial segments are read; see [`LowLatency::has_parts`].
+    pub fn reads_parts(&self) -> bool {
+        self.timeline().low_latency.has_parts()
+    }
+
+    /// Length of a VOD stream, or of the current live window.
+    pub fn total_duration(&self) -> Duration {
+        self.timeline().durations.iter().sum()
+    }
+
+    /// Playlist time the reader has not fetched yet; with the decoded audio still
+    /// buffered this is how far playback trails the live edge.
+    pub fn unfetched_duration(&self) -> Duration {
+        let timeline = self.timeline();
+        let fetched = timeline
+            .next_sequence
+            .saturating_sub(timeline.media_sequence);
+        timeline.durations.iter().skip(fetched).sum()
+    }
+
+    /// Returns a reader starting with the segment that contains `position`,
+    /// measured from the start of the playlist, and the time at which that segment starts.
+    pub fn reader_at(&self, position: Duration) -> (HlsStreamReader, Duration) {
+        let (sequence, segment_start) = {
+            let timeline = self.timeline();
+            let last = timeline.durations.len().saturating_sub(1);
+            let mut start = Duration::ZERO;
+            let mut index = 0;
+            while index < last && start + timeline.durations[index] <= position {
+                start += timeline.durations[index];
+                index += 1;
+            }
+            (timeline.media_sequence + index, start)
+        };
+        (self.reader_from(sequence, 0), segment_start)
+    }
+
+    /// Returns a reader starting `behind` the live edge, clamped to the window,
+    /// and how much of its first segment to skip.
+    pub fn reader_behind_live(&self, behind: Duration) -> (HlsStreamReader, Duration) {
+        let (sequence, skip) = {
+            let timeline = self.timeline();
+            let mut remaining = Duration::ZERO;
+            let mut index = timeline.durations.len();
+            while index > 0 && remaining < behind {
+                index -= 1;
+                remaining += timeline.durations[index];
+            }
+            (
+                timeline.media_sequence + index,
+                remaining.saturating_sub(behind),
+            )
+        };
+        (self.reader_from(sequence, 0), skip)
+    }
+
+    /// Returns a reader where live playback starts: `target_latency` behind the
+    /// edge of a low-latency playlist, otherwise `edge_segments` segments before it.
+    pub fn reader_at_live_edge(
+        &self,
+        edge_segments: usize,
+        target_latency: Option<Duration>,
+    ) -> HlsStreamReader {
+        if let Some((sequence, part)) = self.low_latency_start(target_latency) {
+            return self.reader_from(sequence, part);
+        }
+        let sequence = {
+            let timeline = self.timeline();
+            timeline.media_sequence
+                + timeline
+                    .durations
+                    .len()
+                    .saturating_sub(edge_segments)
+        };
+        self.reader_from(sequence, 0)
+    }
+
+    /// Segment and part at which playback is `target_latency` behind the live
+    /// edge, never closer than the server's `PART-HOLD-BACK`. Starts at an
+    /// independent part when the playlist marks any.
+    fn low_latency_start(&self, target_latency: Option<Duration>) -> Option<(usize, usize)> {
+        let timeline = self.timeline();
+        let low_latency = &timeline.low_latency;
+        if !low_latency.has_parts() {
+            return None;
+        }
+        let hold_back = low_latency.part_hold_back().unwrap_or_default();
+        let target = target_latency.map_or(hold_back, |target| target.max(hold_back));
+        // Audio playlists often leave INDEPENDENT out, as every part is.
+        let marks_independent = low_latency.parts.iter().any(|part| part.independent);
+
+        let unit = |part: &PartialSegment| {
+            let startable = part.independent || !marks_independent;
+            (part.sequence, part.index, part.duration, startable)
+        };
+
+        // Whole segments where their parts are gone, parts after that, oldest first.
+        let mut units = Vec::new();
+        for (offset, duration) in timeline.durations.iter().enumerate() {
+            let sequence = timeline.media_sequence + offset;
+            let before = units.len();
+            units.extend(low_latency.parts_of(sequence).filter(|part| !part.gap).map(unit));
+            if units.len() == before {
+                units.push((sequence, 0, *duration, true));
+            }
+        }
+        let pending = low_latency.parts_of(low_latency.next_sequence);
+        units.extend(pending.filter(|part| !part.gap).map(unit));
+
+        let mut behind = Duration::ZERO;
+        let mut start = units.first().map(|unit| (unit.0, unit.1));
+        for (sequence, part, duration, independent) in units.iter().rev() {
+            behind += *duration;
+            if behind >= target && *independent {
+                start = Some((*sequence, *part));
+                break;
+            }
+        }
+        start
+    }
+
+    /// Each reader gets its own copy, so a reader being torn down cannot
+    /// overwrite the position of its replacement. `part` is the partial
+    /// segment of `sequence` to start at; 0 starts at the segment itself.
+    fn reader_from(&self, sequence: usize, part: usize) -> HlsStreamReader {
+        let timeline = {
+            let timeline = self.timeline();
+            HlsTimeline {
+                media_sequence: timeline.media_sequence,
+                durations: timeline.durations.clone(),
+                next_sequence: sequence,
+                low_latency: timeline.low_latency.clone(),
+            }
+        };
+        let seeker = Arc::new(Self {
+            http: self.http.clone(),
+            session: self.session.clone(),
+            renditions: self.renditions.clone(),
+            variant_urls: self.variant_urls.clone(),
+            playlist_url: self.playlist_url.clone(),
+            hint_url: self.hint_url.clone(),
+            live: self.live,
+            prefetch_segments: self.prefetch_segments,
+            timeline: Mutex::new(timeline),
+        });
+        let variant = self.session.next_variant(None);
+        let playlist_url = self.media_playlist_url(variant);
+        let http = self.http.for_new_source();
+        let mut reader = HlsStreamReader::start(http, seeker, variant, playlist_url, sequence);
+        reader.next_part = part;
+        reader
+    }
+
+    /// Media playlist to read for `variant`: its audio rendition's if that has
+    /// one, otherwise the variant's own.
+    fn media_playlist_url(&self, variant: usize) -> reqwest::Url {
+        self.renditions
+            .url(variant)
+            .or_else(|| self.variant_urls.get(variant).cloned())
+            .unwrap_or_else(|| self.playlist_url.clone())
+    }
+
+    fn timeline(&self) -> std::sync::MutexGuard<'_, HlsTimeline> {
+        self.timeline.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn update_playlist(&self, playlist: &MediaPlaylist<'_>, low_latency: &LowLatency) {
+        let mut timeline = self.timeline();
+        timeline.low_latency = low_latency.clone();
+        timeline.media_sequence = playlist.media_sequence;
+        timeline.durations = playlist
+            .segments
+            .values()
+            .map(|segment| segment.duration.duration())
+            .collect();
+    }
+
+    fn update_position(&self, next_sequence: usize) {
+        self.timeline().next_sequence = next_sequence;
+    }
+}
+
+pub struct HlsStreamReader {
+    playlist_url: reqwest::Url,
+    http: HttpContext,
+    /// Media playlist URLs of the master playlist's variants, by variant index.
+    variant_urls: Vec<reqwest::Url>,
+    variant: usize,
+    session: Arc<HlsSession>,
+    seeker: Arc<HlsSeeker>,
+    throughput: ThroughputEstimator,
+    cached_playlist: Option<MediaPlaylist<'static>>,
+    /// Partial segments of `cached_playlist`.
+    low_latency: LowLatency,
+    next_sequence: Option<usize>,
+    /// Next partial segment of `next_sequence`; 0 reads it whole once it is complete.
+    next_part: usize,
+    current_segment: Option<SegmentReader>,
+    keys: HashMap<reqwest::Url, [u8; AES_BLOCK_LEN]>,
+    /// Initialization section last written to the byte stream.
+    current_map: Option<SegmentSource>,
+    range_connection: SharedRangeConnection,
+    prefetcher: SegmentPrefetcher,
+    /// Reloads live playlists; started with the first reload.
+    refresher: Option<PlaylistRefresher>,
+    /// Reload of the refresher `cached_playlist` came from; 0 before the first.
+    playlist_generation: u64,
+    cues: Arc<MetadataCues>,
+    dates: Arc<ProgramDates>,
+    /// Playlist duration of the segments opened so far.
+    stream_time: Duration,
+    /// First segment after a discontinuity, held back until the next decoder reads.
+    pending_segment: Option<HlsSegment>,
+    /// Whether the current discontinuity section has produced any data.
+    section_started: bool,
+    at_discontinuity: bool,
+    failed_segments: u32,
+    ended: bool,
+    pos: u64,
+}
+
+impl HlsStreamReader {
+    pub fn new(
+        url: &str,
+        http: HttpContext,
+        max_bandwidth: Option<u64>,
+        live_edge_segments: usize,
+        target_latency: Option<Duration>,
+        prefetch_segments: usize,
+    ) -> Result<(Self, Option<String>, Option<Duration>), RodioError> {
+        let playlist_url =
+            reqwest::Url::parse(url).map_err(|_| RodioError::InvalidUrl(url.to_string()))?;
+        let body = request_stream(playlist_url.as_str(), false, &http)?.text()?;
+        let media = parse_hls_media_playlist(&body);
+        let (variants, variant_urls, renditions) = match &media {
+            Ok(_) => (Vec::new(), Vec::new(), HlsRenditions::none()),
+            Err(_) => {
+                let master = MasterPlaylist::try_from(body.as_str()).map_err(|err| {
+                    RodioError::Playlist(format!("hls master playlist parse failed: {err}"))
+                })?;
+                let (variants, variant_urls) = hls_variants(&master, &playlist_url)?;
+                (
+                    variants,
+                    variant_urls,
+                    hls_renditions(&master, &playlist_url)?,
+                )
+            }
+        };
+        let session = Arc::new(HlsSession::new(variants, max_bandwidth));
+        let renditions = Arc::new(renditions);
+        let variant = session.next_variant(None);
+        let ((playlist, low_latency), playlist_url) = match media {
+            Ok(media) => (media, playlist_url),
+            Err(_) => {
+                let url = renditions
+                    .url(variant)
+                    .unwrap_or_else(|| variant_urls[variant].clone());
+                (fetch_hls_media_playlist(&url, &http)?, url)
+            }
+        };
+        let hint_url = first_hls_segment_url(&playlist_url, &playlist);
+        let total_duration = hls_total_duration(&playlist);
+        let seeker = Arc::new(HlsSeeker {
+            http: http.clone(),
+            session,
+            renditions,
+            variant_urls,
+            playlist_url: playlist_url.clone(),
+            hint_url: hint_url.clone(),
+            live: !playlist.has_end_list,
+            prefetch_segments,
+            timeline: Mutex::new(HlsTimeline {
+                media_sequence: playlist.media_sequence,
+                durations: Vec::new(),
+                next_sequence: playlist.media_sequence,
+                low_latency: LowLatency::default(),
+            }),
+        });
+        seeker.update_playlist(&playlist, &low_latency);
+        // The spec advises against starting a live stream right at its edge.
+        let mut reader = if seeker.is_live() {
+            seeker.reader_at_live_edge(live_edge_segments, target_latency)
+        } else {
+            seeker.reader_from(playlist.media_sequence, 0)
+        };
+        reader.cached_playlist = Some(playlist);
+        reader.low_latency = low_latency;
+        Ok((reader, hint_url, total_duration))
+    }
+
+    /// The flag that stops this reader's retries and waits.
+    pub fn cancelled(&self) -> Arc<AtomicBool> {
+        self.http.cancelled.clone()
+    }
+
+    fn start(
+        http: HttpContext,
+        seeker: Arc<HlsSeeker>,
+        variant: usize,
+        playlist_url: reqwest::Url,
+        next_sequence: usize,
+    ) -> Self {
+        let prefetcher = SegmentPrefetcher::new(seeker.prefetch_segments);
+        Self {
+            playlist_url,
+            http,
+            variant_urls: seeker.variant_urls.clone(),
+            variant,
+            session: seeker.session.clone(),
+            seeker,
+            throughput: ThroughputEstimator::new(),
+            cached_playlist: None,
+            low_latency: LowLatency::default(),
+            next_sequence: Some(next_sequence),
+            next_part: 0,
+            current_segment: None,
+            keys: HashMap::new(),
+            current_map: None,
+            range_connection: Arc::new(Mutex::new(None)),
+            prefetcher,
+            refresher: None,
+            playlist_generation: 0,
+            cues: Arc::new(MetadataCues::default()),
+            dates: Arc::new(ProgramDates::default()),
+            stream_time: Duration::ZERO,
+            pending_segment: None,
+            section_started: false,
+            at_discontinuity: false,
+            failed_segments: 0,
+            ended: false,
+            pos: 0,
+        }
+    }
+
+    pub fn playback(&self) -> HlsPlayback {
+        HlsPlayback {
+            session: self.session.clone(),
+            seeker: self.seeker.clone(),
+            renditions: self.seeker.renditions.clone(),
+            cues: self.cues.clone(),
+            dates: self.dates.clone(),
+        }
+    }
+
+    fn load_playlist(&mut self) -> Result<(), RodioError> {
+        let (playlist, low_latency) = if self.seeker.is_live() {
+            self.reload_live_playlist()?
+        } else {
+            retry(&self.http.reconnect, &self.http.cancelled, || {
+                fetch_hls_media_playlist(&self.playlist_url, &self.http)
+            })?
+        };
+        self.seeker.update_playlist(&playlist, &low_latency);
+        self.cached_playlist = Some(playlist);
+        self.low_latency = low_latency;
+        Ok(())
+    }
+
+    /// The refresher's next reload after the one already used, so this waits
+    /// for its timer rather than polling.
+    fn reload_live_playlist(
+        &mut self,
+    ) -> Result<(MediaPlaylist<'static>, LowLatency), RodioError> {
+        let refresher = match self.refresher.take() {
+            Some(refresher) => refresher,
+            None => PlaylistRefresher::spawn(self.playlist_url.clone(), self.http.clone())?,
+        };
+        refresher.set_url(&self.playlist_url);
+        let reloaded = refresher.wait_newer(self.playlist_generation);
+        self.refresher = Some(refresher);
+        let (generation, playlist) = reloaded?;
+        self.playlist_generation = generation;
+        Ok(playlist)
+    }
+
+    /// Variants share media sequence numbers, so switching keeps `next_sequence`
+    /// and only swaps the media playlist the next segment comes from. Variants
+    /// sharing an audio rendition keep reading the same playlist.
+    fn update_variant(&mut self) {
+        if self.variant_urls.len() < 2 {
+            return;
+        }
+        let variant = self.session.next_variant(self.throughput.estimate());
+        if variant != self.variant {
+            self.variant = variant;
+            let playlist_url = self.seeker.media_playlist_url(variant);
+            if playlist_url != self.playlist_url {
+                self.playlist_url = playlist_url;
+                self.cached_playlist = None;
+            }
+        }
+    }
+
+    /// Fetches `segment`, from the prefetcher when it was downloaded ahead,
+    /// retrying failures with the reconnect policy's backoff.
+    fn fetch_segment(&mut self, segment: HlsSegment) -> Result<SegmentReader, RodioError> {
+        let mut prefetched = segment
+            .source
+            .range
+            .is_none()
+            .then(|| self.prefetcher.take(&segment.source.url))
+            .flatten();
+        self.prefetch_upcoming();
+        let policy = self.http.reconnect;
+        let cancelled = self.http.cancelled.clone();
+        retry(&policy, &cancelled, || match prefetched.take() {
+            Some(fetched) => {
+                let fetched = fetched?;
+                if self.variant_urls.len() >= 2 {
+                    self.throughput
+                        .add_sample(fetched.bytes.len(), fetched.elapsed);
+                }
+                self.open_segment(segment.clone(), Some(fetched.bytes))
+            }
+            None => self.download_segment(segment.clone()),
+        })
+    }
+
+    /// With several variants, segments are downloaded whole so their timing
+    /// measures the network rather than how fast playback consumes them.
+    fn download_segment(&mut self, segment: HlsSegment) -> Result<SegmentReader, RodioError> {
+        if self.variant_urls.len() < 2 {
+            return self.open_segment(segment, None);
+        }
+        let started = Instant::now();
+        let mut bytes = Vec::new();
+        self.open_segment(segment, None)?.read_to_end(&mut bytes)?;
+        self.throughput.add_sample(bytes.len(), started.elapsed());
+        Ok(Box::new(Cursor::new(bytes)))
+    }
+
+    fn next_segment(&mut self) -> Result<Option<HlsSegment>, RodioError> {
+        self.update_variant();
+        loop {
+            if self.ended {
+                return Ok(None);
+            }
+            if self.cached_playlist.is_none() {
+                self.load_playlist()?;
+            }
+            let (segment_count, has_end_list, media_sequence) = {
+                let playlist = self.cached_playlist.as_ref().ok_or_else(|| {
+                    RodioError::Internal("hls playlist missing after load".to_string())
+                })?;
+                (
+                    playlist.segments.values().count(),
+                    playlist.has_end_list,
+                    playlist.media_sequence,
+                )
+            };
+            if segment_count == 0 && !self.low_latency.has_parts() {
+                if has_end_list || !self.seeker.is_live() {
+                    self.ended = true;
+                    return Ok(None);
+                }
+                // Waits for the next reload of the live playlist.
+                self.cached_playlist = None;
+                continue;
+            }
+
+            let mut next_sequence = self.next_sequence.unwrap_or(media_sequence);
+            if next_sequence < media_sequence {
+                next_sequence = media_sequence;
+                self.next_sequence = Some(next_sequence);
+                self.next_part = 0;
+            }
+
+            let index = next_sequence - media_sequence;
+            if self.reading_parts(next_sequence, media_sequence + segment_count) {
+                match self.next_partial_segment(next_sequence, index < segment_count)? {
+                    Some(segment) => return Ok(Some(segment)),
+                    None => continue,
+                }
+            }
+            if index >= segment_count {
+                if has_end_list || !self.seeker.is_live() {
+                    self.ended = true;
+                    return Ok(None);
+                }
+                // Waits for the next reload of the live playlist.
+                self.cached_playlist = None;
+                continue;
+            }
+
+            let playlist = self
+                .cached_playlist
+                .as_ref()
+                .ok_or_else(|| RodioError::Internal("hls playlist missing after load".to_string()))?;
+            let segment = playlist
+                .segments
+                .values()
+                .nth(index)
+                .ok_or_else(|| RodioError::Internal("hls segment lookup failed".to_string()))?;
+            let discontinuity = segment.has_discontinuity;
+            let duration = segment.duration.duration();
+            // Undated segments continue the previous date, unless a discontinuity broke it.
+            let date = segment
+                .program_date_time
+                .as_ref()
+                .and_then(|tag| parse_date_time(&tag.date_time));
+            let encryption = segment_encryption(&self.playlist_url, segment, next_sequence)?;
+            // The parser already resolved implicit offsets against the previous sub-range.
+            let source = SegmentSource {
+                url: resolve_hls_url(&self.playlist_url, segment.uri().as_ref())?,
+                range: segment.byte_range.map(|range| byte_range_bounds(*range)),
+            };
+            let init = self.init_section(Some(index), next_sequence, discontinuity)?;
+            self.next_sequence = Some(next_sequence + 1);
+            self.seeker.update_position(next_sequence + 1);
+            let start = self.stream_time;
+            self.stream_time += duration;
+            if date.is_some() || discontinuity {
+                self.dates.push(start, date);
+            }
+            return Ok(Some(HlsSegment {
+                source,
+                encryption,
+                init,
+                start,
+                discontinuity,
+            }));
+        }
+    }
+
+    /// Whether the cursor is in partial segments: within a segment already
+    /// started part by part, or past the last complete segment (`live_edge`)
+    /// of a low-latency playlist.
+    fn reading_parts(&self, next_sequence: usize, live_edge: usize) -> bool {
+        self.next_part > 0 || (next_sequence >= live_edge && self.low_latency.has_parts())
+    }
+
+    /// The partial segment of `sequence` at `next_part`. `None` when there is
+    /// nothing to read yet, after waiting for a reload, or once a `complete`
+    /// segment has been read to its end and the cursor moved to the next one.
+    fn next_partial_segment(
+        &mut self,
+        sequence: usize,
+        complete: bool,
+    ) -> Result<Option<HlsSegment>, RodioError> {
+        let part = loop {
+            match self.low_latency.part(sequence, self.next_part) {
+                Some(part) if part.gap => {
+                    self.stream_time += part.duration;
+                    self.next_part += 1;
+                }
+                Some(part) => break part.clone(),
+                None if complete => {
+                    // Read to its end, or the playlist no longer lists its parts.
+                    self.next_part = 0;
+                    self.next_sequence = Some(sequence + 1);
+                    self.seeker.update_position(sequence + 1);
+                    return Ok(None);
+                }
+                None => {
+                    // The blocking reload returns once the server has the part.
+                    self.cached_playlist = None;
+                    return Ok(None);
+                }
+            }
+        };
+        // A segment still being produced uses the last listed segment's map.
+        let segment_count = self
+            .cached_playlist
+            .as_ref()
+            .map_or(0, |playlist| playlist.segments.values().count());
+        let index = match self.cached_playlist.as_ref() {
+            Some(playlist) if complete => Some(sequence - playlist.media_sequence),
+            _ => segment_count.checked_sub(1),
+        };
+        let discontinuity = part.index == 0 && part.discontinuity;
+        let init = self.init_section(index, sequence, discontinuity)?;
+        self.next_part += 1;
+        let start = self.stream_time;
+        self.stream_time += part.duration;
+        if part.date.is_some() || discontinuity {
+            self.dates.push(start, part.date);
+        }
+        Ok(Some(HlsSegment {
+            source: SegmentSource {
+                url: resolve_hls_url(&self.playlist_url, &part.uri)?,
+                range: part.byte_range,
+            },
+            // Parts are only read from clear playlists; see `LowLatency::has_parts`.
+            encryption: None,
+            init,
+            start,
+            discontinuity,
+        }))
+    }
+
+    /// `EXT-X-MAP` section to emit before the segment at `index` of the cached
+    /// playlist, when it differs from the last one written or a discontinuity
+    /// restarts the decoder.
+    fn init_section(
+        &mut self,
+        index: Option<usize>,
+        sequence: usize,
+        discontinuity: bool,
+    ) -> Result<Option<InitSection>, RodioError> {
+        let map = match (&self.cached_playlist, index) {
+            (Some(playlist), Some(index)) => hls_segment_map(playlist, index),
+            _ => None,
+        };
+        let Some(map) = map else {
+            self.current_map = None;
+            return Ok(None);
+        };
+        let source = SegmentSource {
+            url: resolve_hls_url(&self.playlist_url, map.uri().as_ref())?,
+            range: map.range().map(byte_range_bounds),
+        };
+        if self.current_map.as_ref() == Some(&source) && !discontinuity {
+            return Ok(None);
+        }
+        // Init sections hold no samples, so only AES-128 applies to them.
+        let encryption = select_encryption(&self.playlist_url, map.keys(), sequence)?
+            .filter(|encryption| encryption.method == EncryptionMethod::Aes128);
+        self.current_map = Some(source.clone());
+        Ok(Some(InitSection { source, encryption }))
+    }
+
+    /// Starts downloading the segments after the one being fetched, as far as
+    /// the cached playlist goes. Byte-range segments are left to the shared
+    /// ranged connection.
+    fn prefetch_upcoming(&mut self) {
+        let (Some(playlist), Some(next_sequence)) = (&self.cached_playlist, self.next_sequence)
+        else {
+            return;
+        };
+        let live_edge = playlist.media_sequence + playlist.segments.values().count();
+        if self.reading_parts(next_sequence, live_edge) {
+            // Listed parts, then the one the server hinted at before listing it.
+            let cursor = (next_sequence, self.next_part);
+            let hint = self
+                .low_latency
+                .preload_hint
+                .iter()
+                .filter(|hint| (hint.sequence, hint.index) >= cursor)
+                .map(|hint| hint.uri.as_str());
+            let urls: Vec<reqwest::Url> = self
+                .low_latency
+                .parts
+                .iter()
+                .filter(|part| (part.sequence, part.index) >= cursor)
+                .filter(|part| !part.gap && part.byte_range.is_none())
+                .map(|part| part.uri.as_str())
+                .chain(hint)
+                .take(self.prefetcher.depth())
+                .filter_map(|uri| resolve_hls_url(&self.playlist_url, uri).ok())
+                .collect();
+            for url in urls {
+                self.prefetcher.start(url, &self.http);
+            }
+            return;
+        }
+        let urls: Vec<reqwest::Url> = playlist
+            .segments
+            .values()
+            .skip(next_sequence.saturating_sub(playlist.media_sequence))
+            .take(self.prefetcher.depth())
+            .filter(|segment| segment.byte_range.is_none())
+            .filter_map(|segment| resolve_hls_url(&self.playlist_url, segment.uri().as_ref()).ok())
+            .collect();
+        for url in urls {
+            self.prefetcher.start(url, &self.http);
+        }
+    }
+
+    /// `body` is the segment's data when it was prefetched.
+    fn open_segment(
+        &mut self,
+        segment: HlsSegment,
+        body: Option<Vec<u8>>,
+    ) -> Result<SegmentReader, RodioError> {
+        let cues = SegmentCues {
+            cues: self.cues.clone(),
+            start: segment.start,
+        };
+        let Some(init) = segment.init else {
+            return self.open_media(segment.source, body, segment.encryption, &cues);
+        };
+        // Prepending the init section turns the segment into a self-contained
+        // fragmented MP4 stream; repeated ftyp/moov boxes are skipped by the demuxer.
+        let mut bytes = Vec::new();
+        self.open_media(init.source, None, init.encryption, &cues)?
+            .read_to_end(&mut bytes)?;
+        let reader = self.open_media(segment.source, body, segment.encryption, &cues)?;
+        Ok(Box::new(Cursor::new(bytes).chain(reader)))
+    }
+
+    fn open_source(&self, source: SegmentSource) -> Result<SegmentReader, RodioError> {
+        match source.range {
+            Some(range) => Ok(Box::new(ByteRangeReader {
+                connection: self.range_connection.clone(),
+                http: self.http.clone(),
+                url: source.url,
+                offset: range.start,
+                end: range.end,
+            })),
+            None => Ok(Box::new(request_stream(
+                source.url.as_str(),
+                false,
+                &self.http,
+            )?)),
+        }
+    }
+
+    fn open_media(
+        &mut self,
+        source: SegmentSource,
+        body: Option<Vec<u8>>,
+        encryption: Option<SegmentEncryption>,
+        cues: &SegmentCues,
+    ) -> Result<SegmentReader, RodioError> {
+        let key = encryption
+            .as_ref()
+            .map(|encryption| self.fetch_key(&encryption.key_url))
+            .transpose()?;
+        let reader = match body {
+            Some(body) => Box::new(Cursor::new(body)),
+            None => self.open_source(source)?,
+        };
+        let (Some(encryption), Some(key)) = (encryption, key) else {
+            return Ok(elementary_stream(reader, cues)?);
+        };
+        match encryption.method {
+            EncryptionMethod::Aes128 => {
+                let reader = Aes128CbcReader::new(reader, &key, &encryption.iv);
+                Ok(elementary_stream(reader, cues)?)
+            }
+            EncryptionMethod::SampleAes => {
+                // Frames are decrypted individually, so the segment is fetched whole.
+                let mut bytes = Vec::new();
+                elementary_stream(reader, cues)?.read_to_end(&mut bytes)?;
+                decrypt_sample_aes_adts(&mut bytes, &key, &encryption.iv)?;
+                Ok(Box::new(Cursor::new(bytes)))
+            }
+            method => Err(RodioError::Playlist(format!(
+                "hls encryption method {method} is not supported"
+            ))),
+        }
+    }
+
+    /// Key requests go through the same client, headers and auth as segments.
+    fn fetch_key(&mut self, url: &reqwest::Url) -> Result<[u8; AES_BLOCK_LEN], RodioError> {
+        if let Some(key) = self.keys.get(url) {
+            return Ok(*key);
+        }
+        let bytes = download_bytes(url.as_str(), &self.http)?;
+        let key = <[u8; AES_BLOCK_LEN]>::try_from(bytes.as_slice()).map_err(|_| {
+            RodioError::Playlist(format!(
+                "hls key {url} is {} bytes, expected {AES_BLOCK_LEN}",
+                bytes.len()
+            ))
+        })?;
+        if self.keys.len() >= MAX_CACHED_KEYS {
+            self.keys.clear();
+        }
+        self.keys.insert(url.clone(), key);
+        Ok(key)
+    }
+
+    /// Records a segment that could not be fetched or read, so the stream can
+    /// continue with the next one; too many in a row, or a server failing its
+    /// certificate pin, end the stream.
+    fn skip_failed_segment(&mut self, error: RodioError) -> io::Result<()> {
+        self.failed_segments += 1;
+        if self.failed_segments > MAX_SKIPPED_SEGMENTS
+            || matches!(error, RodioError::CertificatePinMismatch(_))
+        {
+            self.ended = true;
+            return Err(io::Error::other(error));
+        }
+        Ok(())
+    }
+}
+
+impl Read for HlsStreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.at_discontinuity {
+                return Ok(0);
+            }
+            if let Some(segment) = &mut self.current_segment {
+                match segment.read(buf) {
+                    Ok(0) => self.current_segment = None,
+                    Ok(read) => {
+                        self.pos = self.pos.saturating_add(read as u64);
+                        return Ok(read);
+                    }
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    // The rest of a segment that breaks off mid-stream is dropped.
+                    Err(error) => {
+                        self.current_segment = None;
+                        self.skip_failed_segment(error.into())?;
+                    }
+                }
+                continue;
+            }
+
+            if self.ended {
+                return Ok(0);
+            }
+
+            let next = match self.pending_segment.take() {
+                Some(segment) => Some(segment),
+                None => self.next_segment().map_err(io::Error::other)?,
+            };
+            let Some(segment) = next else {
+                self.ended = true;
+                return Ok(0);
+            };
+            // The decoder of this section ends here; the next one starts fresh.
+            if segment.discontinuity && self.section_started {
+                self.pending_segment = Some(segment);
+                self.section_started = false;
+                self.at_discontinuity = true;
+                return Ok(0);
+            }
+            match self.fetch_segment(segment) {
+                Ok(reader) => {
+                    self.failed_segments = 0;
+                    self.section_started = true;
+                    self.current_segment = Some(reader);
+                }
+                Err(error) => self.skip_failed_segment(error)?,
+            }
+        }
+    }
+}
+
+/// Sections end at each `EXT-X-DISCONTINUITY`.
+impl SectionedStream for HlsStreamReader {
+    fn take_boundary(&mut self) -> bool {
+        std::mem::take(&mut self.at_discontinuity)
+    }
+
+    fn restart_section(&mut self) -> bool {
+        // Segments carry their init section, so the next one starts cleanly.
+        self.current_segment = None;
+        self.section_started = false;
+        !self.ended
+    }
+}
+
+impl Seek for HlsStreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        seek_in_place(self.pos, pos)
+    }
+}
+
+/// Demuxes transport stream segments to their audio and takes the ID3 tags off
+/// the front of packed audio segments, queueing their metadata at the segment start.
+fn elementary_stream<R>(mut reader: R, cues: &SegmentCues) -> io::Result<SegmentReader>
+where
+    R: Read + Send + Sync + 'static,
+{
+    let mut head = Vec::with_capacity(PROBE_LEN);
+    (&mut reader)
+        .take(PROBE_LEN as u64)
+        .read_to_end(&mut head)?;
+    if is_ts(&head) {
+        return Ok(Box::new(TsDemuxer::new(
+            Cursor::new(head).chain(reader),
+            cues.clone(),
+        )));
+    }
+    let mut offset = 0;
+    while let Some(len) = id3::declared_len(&head[offset..]) {
+        // Read the rest of the tag and the header of the next one, if any.
+        let wanted = offset + len + ID3_PROBE_LEN;
+        if head.len() < wanted {
+            (&mut reader)
+                .take((wanted - head.len()) as u64)
+                .read_to_end(&mut head)?;
+        }
+        let end = (offset + len).min(head.len());
+        cues.push(Duration::ZERO, id3::parse_tag(&head[offset..end]));
+        offset = end;
+    }
+    head.drain(..offset);
+    Ok(Box::new(Cursor::new(head).chain(reader)))
+}
+
+fn byte_range_bounds(range: ByteRange) -> Range<u64> {
+    let start = range.start().unwrap_or(0);
+    start as u64..range.end() as u64
+}
+
+/// The parser attaches `EXT-X-MAP` to the segment right after the tag only;
+/// it applies to every following segment until the next one.
+fn hls_segment_map<'a>(playlist: &'a MediaPlaylist<'_>, index: usize) -> Option<&'a ExtXMap<'a>> {
+    playlist
+        .segments
+        .values()
+        .take(index + 1)
+        .filter_map(|segment| segment.map.as_ref())
+        .next_back()
+}
+
+fn segment_encryption(
+    playlist_url: &reqwest::Url,
+    segment: &MediaSegment<'_>,
+    sequence: usize,
+) -> Result<Option<SegmentEncryption>, RodioError> {
+    select_encryption(playlist_url, segment.keys(), sequence)
+}
+
+/// Picks the `KEYFORMAT="identity"` key. Keys in other formats belong to
+/// DRM systems and cannot be used directly.
+fn select_encryption(
+    playlist_url: &reqwest::Url,
+    keys: Vec<&DecryptionKey<'_>>,
+    sequence: usize,
+) -> Result<Option<SegmentEncryption>, RodioError> {
+    if keys.is_empty() {
+        return Ok(None);
+    }
+    let key = keys
+        .into_iter()
+        .find(|key| matches!(key.format, None | Some(KeyFormat::Identity)))
+        .ok_or_else(|| {
+            RodioError::Playlist("hls segments use an unsupported DRM key format".to_string())
+        })?;
+    // Without an explicit IV the media sequence number is the IV, big-endian.
+    let iv = key
+        .iv
+        .to_slice()
+        .unwrap_or_else(|| (sequence as u128).to_be_bytes());
+    Ok(Some(SegmentEncryption {
+        method: key.method,
+        key_url: resolve_hls_url(playlist_url, key.uri().as_ref())?,
+        iv,
+    }))
+}
+
+pub fn hls_refresh_delay(target_duration: Duration) -> Duration {
+    let millis = (target_duration.as_millis() as u64 / 2).clamp(500, 2000);
+    Duration::from_millis(millis)
+}
+
+fn hls_total_duration(playlist: &MediaPlaylist<'_>) -> Option<Duration> {
+    if !playlist.has_end_list {
+        return None;
+    }
+    let mut total = Duration::ZERO;
+    for segment in playlist.segments.values() {
+        total = total.saturating_add(segment.duration.duration());
+    }
+    Some(total)
+}
+
+fn first_hls_segment_url(
+    playlist_url: &reqwest::Url,
+    playlist: &MediaPlaylist<'_>,
+) -> Option<String> {
+    // For fragmented MP4 the init section's extension says more than `.m4s` segments.
+    let segment = playlist.segments.values().next()?;
+    let uri = match hls_segment_map(playlist, 0) {
+        Some(map) => map.uri().as_ref(),
+        None => segment.uri().as_ref(),
+    };
+    resolve_hls_url(playlist_url, uri)
+        .ok()
+        .map(|url| url.to_string())
+}
+
+fn parse_hls_media_playlist(
+    body: &str,
+) -> Result<(MediaPlaylist<'static>, LowLatency), RodioError> {
+    let (body, low_latency) = parse_low_latency(body);
+    MediaPlaylist::try_from(body.as_str())
+        .map(|playlist| (playlist.into_owned(), low_latency))
+        .map_err(|err| RodioError::Playlist(format!("hls media playlist parse failed: {err}")))
+}
+
+fn resolve_hls_url(base_url: &reqwest::Url, candidate: &str) -> Result<reqwest::Url, RodioError> {
+    if let Ok(url) = reqwest::Url::parse(candidate) {
+        return Ok(url);
+    }
+    base_url
+        .join(candidate)
+        .map_err(|_| RodioError::InvalidUrl(candidate.to_string()))
+}
+
+/// Playable variants in master playlist order, with their resolved media playlist URLs.
+fn hls_variants(
+    master: &MasterPlaylist<'_>,
+    base_url: &reqwest::Url,
+) -> Result<(Vec<HlsVariant>, Vec<reqwest::Url>), RodioError> {
+    let mut variants = Vec::new();
+    let mut urls = Vec::new();
+    for variant in &master.variant_streams {
+        let VariantStream::ExtXStreamInf { uri, .. } = variant else {
+            continue;
+        };
+        let url = resolve_hls_url(base_url, uri.as_ref())?;
+        variants.push(HlsVariant {
+            index: variants.len() as u32,
+            bandwidth: variant.bandwidth(),
+            average_bandwidth: variant.average_bandwidth(),
+            codecs: variant.codecs().map(|codecs| codecs.to_string()),
+            url: url.to_string(),
+        });
+        urls.push(url);
+    }
+    if variants.is_empty() {
+        return Err(RodioError::Playlist(
+            "hls master playlist has no stream variants".to_string(),
+        ));
+    }
+    Ok((variants, urls))
+}
+
+/// `AUDIO` renditions of `master` and the audio group each variant plays with.
+fn hls_renditions(
+    master: &MasterPlaylist<'_>,
+    base_url: &reqwest::Url,
+) -> Result<HlsRenditions, RodioError> {
+    let mut renditions = Vec::new();
+    let mut urls = Vec::new();
+    for media in &master.media {
+        if media.media_type != MediaType::Audio {
+            continue;
+        }
+        let url = media
+            .uri()
+            .map(|uri| resolve_hls_url(base_url, uri.as_ref()))
+            .transpose()?;
+        renditions.push(HlsRendition {
+            group_id: media.group_id().to_string(),
+            name: media.name().to_string(),
+            language: media.language().map(|language| language.to_string()),
+            is_default: media.is_default,
+            autoselect: media.is_autoselect,
+            url: url.as_ref().map(|url| url.to_string()),
+        });
+        urls.push(url);
+    }
+    let variant_groups = master
+        .variant_streams
+        .iter()
+        .filter_map(|variant| match variant {
+            VariantStream::ExtXStreamInf { audio, .. } => {
+                Some(audio.as_ref().map(|audio| audio.to_string()))
+            }
+            _ => None,
+        })
+        .collect();
+    Ok(HlsRenditions {
+        renditions,
+        urls,
+        variant_groups,
+        selected: Mutex::new(None),
+    })
+}
+
+pub fn fetch_hls_media_playlist(
+    url: &reqwest::Url,
+    http: &HttpContext,
+) -> Result<(MediaPlaylist<'static>, LowLatency), RodioError> {
+    let response = request_stream(url.as_str(), false, http)?;
+    let body = response.text()?;
+    parse_hls_media_playlist(&body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use super::*;
+
+    /// Serves the first `served` bytes of `data` from the requested `Range`
+    /// start, counting connections.
+    fn range_server(data: Vec<u8>, served: usize) -> (reqwest::Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/audio.aac", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut start = 0;
+                let mut request = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while request.read_line(&mut line).is_ok_and(|read| read > 2) {
+                    if let Some(range) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        start = range.trim().trim_end_matches('-').parse().unwrap();
+                    }
+                    line.clear();
+                }
+                let body = &data[start.min(served)..served];
+                let header = format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\
+                     Content-Range: bytes {start}-{}/*\r\nConnection: close\r\n\r\n",
+                    body.len(),
+                    served.saturating_sub(1),
+                );
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+        (reqwest::Url::parse(&url).unwrap(), connections)
+    }
+
+    fn range_reader(
+        connection: &SharedRangeConnection,
+        url: &reqwest::Url,
+        range: Range<u64>,
+    ) -> ByteRangeReader {
+        ByteRangeReader {
+            connection: connection.clone(),
+            http: HttpContext::default(),
+            url: url.clone(),
+            offset: range.start,
+            end: range.end,
+        }
+    }
+
+    #[test]
+    fn consecutive_byte_ranges_share_a_connection() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let (url, connections) = range_server(data.clone(), data.len());
+        let connection: SharedRangeConnection = Arc::new(Mutex::new(None));
+        for range in [0..100, 100..250, 250..251] {
+            let mut out = Vec::new();
+            let mut reader = range_reader(&connection, &url, range.clone());
+            let mut buf = [0u8; 7];
+            loop {
+                let read = reader.read(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                out.extend_from_slice(&buf[..read]);
+            }
+            assert_eq!(out, &data[range.start as usize..range.end as usize]);
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        // A gap needs a request of its own.
+        let mut out = Vec::new();
+        range_reader(&connection, &url, 400..410)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, &data[400..410]);
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn byte_range_past_the_resource_fails() {
+        let data = vec![7u8; 100];
+        let (url, _) = range_server(data, 50);
+        let connection: SharedRangeConnection = Arc::new(Mutex::new(None));
+        let mut out = Vec::new();
+        let err = range_reader(&connection, &url, 0..100)
+            .read_to_end(&mut out)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(out.len(), 50);
+    }
+
+    #[test]
+    fn byte_ranges_without_offset_continue_the_previous_one() {
+        let (playlist, _) = parse_hls_media_playlist(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-VERSION:4\n\
+             #EXTINF:4,\n#EXT-X-BYTERANGE:100@0\naudio.aac\n\
+             #EXTINF:4,\n#EXT-X-BYTERANGE:50\naudio.aac\n\
+             #EXTINF:4,\n#EXT-X-BYTERANGE:25\naudio.aac\n\
+             #EXTINF:4,\n#EXT-X-BYTERANGE:10@500\naudio.aac\n#EXT-X-ENDLIST\n",
+        )
+        .unwrap();
+        let ranges: Vec<_> = playlist
+            .segments
+            .values()
+            .map(|segment| segment.byte_range.map(|range| byte_range_bounds(*range)))
+            .collect();
+        assert_eq!(
+            ranges,
+            [Some(0..100), Some(100..150), Some(150..175), Some(500..510)]
+        );
+    }
+
+    /// A seeker over `playlist`, as `HlsStreamReader::new` builds it.
+    fn playlist_seeker(playlist: &str) -> HlsSeeker {
+        let (playlist, low_latency) = parse_hls_media_playlist(playlist).unwrap();
+        let url = reqwest::Url::parse("https://example.com/audio/index.m3u8").unwrap();
+        let seeker = HlsSeeker {
+            http: HttpContext::default(),
+            session: Arc::new(HlsSession::new(Vec::new(), None)),
+            renditions: Arc::new(HlsRenditions::none()),
+            variant_urls: Vec::new(),
+            playlist_url: url,
+            hint_url: None,
+            live: !playlist.has_end_list,
+            prefetch_segments: 0,
+            timeline: Mutex::new(HlsTimeline {
+                media_sequence: playlist.media_sequence,
+                durations: Vec::new(),
+                next_sequence: playlist.media_sequence,
+                low_latency: LowLatency::default(),
+            }),
+        };
+        seeker.update_playlist(&playlist, &low_latency);
+        seeker
+    }
+
+    /// Sequence number the reader starts at, and the offset returned with it.
+    fn start(reader: (HlsStreamReader, Duration)) -> (Option<usize>, Duration) {
+        (reader.0.next_sequence, reader.1)
+    }
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Segments 10 to 12, of 4, 4 and 2.5 seconds.
+    const PLAYLIST: &str = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:10\n\
+        #EXTINF:4,\na.aac\n#EXTINF:4,\nb.aac\n#EXTINF:2.5,\nc.aac\n";
+
+    #[test]
+    fn seeks_to_the_segment_containing_the_position() {
+        let seeker = playlist_seeker(&format!("{PLAYLIST}#EXT-X-ENDLIST\n"));
+        assert!(!seeker.is_live());
+        assert_eq!(seeker.total_duration(), millis(10_500));
+        let cases = [
+            (0, 10, 0),
+            (3999, 10, 0),
+            (4000, 11, 4000),
+            (7999, 11, 4000),
+            (8000, 12, 8000),
+            (10_499, 12, 8000),
+            // Past the end: the last segment.
+            (60_000, 12, 8000),
+        ];
+        for (position, sequence, segment_start) in cases {
+            assert_eq!(
+                start(seeker.reader_at(millis(position))),
+                (Some(sequence), millis(segment_start)),
+                "position {position}"
+            );
+        }
+        let empty = playlist_seeker("#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-ENDLIST\n");
+        assert_eq!(
+            start(empty.reader_at(millis(5000))),
+            (Some(0), Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn time_shifts_within_the_live_window() {
+        let seeker = playlist_seeker(PLAYLIST);
+        assert!(seeker.is_live());
+        let cases = [
+            // At the edge: the next segment to appear.
+            (0, 13, 0),
+            (1000, 12, 1500),
+            (2500, 12, 0),
+            (5000, 11, 1500),
+            (6500, 11, 0),
+            (10_500, 10, 0),
+            // Further back than the window: its start.
+            (60_000, 10, 0),
+        ];
+        for (behind, sequence, skip) in cases {
+            assert_eq!(
+                start(seeker.reader_behind_live(millis(behind))),
+                (Some(sequence), millis(skip)),
+                "{behind} ms behind"
+            );
+        }
+        let edge = |segments| seeker.reader_at_live_edge(segments, None).next_sequence;
+        assert_eq!(edge(3), Some(10));
+        assert_eq!(edge(1), Some(12));
+        assert_eq!(edge(0), Some(13));
+        assert_eq!(edge(9), Some(10));
+    }
+
+    fn renditions(master: &str) -> HlsRenditions {
+        let url = reqwest::Url::parse("https://example.com/master.m3u8").unwrap();
+        hls_renditions(&MasterPlaylist::try_from(master).unwrap(), &url).unwrap()
+    }
+
+    fn current_name(renditions: &HlsRenditions, variant: usize) -> Option<String> {
+        renditions.current(variant).map(|rendition| rendition.name)
+    }
+
+    const MASTER: &str = r#"#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="lo",NAME="English",LANGUAGE="en",AUTOSELECT=YES,URI="lo/en.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="lo",NAME="Deutsch",LANGUAGE="de",DEFAULT=YES,AUTOSELECT=YES,URI="lo/de.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="hi",NAME="Commentary",LANGUAGE="en-GB",URI="hi/commentary.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="hi",NAME="English HQ",LANGUAGE="EN",AUTOSELECT=YES,URI="hi/en.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="hi",NAME="Deutsch HQ",LANGUAGE="de",AUTOSELECT=YES,URI="hi/de.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="muxed",NAME="Main",LANGUAGE="fr"
+#EXT-X-STREAM-INF:BANDWIDTH=64000,AUDIO="lo"
+lo.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=256000,AUDIO="hi"
+hi.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=128000,AUDIO="muxed"
+muxed.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=32000
+plain.m3u8
+"#;
+
+    #[test]
+    fn picks_the_default_then_autoselect_rendition() {
+        let renditions = renditions(MASTER);
+        assert_eq!(renditions.renditions().len(), 6);
+        assert_eq!(current_name(&renditions, 0).as_deref(), Some("Deutsch"));
+        assert_eq!(
+            renditions.url(0).unwrap().as_str(),
+            "https://example.com/lo/de.m3u8"
+        );
+        assert_eq!(current_name(&renditions, 1).as_deref(), Some("English HQ"));
+        // Audio carried in the variant stream itself.
+        assert_eq!(current_name(&renditions, 2).as_deref(), Some("Main"));
+        assert_eq!(renditions.url(2), None);
+        assert_eq!(current_name(&renditions, 3), None);
+        assert_eq!(current_name(&renditions, 9), None);
+    }
+
+    #[test]
+    fn keeps_the_chosen_language_across_groups() {
+        let renditions = renditions(MASTER);
+        renditions.select("lo", "English").unwrap();
+        assert_eq!(current_name(&renditions, 0).as_deref(), Some("English"));
+        assert_eq!(current_name(&renditions, 1).as_deref(), Some("English HQ"));
+        assert_eq!(current_name(&renditions, 2).as_deref(), Some("Main"));
+
+        renditions.select("hi", "Deutsch HQ").unwrap();
+        assert_eq!(current_name(&renditions, 0).as_deref(), Some("Deutsch"));
+        renditions.select("hi", "Commentary").unwrap();
+        assert_eq!(current_name(&renditions, 1).as_deref(), Some("Commentary"));
+        // No rendition of the same name or language: the group's default.
+        assert_eq!(current_name(&renditions, 0).as_deref(), Some("Deutsch"));
+
+        assert!(matches!(
+            renditions.select("lo", "Deutsch HQ"),
+            Err(RodioError::HlsRenditionNotFound(_))
+        ));
+        assert_eq!(current_name(&renditions, 1).as_deref(), Some("Commentary"));
+    }
+}
@@ -0,0 +1,769 @@
+//! HTTP client configuration and request helpers.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, COOKIE, RANGE};
+use reqwest::{redirect, Certificate, Proxy, StatusCode};
+use rustls::pki_types::CertificateDer;
+
+use crate::error::RodioError;
+use crate::reconnect::{
+    is_retryable_reqwest, is_retryable_status, sleep_unless_cancelled, ReconnectPolicy,
+};
+use crate::tls::{
+    client_config, normalize_pin_host, parse_certificates_pem, parse_spki_pin, ClientIdentity,
+    SpkiPin,
+};
+
+const DEFAULT_USER_AGENT: &str = "RodioKt/1.0";
+
+/// Address family used for outgoing connections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum IpFamily {
+    /// Let the resolver pick (default).
+    Any,
+    /// Only connect over IPv4.
+    Ipv4,
+    /// Only connect over IPv6.
+    Ipv6,
+}
+
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct HttpCookie {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, uniffi::Enum)]
+pub enum HttpAuth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer {
+        token: String,
+    },
+}
+
+/// Per-playback request settings, sent with the initial request and every
+/// follow-up request (playlists, HLS segments, reconnects).
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct HttpRequestOptions {
+    pub headers: Vec<HttpHeader>,
+    #[uniffi(default = None)]
+    pub auth: Option<HttpAuth>,
+    pub cookies: Vec<HttpCookie>,
+}
+
+/// Request settings carried by a playback session.
+#[derive(Clone, Default)]
+pub struct HttpContext {
+    pub reconnect: ReconnectPolicy,
+    /// Named network profile; `None` uses the global defaults.
+    pub profile: Option<String>,
+    /// Set when the source reading through this context is stopped; retry
+    /// backoff and live waits end early once it is.
+    pub cancelled: Arc<AtomicBool>,
+    headers: HeaderMap,
+    auth: Option<HttpAuth>,
+}
+
+impl HttpContext {
+    pub fn new(
+        reconnect: ReconnectPolicy,
+        profile: Option<String>,
+        options: Option<&HttpRequestOptions>,
+    ) -> Result<Self, RodioError> {
+        let mut context = Self {
+            reconnect,
+            profile,
+            ..Self::default()
+        };
+        let Some(options) = options else {
+            return Ok(context);
+        };
+        for header in &options.headers {
+            let name = HeaderName::from_bytes(header.name.as_bytes())
+                .map_err(|_| RodioError::InvalidHeader(header.name.clone()))?;
+            let value = HeaderValue::from_str(&header.value)
+                .map_err(|_| RodioError::InvalidHeader(header.name.clone()))?;
+            context.headers.append(name, value);
+        }
+        if !options.cookies.is_empty() {
+            let cookies = options
+                .cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<_>>()
+                .join("; ");
+            let value = HeaderValue::from_str(&cookies)
+                .map_err(|_| RodioError::InvalidHeader(COOKIE.to_string()))?;
+            context.headers.insert(COOKIE, value);
+        }
+        context.auth = options.auth.clone();
+        Ok(context)
+    }
+
+    /// The same settings with a cancel flag of their own, for a source that
+    /// replaces the one currently playing.
+    pub fn for_new_source(&self) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            ..self.clone()
+        }
+    }
+
+    fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        request = request.headers(self.headers.clone());
+        match &self.auth {
+            Some(HttpAuth::Basic { username, password }) => {
+                request.basic_auth(username, password.as_ref())
+            }
+            Some(HttpAuth::Bearer { token }) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct HttpOptions {
+    generation: u64,
+    allow_invalid_certs: bool,
+    extra_roots: Vec<CertificateDer<'static>>,
+    identity: Option<ClientIdentity>,
+    /// SHA-256 SPKI pins keyed by normalized host.
+    pins: HashMap<String, Vec<SpkiPin>>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    proxy: Option<reqwest::Url>,
+    user_agent: String,
+    default_headers: HeaderMap,
+    max_redirects: Option<u32>,
+    ip_family: IpFamily,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            generation: 0,
+            allow_invalid_certs: false,
+            extra_roots: Vec::new(),
+            identity: None,
+            pins: HashMap::new(),
+            connect_timeout: None,
+            read_timeout: None,
+            proxy: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            default_headers: HeaderMap::new(),
+            max_redirects: None,
+            ip_family: IpFamily::Any,
+        }
+    }
+}
+
+/// Global defaults plus named profiles that players can opt into.
+#[derive(Default)]
+struct HttpRegistry {
+    defaults: HttpOptions,
+    profiles: HashMap<String, HttpOptions>,
+}
+
+impl HttpRegistry {
+    fn options(&self, profile: Option<&str>) -> Result<&HttpOptions, RodioError> {
+        match profile {
+            None => Ok(&self.defaults),
+            Some(name) => self
+                .profiles
+                .get(name)
+                .ok_or_else(|| RodioError::NetworkProfileNotFound(name.to_string())),
+        }
+    }
+
+    fn options_mut(&mut self, profile: Option<&str>) -> Result<&mut HttpOptions, RodioError> {
+        match profile {
+            None => Ok(&mut self.defaults),
+            Some(name) => self
+                .profiles
+                .get_mut(name)
+                .ok_or_else(|| RodioError::NetworkProfileNotFound(name.to_string())),
+        }
+    }
+}
+
+static HTTP_OPTIONS: OnceLock<Mutex<HttpRegistry>> = OnceLock::new();
+static HTTP_OPTIONS_GENERATION: AtomicU64 = AtomicU64::new(1);
+static HTTP_CLIENTS: OnceLock<Mutex<HashMap<Option<String>, CachedClient>>> = OnceLock::new();
+
+/// A client built for one generation of a profile's options. Sharing it keeps
+/// TLS sessions and keep-alive connections alive across segment requests.
+struct CachedClient {
+    generation: u64,
+    client: Client,
+}
+
+fn http_options() -> &'static Mutex<HttpRegistry> {
+    HTTP_OPTIONS.get_or_init(|| Mutex::new(HttpRegistry::default()))
+}
+
+fn http_options_snapshot(profile: Option<&str>) -> Result<HttpOptions, RodioError> {
+    let guard = http_options()
+        .lock()
+        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
+    guard.options(profile).cloned()
+}
+
+fn update_http_options<F>(profile: Option<&str>, f: F) -> Result<(), RodioError>
+where
+    F: FnOnce(&mut HttpOptions),
+{
+    let mut guard = http_options()
+        .lock()
+        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
+    let options = guard.options_mut(profile)?;
+    f(options);
+    options.generation = HTTP_OPTIONS_GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+fn apply_http_options(
+    mut builder: ClientBuilder,
+    options: &HttpOptions,
+) -> Result<ClientBuilder, RodioError> {
+    if options.identity.is_some() || !options.pins.is_empty() {
+        let config = client_config(
+            options.allow_invalid_certs,
+            &options.extra_roots,
+            options.identity.as_ref(),
+            &options.pins,
+        )?;
+        builder = builder.use_preconfigured_tls(config);
+    } else {
+        if options.allow_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        for cert in &options.extra_roots {
+            builder = builder.add_root_certificate(Certificate::from_der(cert)?);
+        }
+    }
+    if let Some(timeout) = options.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    // The blocking client applies this to the response head and to every body
+    // read. It is always set, as leaving it out keeps reqwest's 30 s default,
+    // which would cut off every live stream after half a minute.
+    builder = builder.timeout(options.read_timeout);
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(Proxy::all(proxy.as_str())?);
+    }
+    builder = builder
+        .user_agent(options.user_agent.as_str())
+        .default_headers(options.default_headers.clone());
+    if let Some(max) = options.max_redirects {
+        let policy = if max == 0 {
+            redirect::Policy::none()
+        } else {
+            redirect::Policy::limited(max as usize)
+        };
+        builder = builder.redirect(policy);
+    }
+    match options.ip_family {
+        IpFamily::Any => {}
+        IpFamily::Ipv4 => builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        IpFamily::Ipv6 => builder = builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+    }
+    Ok(builder)
+}
+
+pub fn http_client(profile: Option<&str>) -> Result<Client, RodioError> {
+    let mut cache = HTTP_CLIENTS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .map_err(|_| RodioError::Internal("http client lock failed".to_string()))?;
+    let options = http_options_snapshot(profile)?;
+    let key = profile.map(str::to_string);
+    if let Some(cached) = cache
+        .get(&key)
+        .filter(|cached| cached.generation == options.generation)
+    {
+        return Ok(cached.client.clone());
+    }
+    let client = build_http_client(&options)?;
+    cache.insert(
+        key,
+        CachedClient {
+            generation: options.generation,
+            client: client.clone(),
+        },
+    );
+    Ok(client)
+}
+
+fn build_http_client(options: &HttpOptions) -> Result<Client, RodioError> {
+    let builder = apply_http_options(Client::builder(), options)?;
+    match builder.build() {
+        Ok(client) => Ok(client),
+        Err(_) => {
+            let builder = Client::builder()
+                .tls_built_in_native_certs(false)
+                .tls_built_in_webpki_certs(true);
+            let builder = apply_http_options(builder, options)?;
+            Ok(builder.build()?)
+        }
+    }
+}
+
+pub fn request_stream(
+    url: &str,
+    want_metadata: bool,
+    context: &HttpContext,
+) -> Result<Response, RodioError> {
+    send_with_retry(url, context, |request| {
+        if want_metadata {
+            request.header("Icy-MetaData", "1")
+        } else {
+            request
+        }
+    })
+}
+
+/// Requests `url` from byte `start` (through `end`, inclusive, when given) and
+/// returns a body positioned at `start`, even when the server ignores `Range`.
+pub fn request_range(
+    url: &str,
+    start: u64,
+    end: Option<u64>,
+    context: &HttpContext,
+) -> Result<Response, RodioError> {
+    let range = match end {
+        Some(end) => format!("bytes={start}-{end}"),
+        None => format!("bytes={start}-"),
+    };
+    let mut response = send_with_retry(url, context, |request| {
+        request.header(RANGE, range.as_str())
+    })?;
+    if start > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+        let skipped = io::copy(&mut (&mut response).take(start), &mut io::sink())?;
+        if skipped < start {
+            return Err(RodioError::Http(format!("{url} ended before byte {start}")));
+        }
+    }
+    Ok(response)
+}
+
+fn send_with_retry<F>(
+    url: &str,
+    context: &HttpContext,
+    customize: F,
+) -> Result<Response, RodioError>
+where
+    F: Fn(RequestBuilder) -> RequestBuilder,
+{
+    let client = http_client(context.profile.as_deref())?;
+    let reconnect = &context.reconnect;
+    let mut attempt = 0;
+    loop {
+        let request = customize(context.apply(client.get(url)));
+        let can_retry = attempt < reconnect.max_attempts;
+        let error = match request.send() {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => {
+                let status = response.status().as_u16();
+                if !can_retry || !is_retryable_status(status) {
+                    return Err(RodioError::HttpStatus(status));
+                }
+                RodioError::HttpStatus(status)
+            }
+            Err(err) => {
+                if !can_retry || !is_retryable_reqwest(&err) {
+                    return Err(err.into());
+                }
+                err.into()
+            }
+        };
+        if !sleep_unless_cancelled(reconnect.delay(attempt), &context.cancelled) {
+            return Err(error);
+        }
+        attempt += 1;
+    }
+}
+
+pub fn download_bytes(url: &str, context: &HttpContext) -> Result<Vec<u8>, RodioError> {
+    let response = request_stream(url, false, context)?;
+    let bytes = response.bytes()?;
+    Ok(bytes.to_vec())
+}
+
+fn parse_proxy_url(
+    url: &str,
+    username: Option<String>,
+    password: Option<String>,
+) -> Result<reqwest::Url, RodioError> {
+    let mut parsed =
+        reqwest::Url::parse(url).map_err(|_| RodioError::InvalidUrl(url.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https" | "socks5" | "socks5h") {
+        return Err(RodioError::InvalidUrl(url.to_string()));
+    }
+    // Credentials travel in the URL so HTTP (Proxy-Authorization) and SOCKS5 auth share one path.
+    if let Some(username) = username {
+        parsed
+            .set_username(&username)
+            .map_err(|_| RodioError::InvalidUrl(url.to_string()))?;
+        parsed
+            .set_password(password.as_deref())
+            .map_err(|_| RodioError::InvalidUrl(url.to_string()))?;
+    }
+    Ok(parsed)
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_set_allow_invalid_certs(
+    allow: bool,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| {
+        options.allow_invalid_certs = allow
+    })
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_add_root_cert_pem(pem: String, profile: Option<String>) -> Result<(), RodioError> {
+    let certs = parse_certificates_pem(pem.as_bytes())?;
+    update_http_options(profile.as_deref(), |options| {
+        options.extra_roots.extend(certs)
+    })
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_clear_root_certs(profile: Option<String>) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| options.extra_roots.clear())
+}
+
+/// Presents a client certificate to servers that ask for one (mutual TLS).
+/// `pem` holds the certificate chain, leaf first, followed by the private key.
+#[uniffi::export(default(profile = None))]
+pub fn http_set_client_identity_pem(
+    pem: String,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    let identity = ClientIdentity::from_pem(pem.as_bytes())?;
+    update_http_options(profile.as_deref(), |options| {
+        options.identity = Some(identity)
+    })
+}
+
+/// Same as [`http_set_client_identity_pem`] for a PKCS#12 (`.p12`/`.pfx`) archive.
+#[uniffi::export(default(profile = None))]
+pub fn http_set_client_identity_pkcs12(
+    der: Vec<u8>,
+    password: String,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    let identity = ClientIdentity::from_pkcs12(&der, &password)?;
+    update_http_options(profile.as_deref(), |options| {
+        options.identity = Some(identity)
+    })
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_clear_client_identity(profile: Option<String>) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| options.identity = None)
+}
+
+/// Pins `host` to a SHA-256 hash of a SubjectPublicKeyInfo (`sha256//<base64>`,
+/// base64 or hex). Connections to a pinned host must present at least one of
+/// its pinned keys in the certificate chain.
+#[uniffi::export(default(profile = None))]
+pub fn http_add_pin(
+    host: String,
+    sha256: String,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    let pin = parse_spki_pin(&sha256)?;
+    let host = normalize_pin_host(&host);
+    if host.is_empty() {
+        return Err(RodioError::InvalidUrl(host));
+    }
+    update_http_options(profile.as_deref(), |options| {
+        let pins = options.pins.entry(host).or_default();
+        if !pins.contains(&pin) {
+            pins.push(pin);
+        }
+    })
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_clear_pins(profile: Option<String>) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| options.pins.clear())
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_set_connect_timeout_ms(
+    timeout_ms: Option<u64>,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| {
+        options.connect_timeout = timeout_ms.map(Duration::from_millis)
+    })
+}
+
+/// Limits how long a request waits for the response head and for each body
+/// read. `None` (the default) never times out.
+#[uniffi::export(default(profile = None))]
+pub fn http_set_read_timeout_ms(
+    timeout_ms: Option<u64>,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| {
+        options.read_timeout = timeout_ms.map(Duration::from_millis)
+    })
+}
+
+/// Routes every request through `url` (`http://`, `https://`, `socks5://` or `socks5h://`).
+#[uniffi::export(default(profile = None))]
+pub fn http_set_proxy(
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    let proxy = parse_proxy_url(&url, username, password)?;
+    update_http_options(profile.as_deref(), |options| options.proxy = Some(proxy))
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_clear_proxy(profile: Option<String>) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| options.proxy = None)
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_set_user_agent(user_agent: String, profile: Option<String>) -> Result<(), RodioError> {
+    HeaderValue::from_str(&user_agent)
+        .map_err(|_| RodioError::InvalidHeader(user_agent.clone()))?;
+    update_http_options(profile.as_deref(), |options| {
+        options.user_agent = user_agent
+    })
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_set_default_header(
+    name: String,
+    value: String,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    let header_name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| RodioError::InvalidHeader(name.clone()))?;
+    let header_value =
+        HeaderValue::from_str(&value).map_err(|_| RodioError::InvalidHeader(name))?;
+    update_http_options(profile.as_deref(), |options| {
+        options.default_headers.insert(header_name, header_value);
+    })
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_clear_default_headers(profile: Option<String>) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| {
+        options.default_headers.clear()
+    })
+}
+
+/// Follows at most `max_redirects` redirects; `0` disables redirects.
+#[uniffi::export(default(profile = None))]
+pub fn http_set_max_redirects(
+    max_redirects: u32,
+    profile: Option<String>,
+) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| {
+        options.max_redirects = Some(max_redirects)
+    })
+}
+
+#[uniffi::export(default(profile = None))]
+pub fn http_set_ip_family(family: IpFamily, profile: Option<String>) -> Result<(), RodioError> {
+    update_http_options(profile.as_deref(), |options| options.ip_family = family)
+}
+
+pub fn http_ensure_profile(name: &str) -> Result<(), RodioError> {
+    let guard = http_options()
+        .lock()
+        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
+    guard.options(Some(name)).map(|_| ())
+}
+
+/// Creates (or resets) a named network profile, starting from the current global defaults.
+#[uniffi::export]
+pub fn http_create_profile(name: String) -> Result<(), RodioError> {
+    let mut guard = http_options()
+        .lock()
+        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
+    let mut options = guard.defaults.clone();
+    options.generation = HTTP_OPTIONS_GENERATION.fetch_add(1, Ordering::Relaxed);
+    guard.profiles.insert(name, options);
+    Ok(())
+}
+
+#[uniffi::export]
+pub fn http_remove_profile(name: String) -> Result<(), RodioError> {
+    let mut guard = http_options()
+        .lock()
+        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
+    guard
+        .profiles
+        .remove(&name)
+        .map(|_| ())
+        .ok_or(RodioError::NetworkProfileNotFound(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::AUTHORIZATION;
+
+    use super::*;
+
+    #[test]
+    fn accepts_http_and_socks_proxies() {
+        for url in [
+            "http://proxy.example.com:3128",
+            "https://proxy.example.com",
+            "socks5://127.0.0.1:1080",
+            "socks5h://proxy.example.com:1080",
+        ] {
+            let parsed = parse_proxy_url(url, None, None).unwrap();
+            assert_eq!(parsed.username(), "", "{url}");
+        }
+        let parsed = parse_proxy_url(
+            "socks5h://proxy.example.com:1080",
+            Some("me".to_string()),
+            Some("p@ss word".to_string()),
+        )
+        .unwrap();
+        assert_eq!(parsed.scheme(), "socks5h");
+        assert_eq!(parsed.username(), "me");
+        assert_eq!(parsed.password(), Some("p%40ss%20word"));
+        let without_password =
+            parse_proxy_url("http://proxy:8080", Some("me".to_string()), None).unwrap();
+        assert_eq!(without_password.password(), None);
+    }
+
+    #[test]
+    fn rejects_other_proxy_schemes() {
+        for url in [
+            "socks4://proxy:1080",
+            "ftp://proxy",
+            "proxy.example.com:3128",
+            "not a url",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    parse_proxy_url(url, None, None),
+                    Err(RodioError::InvalidUrl(_))
+                ),
+                "{url}"
+            );
+        }
+    }
+
+    fn header(name: &str, value: &str) -> HttpHeader {
+        HttpHeader {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn cookie(name: &str, value: &str) -> HttpCookie {
+        HttpCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Headers `context` adds to a request.
+    fn applied_headers(context: &HttpContext) -> HeaderMap {
+        let request = context
+            .apply(Client::new().get("https://example.com/stream"))
+            .build()
+            .unwrap();
+        request.headers().clone()
+    }
+
+    #[test]
+    fn contexts_carry_per_playback_headers() {
+        let plain = HttpContext::new(ReconnectPolicy::default(), None, None).unwrap();
+        assert!(plain.profile.is_none());
+        assert!(applied_headers(&plain).is_empty());
+
+        let options = HttpRequestOptions {
+            headers: vec![header("X-Token", "a"), header("X-Token", "b")],
+            auth: Some(HttpAuth::Bearer {
+                token: "secret".to_string(),
+            }),
+            cookies: vec![cookie("session", "1"), cookie("theme", "dark")],
+        };
+        let context = HttpContext::new(
+            ReconnectPolicy::default(),
+            Some("cellular".to_string()),
+            Some(&options),
+        )
+        .unwrap();
+        assert_eq!(context.profile.as_deref(), Some("cellular"));
+        let headers = applied_headers(&context);
+        let tokens: Vec<_> = headers.get_all("x-token").iter().collect();
+        assert_eq!(tokens, ["a", "b"]);
+        assert_eq!(headers[COOKIE], "session=1; theme=dark");
+        assert_eq!(headers[AUTHORIZATION], "Bearer secret");
+
+        for headers in [
+            vec![header("bad name", "a")],
+            vec![header("X-Ok", "line\nbreak")],
+        ] {
+            let options = HttpRequestOptions {
+                headers,
+                auth: None,
+                cookies: Vec::new(),
+            };
+            assert!(matches!(
+                HttpContext::new(ReconnectPolicy::default(), None, Some(&options)),
+                Err(RodioError::InvalidHeader(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn resolves_network_profiles() {
+        let name = "resolves-network-profiles";
+        let defaults = http_options_snapshot(None).unwrap();
+        assert!(matches!(
+            http_options_snapshot(Some(name)),
+            Err(RodioError::NetworkProfileNotFound(_))
+        ));
+        assert!(http_client(Some(name)).is_err());
+
+        http_create_profile(name.to_string()).unwrap();
+        http_set_user_agent("profile-agent".to_string(), Some(name.to_string())).unwrap();
+        assert_eq!(
+            http_options_snapshot(Some(name)).unwrap().user_agent,
+            "profile-agent"
+        );
+        // The global defaults, used when a player names no profile, are untouched.
+        assert_eq!(
+            http_options_snapshot(None).unwrap().user_agent,
+            defaults.user_agent
+        );
+        assert!(http_client(Some(name)).is_ok());
+
+        http_remove_profile(name.to_string()).unwrap();
+        assert!(http_ensure_profile(name).is_err());
+    }
+}
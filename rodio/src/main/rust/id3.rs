@@ -0,0 +1,297 @@
+//! ID3v2 tags carried as HLS timed metadata.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+
+const HEADER_LEN: usize = 10;
+/// Holds the MPEG-TS timestamp of packed audio; it times segments, not songs.
+const TRANSPORT_STREAM_TIMESTAMP_OWNER: &str = "com.apple.streaming.transportStreamTimestamp";
+
+/// Length of the ID3v2 tag at the start of `data`, footer included.
+pub fn tag_len(data: &[u8]) -> Option<usize> {
+    declared_len(data).map(|len| len.min(data.len()))
+}
+
+/// Length the tag header at the start of `data` declares, which may run past `data`.
+pub fn declared_len(data: &[u8]) -> Option<usize> {
+    if data.len() < HEADER_LEN || !data.starts_with(b"ID3") {
+        return None;
+    }
+    let footer = if data[5] & 0x10 != 0 { HEADER_LEN } else { 0 };
+    Some(HEADER_LEN + syncsafe(&data[6..10]) + footer)
+}
+
+/// Key/value pairs for the frames of one tag: text frames under their frame ID,
+/// user-defined frames as `TXXX:<description>`, private frames as `PRIV:<owner>`
+/// with base64 data. Binary frames such as pictures are skipped.
+pub fn parse_tag(data: &[u8]) -> Vec<(String, String)> {
+    let Some(len) = tag_len(data) else {
+        return Vec::new();
+    };
+    let version = data[3];
+    let flags = data[5];
+    if !(3..=4).contains(&version) {
+        return Vec::new();
+    }
+    let mut body = data[HEADER_LEN..len].to_vec();
+    if version == 3 && flags & 0x80 != 0 {
+        body = remove_unsynchronisation(&body);
+    }
+    let mut offset = 0;
+    if flags & 0x40 != 0 && body.len() >= 4 {
+        offset = if version == 4 {
+            syncsafe(&body[..4])
+        } else {
+            4 + u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize
+        };
+    }
+
+    let mut entries = Vec::new();
+    while offset + HEADER_LEN <= body.len() {
+        let header = &body[offset..offset + HEADER_LEN];
+        if header[0] == 0 {
+            // Padding.
+            break;
+        }
+        let id = String::from_utf8_lossy(&header[..4]).into_owned();
+        let size = if version == 4 {
+            syncsafe(&header[4..8])
+        } else {
+            u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize
+        };
+        let frame_flags = header[9];
+        let start = offset + HEADER_LEN;
+        let end = (start + size).min(body.len());
+        offset = end;
+        let mut frame = &body[start..end];
+        let unsynchronised;
+        if version == 4 {
+            // Compressed or encrypted frames cannot be read.
+            if frame_flags & 0x0C != 0 {
+                continue;
+            }
+            if frame_flags & 0x01 != 0 {
+                frame = frame.get(4..).unwrap_or_default();
+            }
+            if frame_flags & 0x02 != 0 {
+                unsynchronised = remove_unsynchronisation(frame);
+                frame = &unsynchronised;
+            }
+        } else if frame_flags & 0xC0 != 0 {
+            continue;
+        }
+        if let Some(entry) = parse_frame(&id, frame) {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+fn parse_frame(id: &str, frame: &[u8]) -> Option<(String, String)> {
+    match id {
+        "TXXX" | "WXXX" => {
+            let (&encoding, rest) = frame.split_first()?;
+            let (description, value) = split_terminated(encoding, rest);
+            let description = decode_text(encoding, description);
+            let value = if id == "WXXX" {
+                decode_text(0, value)
+            } else {
+                join_values(encoding, value)
+            };
+            let key = if description.is_empty() {
+                id.to_string()
+            } else {
+                format!("{id}:{description}")
+            };
+            non_empty(key, value)
+        }
+        "COMM" => {
+            let (&encoding, rest) = frame.split_first()?;
+            let (_, text) = split_terminated(encoding, rest.get(3..)?);
+            non_empty(id.to_string(), decode_text(encoding, text))
+        }
+        "PRIV" => {
+            let end = frame.iter().position(|&byte| byte == 0)?;
+            let owner = decode_text(0, &frame[..end]);
+            if owner == TRANSPORT_STREAM_TIMESTAMP_OWNER {
+                return None;
+            }
+            non_empty(format!("PRIV:{owner}"), BASE64.encode(&frame[end + 1..]))
+        }
+        _ if id.starts_with('T') => {
+            let (&encoding, rest) = frame.split_first()?;
+            non_empty(id.to_string(), join_values(encoding, rest))
+        }
+        _ if id.starts_with('W') => non_empty(id.to_string(), decode_text(0, frame)),
+        _ => None,
+    }
+}
+
+fn non_empty(key: String, value: String) -> Option<(String, String)> {
+    (!value.is_empty()).then_some((key, value))
+}
+
+/// ID3v2.4 separates multiple values with the terminator; they are joined with `/`
+/// as ID3v2.3 does.
+fn join_values(encoding: u8, data: &[u8]) -> String {
+    let mut values = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (value, tail) = split_terminated(encoding, rest);
+        let value = decode_text(encoding, value);
+        if !value.is_empty() {
+            values.push(value);
+        }
+        rest = tail;
+    }
+    values.join("/")
+}
+
+/// Splits at the first string terminator for `encoding`: one zero byte, or two
+/// aligned zero bytes for UTF-16.
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    let end = if matches!(encoding, 1 | 2) {
+        (0..data.len() / 2)
+            .map(|i| i * 2)
+            .find(|&i| data[i] == 0 && data[i + 1] == 0)
+            .map(|i| (i, i + 2))
+    } else {
+        data.iter().position(|&byte| byte == 0).map(|i| (i, i + 1))
+    };
+    match end {
+        Some((end, next)) => (&data[..end], &data[next..]),
+        None => (data, &[]),
+    }
+}
+
+fn decode_text(encoding: u8, data: &[u8]) -> String {
+    let text = match encoding {
+        0 => data.iter().map(|&byte| char::from(byte)).collect(),
+        1 | 2 => {
+            let (big_endian, data) = match data {
+                [0xFE, 0xFF, rest @ ..] => (true, rest),
+                [0xFF, 0xFE, rest @ ..] => (false, rest),
+                _ => (encoding == 2, data),
+            };
+            let units: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|unit| {
+                    if big_endian {
+                        u16::from_be_bytes([unit[0], unit[1]])
+                    } else {
+                        u16::from_le_bytes([unit[0], unit[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(data).into_owned(),
+    };
+    text.trim_matches('\0').trim().to_string()
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0usize, |size, &byte| (size << 7) | usize::from(byte & 0x7F))
+}
+
+/// Drops the zero byte inserted after every 0xFF.
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut previous = 0u8;
+    for &byte in data {
+        if !(previous == 0xFF && byte == 0x00) {
+            out.push(byte);
+        }
+        previous = byte;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syncsafe_bytes(len: usize) -> [u8; 4] {
+        [21, 14, 7, 0].map(|shift| ((len >> shift) & 0x7F) as u8)
+    }
+
+    /// An ID3v2 tag of `version` holding `frames` as `(id, body)`.
+    fn tag(version: u8, frames: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (id, frame) in frames {
+            body.extend_from_slice(id.as_bytes());
+            if version == 4 {
+                body.extend_from_slice(&syncsafe_bytes(frame.len()));
+            } else {
+                body.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            }
+            body.extend_from_slice(&[0, 0]);
+            body.extend_from_slice(frame);
+        }
+        let mut data = b"ID3".to_vec();
+        data.extend_from_slice(&[version, 0, 0]);
+        data.extend_from_slice(&syncsafe_bytes(body.len()));
+        data.extend_from_slice(&body);
+        data
+    }
+
+    #[test]
+    fn parses_text_user_and_private_frames() {
+        let data = tag(
+            4,
+            &[
+                ("TIT2", b"\x03Song"),
+                ("TPE1", b"\x03One\0Two"),
+                ("TXXX", b"\x03mood\0calm"),
+                ("PRIV", b"owner\0\x01\x02"),
+                ("APIC", b"\x00image/png\0\x03\0\x89PNG"),
+            ],
+        );
+        assert_eq!(tag_len(&data), Some(data.len()));
+        assert_eq!(
+            parse_tag(&data),
+            [
+                ("TIT2", "Song"),
+                ("TPE1", "One/Two"),
+                ("TXXX:mood", "calm"),
+                ("PRIV:owner", "AQI="),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn decodes_utf16_text_in_v3_tags() {
+        let mut frame = vec![1, 0xFF, 0xFE];
+        frame.extend("Été".encode_utf16().flat_map(u16::to_le_bytes));
+        let data = tag(3, &[("TALB", &frame)]);
+        assert_eq!(parse_tag(&data), [("TALB".to_string(), "Été".to_string())]);
+    }
+
+    #[test]
+    fn skips_the_transport_stream_timestamp() {
+        let mut frame = TRANSPORT_STREAM_TIMESTAMP_OWNER.as_bytes().to_vec();
+        frame.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(parse_tag(&tag(4, &[("PRIV", &frame)])).is_empty());
+    }
+
+    #[test]
+    fn malformed_tags_do_not_panic() {
+        let data = tag(4, &[("TIT2", b"\x03Song"), ("TXXX", b"\x01\xFF")]);
+        for len in 0..data.len() {
+            parse_tag(&data[..len]);
+        }
+        // Frame and extended header sizes far past the end of the tag.
+        let mut oversized = tag(4, &[("TIT2", b"\x03Song")]);
+        oversized[14..18].copy_from_slice(&[0x7F; 4]);
+        assert!(parse_tag(&oversized).len() <= 1);
+        let mut extended = tag(3, &[("TIT2", b"\x00Song")]);
+        extended[5] = 0x40;
+        parse_tag(&extended);
+        assert_eq!(parse_tag(b"ID3\x02\0\0\0\0\0\0"), Vec::new());
+        assert_eq!(tag_len(b"ID3"), None);
+        assert_eq!(tag_len(b"not a tag at all"), None);
+    }
+}
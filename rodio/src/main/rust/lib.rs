@@ -1,27 +1,63 @@
 //! Rodio Kotlin bindings via UniFFI.
 
+mod abr;
+mod cue;
+mod dash;
+mod decrypt;
 mod error;
+mod hls;
+mod http;
+mod id3;
+mod llhls;
+mod mirror;
+mod nowplaying;
+mod ogg;
+mod pcm;
+mod playlist;
+mod prefetch;
+mod reconnect;
+mod section;
 mod state;
+mod tls;
+mod ts;
+mod wallclock;
 
 use std::fs::File;
 use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
-use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use hls_m3u8::tags::VariantStream;
-use hls_m3u8::{MasterPlaylist, MediaPlaylist};
-use reqwest::blocking::{Client, ClientBuilder, Response};
-use reqwest::header::{HeaderMap, CONTENT_TYPE, USER_AGENT};
-use reqwest::Certificate;
+use reqwest::blocking::Response;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
 
 use rodio::decoder::Decoder;
 use rodio::source::SineWave;
 use rodio::Source;
 
+pub use abr::HlsVariant;
+use cue::{spawn_cue_dispatcher, ByteCues, DecodedCues, MetadataEntries};
+use dash::DashStreamReader;
 pub use error::RodioError;
+pub use hls::{HlsLiveStatus, HlsRendition};
+use hls::{HlsPlayback, HlsSeeker, HlsStreamReader};
+use section::{SectionedStream, StreamSection};
+pub use http::{HttpAuth, HttpCookie, HttpHeader, HttpRequestOptions, IpFamily};
+use http::{download_bytes, http_ensure_profile, request_stream, HttpContext};
+use mirror::Mirrors;
+use nowplaying::parse_bitrate;
+pub use nowplaying::NowPlaying;
+use ogg::OggCommentReader;
+use pcm::{spawn_decoder_worker, PlaybackClock, SourceFeed};
+pub use playlist::PlaylistEntry;
+use playlist::{load_playlist, parse_playlist_body, PlaylistBase};
+pub use reconnect::ReconnectPolicy;
+use reconnect::{is_retryable_error, sleep_unless_cancelled};
 use state::{register, unregister, with_player, with_player_mut, PlayerState};
 
 const MAX_SEEKABLE_HTTP_BYTES: u64 = 25 * 1024 * 1024; // 25MB cap to avoid buffering huge streams
+/// Sections in a row that no decoder can open before a segmented stream gives up.
+const MAX_UNDECODABLE_SECTIONS: u32 = 3;
 
 #[derive(Clone, Copy, Debug, uniffi::Enum)]
 pub enum PlaybackEvent {
@@ -29,12 +65,18 @@ pub enum PlaybackEvent {
     Playing,
     Paused,
     Stopped,
+    Reconnecting,
+    Reconnected,
+    /// A playlist mirror failed and the next one is being tried.
+    MirrorFallback,
 }
 
 #[uniffi::export(callback_interface)]
 pub trait PlaybackCallback: Send + Sync {
     fn on_event(&self, event: PlaybackEvent);
     fn on_metadata(&self, key: String, value: String);
+    /// Called with the merged now-playing model whenever metadata changes it.
+    fn on_now_playing(&self, now_playing: NowPlaying);
     fn on_error(&self, message: String);
 }
 
@@ -50,63 +92,79 @@ fn notify_error(callback: &Option<Arc<dyn PlaybackCallback>>, error: &RodioError
     }
 }
 
-fn reset_sink(state: &mut PlayerState) {
-    state.sink.clear();
-    state.sink.play();
-}
-
-#[derive(Clone, Default)]
-struct HttpOptions {
-    allow_invalid_certs: bool,
-    extra_roots: Vec<Certificate>,
-}
-
-static HTTP_OPTIONS: OnceLock<Mutex<HttpOptions>> = OnceLock::new();
-
-fn http_options() -> &'static Mutex<HttpOptions> {
-    HTTP_OPTIONS.get_or_init(|| Mutex::new(HttpOptions::default()))
+/// Reports stream metadata both raw, one key at a time, and merged into the
+/// player's now-playing model.
+fn notify_metadata(id: u64, entries: MetadataEntries) {
+    // The callback may be set or replaced after playback started.
+    let Ok((callback, changed)) = with_player_mut(id, |state| {
+        let changed = state.now_playing.apply(&entries);
+        Ok((state.callback.clone(), changed.then(|| state.now_playing.clone())))
+    }) else {
+        return;
+    };
+    let Some(callback) = callback else {
+        return;
+    };
+    for (key, value) in entries {
+        callback.on_metadata(key, value);
+    }
+    if let Some(now_playing) = changed {
+        callback.on_now_playing(now_playing);
+    }
 }
 
-fn http_options_snapshot() -> Result<(bool, Vec<Certificate>), RodioError> {
-    let guard = http_options()
-        .lock()
-        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
-    Ok((guard.allow_invalid_certs, guard.extra_roots.clone()))
+/// Forgets the previous source's metadata when a new one is started.
+fn reset_now_playing(id: u64) -> Result<(), RodioError> {
+    with_player_mut(id, |state| {
+        state.now_playing = NowPlaying::default();
+        Ok(())
+    })
 }
 
-fn apply_http_options(
-    mut builder: ClientBuilder,
-    allow_invalid: bool,
-    extra_roots: &[Certificate],
-) -> ClientBuilder {
-    if allow_invalid {
-        builder = builder.danger_accept_invalid_certs(true);
-    }
-    for cert in extra_roots {
-        builder = builder.add_root_certificate(cert.clone());
-    }
-    builder
+fn reset_sink(state: &mut PlayerState) {
+    state.sink.clear();
+    state.sink.play();
+    state.hls = None;
+    state.position_offset = Duration::ZERO;
 }
 
 fn player_callback(id: u64) -> Result<Option<Arc<dyn PlaybackCallback>>, RodioError> {
     with_player(id, |state| Ok(state.callback.clone()))
 }
 
+fn player_http_context(
+    id: u64,
+    options: Option<&HttpRequestOptions>,
+) -> Result<HttpContext, RodioError> {
+    let (reconnect, profile) =
+        with_player(id, |state| Ok((state.reconnect, state.network_profile.clone())))?;
+    HttpContext::new(reconnect, profile, options)
+}
+
 struct IcyMetadataReader<R: Read> {
     inner: R,
     meta_interval: Option<usize>,
     remaining_until_meta: usize,
-    callback: Option<Arc<dyn PlaybackCallback>>,
+    /// Metadata blocks by audio byte offset, delivered when the audio after them plays.
+    cues: Option<ByteCues>,
+    /// Audio bytes returned so far, metadata blocks excluded.
+    audio_pos: u64,
 }
 
 impl<R: Read> IcyMetadataReader<R> {
-    fn new(inner: R, meta_interval: Option<usize>, callback: Option<Arc<dyn PlaybackCallback>>) -> Self {
+    fn new(
+        inner: R,
+        meta_interval: Option<usize>,
+        cues: Option<DecodedCues>,
+        bitrate_kbps: Option<u32>,
+    ) -> Self {
         let interval = meta_interval.filter(|value| *value > 0);
         Self {
             inner,
             meta_interval: interval,
             remaining_until_meta: interval.unwrap_or(0),
-            callback,
+            cues: cues.map(|cues| ByteCues::new(cues, bitrate_kbps)),
+            audio_pos: 0,
         }
     }
 }
@@ -114,6 +172,9 @@ impl<R: Read> IcyMetadataReader<R> {
 impl<R: Read> Read for IcyMetadataReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if let Some(interval) = self.meta_interval {
+            if let Some(cues) = &mut self.cues {
+                cues.consumed(self.audio_pos);
+            }
             if self.remaining_until_meta == 0 {
                 let mut len_buf = [0u8; 1];
                 let read = self.inner.read(&mut len_buf)?;
@@ -124,10 +185,8 @@ impl<R: Read> Read for IcyMetadataReader<R> {
                 if meta_len > 0 {
                     let mut metadata = vec![0u8; meta_len];
                     self.inner.read_exact(&mut metadata)?;
-                    for (key, value) in parse_icy_metadata_block(&metadata) {
-                        if let Some(callback) = &self.callback {
-                            callback.on_metadata(key, value);
-                        }
+                    if let Some(cues) = &mut self.cues {
+                        cues.push(self.audio_pos, parse_icy_metadata_block(&metadata));
                     }
                 }
                 self.remaining_until_meta = interval;
@@ -136,6 +195,7 @@ impl<R: Read> Read for IcyMetadataReader<R> {
             let to_read = buf.len().min(self.remaining_until_meta);
             let read = self.inner.read(&mut buf[..to_read])?;
             self.remaining_until_meta -= read;
+            self.audio_pos += read as u64;
             Ok(read)
         } else {
             self.inner.read(buf)
@@ -144,19 +204,27 @@ impl<R: Read> Read for IcyMetadataReader<R> {
 }
 
 struct StreamReader {
-    inner: Mutex<IcyMetadataReader<Response>>,
+    inner: Mutex<OggCommentReader<IcyMetadataReader<Response>>>,
     pos: u64,
+    /// Set when a read error, rather than the end of the body, stops the stream.
+    failed: Option<Arc<AtomicBool>>,
 }
 
 impl StreamReader {
     fn new(
         response: Response,
         meta_interval: Option<usize>,
-        callback: Option<Arc<dyn PlaybackCallback>>,
+        bitrate_kbps: Option<u32>,
+        cues: Option<DecodedCues>,
+        failed: Option<Arc<AtomicBool>>,
     ) -> Self {
         Self {
-            inner: Mutex::new(IcyMetadataReader::new(response, meta_interval, callback)),
+            inner: Mutex::new(OggCommentReader::new(
+                IcyMetadataReader::new(response, meta_interval, cues.clone(), bitrate_kbps),
+                cues,
+            )),
             pos: 0,
+            failed,
         }
     }
 }
@@ -166,8 +234,12 @@ impl Read for StreamReader {
         let mut guard = self
             .inner
             .lock()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "stream lock poisoned"))?;
-        let read = guard.read(buf)?;
+            .map_err(|_| io::Error::other("stream lock poisoned"))?;
+        let read = guard.read(buf).inspect_err(|_| {
+            if let Some(failed) = &self.failed {
+                failed.store(true, Ordering::Release);
+            }
+        })?;
         self.pos = self.pos.saturating_add(read as u64);
         Ok(read)
     }
@@ -180,267 +252,6 @@ impl Seek for StreamReader {
     }
 }
 
-struct HlsStreamReader {
-    playlist_url: reqwest::Url,
-    cached_playlist: Option<MediaPlaylist<'static>>,
-    next_sequence: Option<usize>,
-    current_response: Option<Response>,
-    ended: bool,
-    pos: u64,
-}
-
-impl HlsStreamReader {
-    fn new(url: &str) -> Result<(Self, Option<String>, Option<Duration>), RodioError> {
-        let playlist_url =
-            reqwest::Url::parse(url).map_err(|_| RodioError::InvalidUrl(url.to_string()))?;
-        let (playlist, resolved_url) = fetch_hls_media_playlist(&playlist_url)?;
-        let hint_url = first_hls_segment_url(&resolved_url, &playlist);
-        let total_duration = hls_total_duration(&playlist);
-        Ok((
-            Self {
-                playlist_url: resolved_url,
-                cached_playlist: Some(playlist),
-                next_sequence: None,
-                current_response: None,
-                ended: false,
-                pos: 0,
-            },
-            hint_url,
-            total_duration,
-        ))
-    }
-
-    fn load_playlist(&mut self) -> Result<(), RodioError> {
-        let (playlist, playlist_url) = fetch_hls_media_playlist(&self.playlist_url)?;
-        self.playlist_url = playlist_url;
-        self.cached_playlist = Some(playlist);
-        Ok(())
-    }
-
-    fn next_segment_url(&mut self) -> Result<Option<reqwest::Url>, RodioError> {
-        loop {
-            if self.ended {
-                return Ok(None);
-            }
-            if self.cached_playlist.is_none() {
-                self.load_playlist()?;
-            }
-            let (segment_count, target_duration, has_end_list, media_sequence) = {
-                let playlist = self.cached_playlist.as_ref().ok_or_else(|| {
-                    RodioError::Internal("hls playlist missing after load".to_string())
-                })?;
-                (
-                    playlist.segments.values().count(),
-                    playlist.target_duration,
-                    playlist.has_end_list,
-                    playlist.media_sequence,
-                )
-            };
-            if segment_count == 0 {
-                if has_end_list {
-                    self.ended = true;
-                    return Ok(None);
-                }
-                self.cached_playlist = None;
-                std::thread::sleep(hls_refresh_delay(target_duration));
-                continue;
-            }
-
-            let mut next_sequence = self.next_sequence.unwrap_or(media_sequence);
-            if next_sequence < media_sequence {
-                next_sequence = media_sequence;
-                self.next_sequence = Some(next_sequence);
-            }
-
-            let index = next_sequence - media_sequence;
-            if index >= segment_count {
-                if has_end_list {
-                    self.ended = true;
-                    return Ok(None);
-                }
-                self.cached_playlist = None;
-                std::thread::sleep(hls_refresh_delay(target_duration));
-                continue;
-            }
-
-            let segment = self
-                .cached_playlist
-                .as_ref()
-                .ok_or_else(|| RodioError::Internal("hls playlist missing after load".to_string()))?
-                .segments
-                .values()
-                .nth(index)
-                .ok_or_else(|| RodioError::Internal("hls segment lookup failed".to_string()))?;
-            validate_hls_segment(segment)?;
-            self.next_sequence = Some(next_sequence + 1);
-            let url = resolve_hls_url(&self.playlist_url, segment.uri().as_ref())?;
-            return Ok(Some(url));
-        }
-    }
-}
-
-impl Read for HlsStreamReader {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        loop {
-            if let Some(response) = &mut self.current_response {
-                let read = response.read(buf)?;
-                if read > 0 {
-                    self.pos = self.pos.saturating_add(read as u64);
-                    return Ok(read);
-                }
-                self.current_response = None;
-            }
-
-            if self.ended {
-                return Ok(0);
-            }
-
-            let next_url = self
-                .next_segment_url()
-                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
-            match next_url {
-                Some(url) => {
-                    let response = request_stream(url.as_str(), false)
-                        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
-                    self.current_response = Some(response);
-                }
-                None => {
-                    self.ended = true;
-                    return Ok(0);
-                }
-            }
-        }
-    }
-}
-
-impl Seek for HlsStreamReader {
-    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        let _ = pos;
-        Ok(self.pos)
-    }
-}
-
-fn validate_hls_segment(segment: &hls_m3u8::MediaSegment<'_>) -> Result<(), RodioError> {
-    if segment.map.is_some() {
-        return Err(RodioError::Playlist(
-            "hls init segments are not supported".to_string(),
-        ));
-    }
-    if segment.byte_range.is_some() {
-        return Err(RodioError::Playlist(
-            "hls byte-range segments are not supported".to_string(),
-        ));
-    }
-    if segment.keys.iter().any(|key| key.is_some()) {
-        return Err(RodioError::Playlist(
-            "hls encrypted segments are not supported".to_string(),
-        ));
-    }
-    Ok(())
-}
-
-fn hls_refresh_delay(target_duration: Duration) -> Duration {
-    let mut millis = target_duration.as_millis() as u64 / 2;
-    if millis < 500 {
-        millis = 500;
-    }
-    if millis > 2000 {
-        millis = 2000;
-    }
-    Duration::from_millis(millis)
-}
-
-fn hls_total_duration(playlist: &MediaPlaylist<'_>) -> Option<Duration> {
-    if !playlist.has_end_list {
-        return None;
-    }
-    let mut total = Duration::ZERO;
-    for segment in playlist.segments.values() {
-        total = total.saturating_add(segment.duration.duration());
-    }
-    Some(total)
-}
-
-fn first_hls_segment_url(
-    playlist_url: &reqwest::Url,
-    playlist: &MediaPlaylist<'_>,
-) -> Option<String> {
-    playlist
-        .segments
-        .values()
-        .next()
-        .and_then(|segment| resolve_hls_url(playlist_url, segment.uri().as_ref()).ok())
-        .map(|url| url.to_string())
-}
-
-fn parse_hls_media_playlist(body: &str) -> Result<MediaPlaylist<'static>, RodioError> {
-    MediaPlaylist::try_from(body)
-        .map(|playlist| playlist.into_owned())
-        .map_err(|err| RodioError::Playlist(format!("hls media playlist parse failed: {err}")))
-}
-
-fn resolve_hls_url(base_url: &reqwest::Url, candidate: &str) -> Result<reqwest::Url, RodioError> {
-    if let Ok(url) = reqwest::Url::parse(candidate) {
-        return Ok(url);
-    }
-    base_url
-        .join(candidate)
-        .map_err(|_| RodioError::InvalidUrl(candidate.to_string()))
-}
-
-fn select_hls_variant_url(
-    master: &MasterPlaylist<'_>,
-    base_url: &reqwest::Url,
-) -> Result<reqwest::Url, RodioError> {
-    let mut best: Option<(&VariantStream<'_>, u64)> = None;
-    for variant in &master.variant_streams {
-        let VariantStream::ExtXStreamInf { .. } = variant else {
-            continue;
-        };
-        let bandwidth = variant.bandwidth();
-        if best
-            .map(|(_, current)| bandwidth > current)
-            .unwrap_or(true)
-        {
-            best = Some((variant, bandwidth));
-        }
-    }
-    let variant = best
-        .map(|(variant, _)| variant)
-        .ok_or_else(|| {
-            RodioError::Playlist("hls master playlist has no stream variants".to_string())
-        })?;
-    let uri = match variant {
-        VariantStream::ExtXStreamInf { uri, .. } => uri.as_ref(),
-        VariantStream::ExtXIFrame { .. } => {
-            return Err(RodioError::Playlist(
-                "hls master playlist has no playable stream variants".to_string(),
-            ));
-        }
-    };
-    resolve_hls_url(base_url, uri)
-}
-
-fn fetch_hls_media_playlist(
-    url: &reqwest::Url,
-) -> Result<(MediaPlaylist<'static>, reqwest::Url), RodioError> {
-    let response = request_stream(url.as_str(), false)?;
-    let body = response.text()?;
-
-    if let Ok(media) = parse_hls_media_playlist(&body) {
-        return Ok((media, url.clone()));
-    }
-
-    let master = MasterPlaylist::try_from(body.as_str())
-        .map(|playlist| playlist.into_owned())
-        .map_err(|err| RodioError::Playlist(format!("hls master playlist parse failed: {err}")))?;
-    let variant_url = select_hls_variant_url(&master, url)?;
-    let response = request_stream(variant_url.as_str(), false)?;
-    let body = response.text()?;
-    let media = parse_hls_media_playlist(&body)?;
-    Ok((media, variant_url))
-}
-
 fn parse_icy_metadata_block(bytes: &[u8]) -> Vec<(String, String)> {
     let text = String::from_utf8_lossy(bytes);
     let trimmed = text.trim_matches('\0').trim();
@@ -521,6 +332,10 @@ fn response_content_type(response: &Response) -> Option<String> {
         .map(|value| value.split(';').next().unwrap_or(value).trim().to_lowercase())
 }
 
+fn has_icy_headers(headers: &HeaderMap) -> bool {
+    headers.keys().any(|name| name.as_str().starts_with("icy-"))
+}
+
 fn icy_metaint(headers: &HeaderMap) -> Option<usize> {
     headers
         .get("icy-metaint")
@@ -532,7 +347,7 @@ fn icy_metaint(headers: &HeaderMap) -> Option<usize> {
 fn url_extension(url: &str) -> Option<String> {
     reqwest::Url::parse(url)
         .ok()
-        .and_then(|u| u.path_segments()?.last().map(|seg| seg.to_lowercase()))
+        .and_then(|u| u.path_segments()?.next_back().map(|seg| seg.to_lowercase()))
         .and_then(|seg| seg.rsplit('.').next().map(|ext| ext.to_string()))
 }
 
@@ -560,11 +375,11 @@ fn is_seekable_http_format(url: &str, content_type: Option<&str>) -> bool {
             return true;
         }
     }
-    match url_extension(url).as_deref() {
+    matches!(
+        url_extension(url).as_deref(),
         Some("mp3") | Some("aac") | Some("m4a") | Some("mp4") | Some("flac") | Some("ogg")
-        | Some("opus") | Some("wav") | Some("aiff") | Some("aif") => true,
-        _ => false,
-    }
+            | Some("opus") | Some("wav") | Some("aiff") | Some("aif")
+    )
 }
 
 fn hint_from_mime(mime: &str) -> Option<&'static str> {
@@ -587,7 +402,7 @@ fn hint_from_url(url: &str) -> Option<&'static str> {
     match ext.to_lowercase().as_str() {
         "mp3" => Some("mp3"),
         "aac" | "aacp" => Some("aac"),
-        "m4a" | "mp4" => Some("mp4"),
+        "m4a" | "mp4" | "m4s" => Some("mp4"),
         "ogg" | "oga" => Some("ogg"),
         "flac" => Some("flac"),
         "wav" => Some("wav"),
@@ -595,38 +410,11 @@ fn hint_from_url(url: &str) -> Option<&'static str> {
     }
 }
 
-fn http_client() -> Result<Client, RodioError> {
-    let (allow_invalid, extra_roots) = http_options_snapshot()?;
-    let builder = apply_http_options(Client::builder(), allow_invalid, &extra_roots);
-    match builder.build() {
-        Ok(client) => Ok(client),
-        Err(_) => {
-            let builder = Client::builder()
-                .tls_built_in_native_certs(false)
-                .tls_built_in_webpki_certs(true);
-            let builder = apply_http_options(builder, allow_invalid, &extra_roots);
-            Ok(builder.build()?)
-        }
-    }
-}
-
-fn request_stream(url: &str, want_metadata: bool) -> Result<Response, RodioError> {
-    let client = http_client()?;
-    let mut request = client.get(url).header(USER_AGENT, "RodioKt/1.0");
-    if want_metadata {
-        request = request.header("Icy-MetaData", "1");
-    }
-    let response = request.send()?;
-    if !response.status().is_success() {
-        return Err(RodioError::HttpStatus(response.status().as_u16()));
+fn is_dash_manifest(url: &str, content_type: Option<&str>) -> bool {
+    if url.to_lowercase().ends_with(".mpd") {
+        return true;
     }
-    Ok(response)
-}
-
-fn download_bytes(url: &str) -> Result<Vec<u8>, RodioError> {
-    let response = request_stream(url, false)?;
-    let bytes = response.bytes()?;
-    Ok(bytes.to_vec())
+    content_type.is_some_and(|content_type| content_type.to_lowercase().contains("dash+xml"))
 }
 
 fn is_hls_playlist(url: &str, content_type: Option<&str>) -> bool {
@@ -644,44 +432,35 @@ fn is_hls_playlist(url: &str, content_type: Option<&str>) -> bool {
 
 fn is_playlist(url: &str, content_type: Option<&str>) -> bool {
     let url = url.to_lowercase();
-    if url.ends_with(".m3u") || url.ends_with(".m3u8") || url.ends_with(".pls") {
+    if url.ends_with(".m3u")
+        || url.ends_with(".m3u8")
+        || url.ends_with(".pls")
+        || url.ends_with(".xspf")
+        || url.ends_with(".asx")
+    {
         return true;
     }
     if let Some(content_type) = content_type {
         return content_type.contains("mpegurl")
             || content_type.contains("x-mpegurl")
             || content_type.contains("scpls")
-            || content_type.contains("playlist");
+            || content_type.contains("playlist")
+            || content_type.contains("xspf")
+            || content_type.contains("x-ms-asf");
     }
     false
 }
 
-fn resolve_playlist(base_url: &str, body: &str) -> Option<String> {
-    let base = reqwest::Url::parse(base_url).ok();
-    for line in body.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-        let candidate = if let Some((key, value)) = line.split_once('=') {
-            if key.trim().to_lowercase().starts_with("file") {
-                value.trim()
-            } else {
-                continue;
-            }
-        } else {
-            line
-        };
-        if candidate.starts_with("http://") || candidate.starts_with("https://") {
-            return Some(candidate.to_string());
-        }
-        if let Some(base) = &base {
-            if let Ok(joined) = base.join(candidate) {
-                return Some(joined.to_string());
-            }
-        }
-    }
-    None
+/// Stream URLs of a radio playlist, in playlist order; entries are usually
+/// mirrors of one stream.
+fn resolve_playlist_mirrors(base_url: &str, body: &str) -> Vec<String> {
+    let Ok(base) = reqwest::Url::parse(base_url) else {
+        return Vec::new();
+    };
+    parse_playlist_body(body, &PlaylistBase::Url(base))
+        .into_iter()
+        .map(|entry| entry.location)
+        .collect()
 }
 
 fn build_stream_decoder(
@@ -702,11 +481,195 @@ fn build_stream_decoder(
     Ok(builder.build()?)
 }
 
-fn build_hls_decoder(
-    reader: HlsStreamReader,
+/// Plays the entries of a local playlist one after another, skipping those
+/// that cannot be opened; `looped` starts over after the last one.
+struct PlaylistFeed {
+    locations: Vec<String>,
+    next: usize,
+    looped: bool,
+    callback: Option<Arc<dyn PlaybackCallback>>,
+    http: HttpContext,
+}
+
+impl PlaylistFeed {
+    fn open(&self, location: &str) -> Result<Box<dyn Source + Send>, RodioError> {
+        let lower = location.to_lowercase();
+        if lower.starts_with("http://") || lower.starts_with("https://") {
+            let response = request_stream(location, false, &self.http)?;
+            return Ok(Box::new(open_stream_decoder(response, location, None, None)?));
+        }
+        Ok(Box::new(open_file_decoder(location)?))
+    }
+}
+
+impl SourceFeed for PlaylistFeed {
+    fn next_source(&mut self, cancelled: &AtomicBool) -> Option<Box<dyn Source + Send>> {
+        // At most one pass over the playlist, so a playlist of broken entries ends.
+        for _ in 0..self.locations.len() {
+            if cancelled.load(Ordering::Acquire) {
+                return None;
+            }
+            if self.next == self.locations.len() {
+                if !self.looped {
+                    return None;
+                }
+                self.next = 0;
+            }
+            let location = &self.locations[self.next];
+            self.next += 1;
+            match self.open(location) {
+                Ok(source) => return Some(source),
+                Err(error) => notify_error(&self.callback, &error),
+            }
+        }
+        None
+    }
+}
+
+fn open_stream_decoder(
+    response: Response,
+    url: &str,
+    cues: Option<&DecodedCues>,
+    failed: Option<&Arc<AtomicBool>>,
+) -> Result<Decoder<StreamReader>, RodioError> {
+    let content_type = response_content_type(&response);
+    let meta_interval = icy_metaint(response.headers());
+    let bitrate_kbps =
+        header_value(response.headers(), "icy-br").and_then(|value| parse_bitrate(&value));
+    let reader = StreamReader::new(
+        response,
+        meta_interval,
+        bitrate_kbps,
+        cues.cloned(),
+        failed.cloned(),
+    );
+    build_stream_decoder(reader, content_type.as_deref(), url)
+}
+
+/// Reopens a live stream on the same sink after the connection drops.
+struct LiveStreamFeed {
+    url: String,
+    want_metadata: bool,
+    callback: Option<Arc<dyn PlaybackCallback>>,
+    http: HttpContext,
+    /// Mirrors of the playlist the stream came from, tried in turn on reconnect.
+    mirrors: Option<Mirrors>,
+    cues: DecodedCues,
+    /// ICY servers end the body cleanly when they drop a listener, so any end
+    /// of an ICY stream is reconnected; other streams only after a read error.
+    icy: bool,
+    failed: Arc<AtomicBool>,
+}
+
+impl LiveStreamFeed {
+    fn reopen(&mut self) -> Result<Decoder<StreamReader>, RodioError> {
+        let mut single_attempt = self.http.clone();
+        single_attempt.reconnect.max_attempts = 0;
+        let response = match &self.mirrors {
+            Some(mirrors) => {
+                let callback = &self.callback;
+                let (response, url) =
+                    mirrors.connect(self.want_metadata, &single_attempt, |error, next| {
+                        notify_mirror_fallback(callback, error, next)
+                    })?;
+                self.url = url;
+                response
+            }
+            None => request_stream(&self.url, self.want_metadata, &single_attempt)?,
+        };
+        open_stream_decoder(response, &self.url, Some(&self.cues), Some(&self.failed))
+    }
+}
+
+/// Reports a failed mirror and the one tried next.
+fn notify_mirror_fallback(
+    callback: &Option<Arc<dyn PlaybackCallback>>,
+    error: &RodioError,
+    next: &str,
+) {
+    notify_error(callback, error);
+    notify_event(callback, PlaybackEvent::MirrorFallback);
+    if let Some(callback) = callback {
+        callback.on_metadata("mirror-url".to_string(), next.to_string());
+    }
+}
+
+impl SourceFeed for LiveStreamFeed {
+    fn next_source(&mut self, cancelled: &AtomicBool) -> Option<Box<dyn Source + Send>> {
+        let reconnect = self.http.reconnect;
+        let failed = self.failed.swap(false, Ordering::AcqRel);
+        if !(self.icy || failed)
+            || reconnect.max_attempts == 0
+            || cancelled.load(Ordering::Acquire)
+        {
+            return None;
+        }
+        notify_event(&self.callback, PlaybackEvent::Reconnecting);
+        for attempt in 0..reconnect.max_attempts {
+            // The first retry is immediate so a clean drop only costs the buffered audio.
+            if attempt > 0 && !sleep_unless_cancelled(reconnect.delay(attempt - 1), cancelled) {
+                return None;
+            }
+            match self.reopen() {
+                Ok(decoder) => {
+                    notify_event(&self.callback, PlaybackEvent::Reconnected);
+                    return Some(Box::new(decoder));
+                }
+                Err(error) if is_retryable_error(&error) => {
+                    if attempt + 1 == reconnect.max_attempts {
+                        notify_error(&self.callback, &error);
+                    }
+                }
+                Err(error) => {
+                    notify_error(&self.callback, &error);
+                    return None;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Decodes a segmented stream section by section, so a codec or sample-rate
+/// change at an HLS discontinuity or a DASH period gets a decoder of its own.
+struct SectionFeed<R> {
+    section: StreamSection<R>,
+    hint_url: Option<String>,
+}
+
+impl<R: SectionedStream + 'static> SourceFeed for SectionFeed<R> {
+    fn next_source(&mut self, cancelled: &AtomicBool) -> Option<Box<dyn Source + Send>> {
+        // A section the decoder cannot open is skipped, up to a few in a row.
+        for _ in 0..MAX_UNDECODABLE_SECTIONS {
+            if cancelled.load(Ordering::Acquire) {
+                return None;
+            }
+            let section = self.section.next_section()?;
+            if let Ok(decoder) = build_section_decoder(section, self.hint_url.as_deref()) {
+                return Some(Box::new(decoder));
+            }
+        }
+        None
+    }
+}
+
+type SectionDecoder<R> = Decoder<StreamSection<R>>;
+
+/// Decoder for the first section of `reader`, and the feed for the ones after it.
+fn open_section_decoder<R: SectionedStream + 'static>(
+    reader: R,
+    hint_url: Option<String>,
+) -> Result<(SectionDecoder<R>, Box<dyn SourceFeed>), RodioError> {
+    let section = StreamSection::new(reader);
+    let decoder = build_section_decoder(section.clone(), hint_url.as_deref())?;
+    Ok((decoder, Box::new(SectionFeed { section, hint_url })))
+}
+
+fn build_section_decoder<R: SectionedStream + 'static>(
+    section: StreamSection<R>,
     hint_url: Option<&str>,
-) -> Result<Decoder<HlsStreamReader>, RodioError> {
-    let mut builder = Decoder::builder().with_data(reader).with_seekable(false);
+) -> Result<SectionDecoder<R>, RodioError> {
+    let mut builder = Decoder::builder().with_data(section).with_seekable(false);
     if let Some(hint) = hint_url.and_then(hint_from_url) {
         builder = builder.with_hint(hint);
     }
@@ -745,14 +708,149 @@ fn play_seekable_http_bytes(
     })
 }
 
-fn play_hls_stream(id: u64, url: &str) -> Result<(), RodioError> {
-    let (reader, hint_url, total_duration) = HlsStreamReader::new(url)?;
-    let decoder = build_hls_decoder(reader, hint_url.as_deref())?;
+fn play_hls_stream(id: u64, url: &str, http: &HttpContext) -> Result<(), RodioError> {
+    let (max_bandwidth, live_edge_segments, target_latency, prefetch_segments) =
+        with_player(id, |state| {
+            Ok((
+                state.hls_max_bandwidth,
+                state.hls_live_edge_segments,
+                state.hls_target_latency,
+                state.hls_prefetch_segments,
+            ))
+        })?;
+    let (reader, hint_url, total_duration) = HlsStreamReader::new(
+        url,
+        http.clone(),
+        max_bandwidth,
+        live_edge_segments as usize,
+        target_latency,
+        prefetch_segments as usize,
+    )?;
+    let playback = reader.playback();
+    let cancelled = reader.cancelled();
+    let (decoder, feed) = open_section_decoder(reader, hint_url)?;
+    play_buffered_stream(
+        id,
+        decoder,
+        total_duration,
+        Some(feed),
+        cancelled,
+        Some(playback),
+        None,
+    )
+}
+
+fn play_dash_stream(id: u64, url: &str, http: &HttpContext) -> Result<(), RodioError> {
+    let (live_edge_segments, prefetch_segments) = with_player(id, |state| {
+        Ok((state.hls_live_edge_segments, state.hls_prefetch_segments))
+    })?;
+    let (reader, total_duration) = DashStreamReader::new(
+        url,
+        http.clone(),
+        live_edge_segments as usize,
+        prefetch_segments as usize,
+    )?;
+    let hint_url = reader.hint_url();
+    let (decoder, feed) = open_section_decoder(reader, hint_url)?;
+    play_buffered_stream(
+        id,
+        decoder,
+        total_duration,
+        Some(feed),
+        http.cancelled.clone(),
+        None,
+        None,
+    )
+}
+
+/// Replaces the playing HLS source with `reader`, skipping the first `skip` of
+/// decoded audio. `position` is the reported position afterwards; `None` keeps
+/// counting from the current one, as live streams do.
+fn restart_hls_stream(
+    id: u64,
+    reader: HlsStreamReader,
+    skip: Duration,
+    position: Option<Duration>,
+) -> Result<(), RodioError> {
+    let playback = reader.playback();
+    let cancelled = reader.cancelled();
+    let (decoder, feed) = open_section_decoder(reader, playback.seeker.hint_url().map(String::from))?;
+    let source = spawn_decoder_worker(
+        decoder.skip_duration(skip),
+        Some(feed),
+        cancelled,
+        "rodio-kt-stream",
+    )?;
+    playback.session.attach_buffer(source.buffer_level());
+    spawn_hls_metadata(id, &playback, source.clock(), skip)?;
+    source.wait_for_prebuffer();
     with_player_mut(id, |state| {
+        let paused = state.sink.is_paused();
+        let position = position.unwrap_or(state.position_offset + state.sink.get_pos());
+        let live = playback.seeker.is_live();
         reset_sink(state);
-        state.current_duration = total_duration;
-        state.seekable = false;
-        state.sink.append(decoder);
+        state.current_duration = (!live).then(|| playback.seeker.total_duration());
+        state.seekable = !live;
+        state.hls = Some(playback);
+        state.position_offset = position;
+        state.sink.append(source);
+        if paused {
+            state.sink.pause();
+        }
+        Ok(())
+    })
+}
+
+/// Reports the stream's timed ID3 metadata as its audio plays, and dates it by
+/// the same clock. `start` is the stream time at which `clock` started counting.
+fn spawn_hls_metadata(
+    id: u64,
+    playback: &HlsPlayback,
+    clock: PlaybackClock,
+    start: Duration,
+) -> Result<(), RodioError> {
+    playback.dates.attach_clock(clock.clone(), start);
+    spawn_cue_dispatcher(playback.cues.clone(), clock, start, move |entries| {
+        notify_metadata(id, entries)
+    })
+}
+
+/// Reports in-band ICY metadata when the audio that follows it is played.
+fn spawn_icy_metadata(id: u64, cues: &DecodedCues, clock: PlaybackClock) -> Result<(), RodioError> {
+    cues.attach_clock(clock.clone());
+    spawn_cue_dispatcher(cues.cues.clone(), clock, Duration::ZERO, move |entries| {
+        notify_metadata(id, entries)
+    })
+}
+
+fn play_buffered_stream<S>(
+    id: u64,
+    source: S,
+    duration: Option<Duration>,
+    feed: Option<Box<dyn SourceFeed>>,
+    cancelled: Arc<AtomicBool>,
+    hls: Option<HlsPlayback>,
+    icy: Option<DecodedCues>,
+) -> Result<(), RodioError>
+where
+    S: Source + Send + 'static,
+{
+    // Network reads and decoding happen on the worker; the sink only copies samples.
+    let source = spawn_decoder_worker(source, feed, cancelled, "rodio-kt-stream")?;
+    if let Some(icy) = &icy {
+        spawn_icy_metadata(id, icy, source.clock())?;
+    }
+    if let Some(hls) = &hls {
+        hls.session.attach_buffer(source.buffer_level());
+        spawn_hls_metadata(id, hls, source.clock(), Duration::ZERO)?;
+    }
+    source.wait_for_prebuffer();
+    with_player_mut(id, |state| {
+        reset_sink(state);
+        state.current_duration = duration;
+        state.seekable = hls.as_ref().is_some_and(|hls| !hls.seeker.is_live());
+        state.hls = hls;
+        state.sink.append(source);
         Ok(())
     })
 }
@@ -793,19 +891,56 @@ pub fn player_clear_callback(id: u64) -> Result<(), RodioError> {
     })
 }
 
+fn open_file_decoder(path: &str) -> Result<Decoder<File>, RodioError> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut builder = Decoder::builder()
+        .with_data(file)
+        .with_byte_len(len);
+    if let Some(hint) = hint_from_url(path) {
+        builder = builder.with_hint(hint);
+    }
+    Ok(builder.build()?)
+}
+
+/// Plays a local playlist file from its first playable entry.
+fn play_playlist_file(
+    id: u64,
+    path: &str,
+    looped: bool,
+    callback: &Option<Arc<dyn PlaybackCallback>>,
+) -> Result<(), RodioError> {
+    let http = player_http_context(id, None)?;
+    let entries = load_playlist(path, &http)?;
+    // Durations listed in the playlist, when every entry has one.
+    let duration = entries
+        .iter()
+        .map(|entry| entry.duration_ms.map(Duration::from_millis))
+        .sum::<Option<Duration>>()
+        .filter(|_| !looped);
+    let cancelled = http.cancelled.clone();
+    let mut feed = PlaylistFeed {
+        locations: entries.into_iter().map(|entry| entry.location).collect(),
+        next: 0,
+        looped,
+        callback: callback.clone(),
+        http,
+    };
+    let first = feed
+        .next_source(&AtomicBool::new(false))
+        .ok_or_else(|| RodioError::Playlist("playlist has no playable entries".to_string()))?;
+    play_buffered_stream(id, first, duration, Some(Box::new(feed)), cancelled, None, None)
+}
+
 #[uniffi::export]
 pub fn player_play_file(id: u64, path: String, looped: bool) -> Result<(), RodioError> {
     let callback = player_callback(id)?;
     let result = (|| {
-        let file = File::open(&path)?;
-        let len = file.metadata()?.len();
-        let mut builder = Decoder::builder()
-            .with_data(file)
-            .with_byte_len(len);
-        if let Some(hint) = hint_from_url(&path) {
-            builder = builder.with_hint(hint);
+        reset_now_playing(id)?;
+        if is_playlist(&path, None) {
+            return play_playlist_file(id, &path, looped, &callback);
         }
-        let mut decoder = builder.build()?;
+        let mut decoder = open_file_decoder(&path)?;
         let seekable = decoder.try_seek(Duration::from_millis(0)).is_ok();
         let duration = if looped {
             None
@@ -850,6 +985,7 @@ pub fn player_play_sine(
     let duration = Duration::from_millis(duration_ms);
     let result = with_player_mut(id, |state| {
         reset_sink(state);
+        state.now_playing = NowPlaying::default();
         state.current_duration = Some(duration);
         state.seekable = false;
         let source = SineWave::new(frequency_hz)
@@ -865,18 +1001,30 @@ pub fn player_play_sine(
     result
 }
 
-#[uniffi::export]
-pub fn player_play_url(id: u64, url: String, looped: bool) -> Result<(), RodioError> {
+#[uniffi::export(default(options = None))]
+pub fn player_play_url(
+    id: u64,
+    url: String,
+    looped: bool,
+    options: Option<HttpRequestOptions>,
+) -> Result<(), RodioError> {
     let callback = player_callback(id)?;
     notify_event(&callback, PlaybackEvent::Connecting);
     let result = (|| {
+        reset_now_playing(id)?;
+        let http = player_http_context(id, options.as_ref())?;
         if looped {
             if is_hls_playlist(&url, None) {
                 return Err(RodioError::Playlist(
                     "hls looped playback is not supported".to_string(),
                 ));
             }
-            let bytes = download_bytes(&url)?;
+            if is_dash_manifest(&url, None) {
+                return Err(RodioError::Playlist(
+                    "dash looped playback is not supported".to_string(),
+                ));
+            }
+            let bytes = download_bytes(&url, &http)?;
             let cursor = Cursor::new(bytes);
             let decoder = Decoder::new_looped(cursor)?;
             return with_player_mut(id, |state| {
@@ -888,13 +1036,19 @@ pub fn player_play_url(id: u64, url: String, looped: bool) -> Result<(), RodioEr
         }
 
         if is_hls_playlist(&url, None) {
-            return play_hls_stream(id, &url);
+            return play_hls_stream(id, &url, &http);
+        }
+        if is_dash_manifest(&url, None) {
+            return play_dash_stream(id, &url, &http);
         }
 
-        let response = request_stream(&url, false)?;
+        let response = request_stream(&url, false, &http)?;
         let content_type = response_content_type(&response);
         if is_hls_playlist(&url, content_type.as_deref()) {
-            return play_hls_stream(id, &url);
+            return play_hls_stream(id, &url, &http);
+        }
+        if is_dash_manifest(&url, content_type.as_deref()) {
+            return play_dash_stream(id, &url, &http);
         }
         if let Some(len) = response.content_length() {
             if len > 0
@@ -905,17 +1059,28 @@ pub fn player_play_url(id: u64, url: String, looped: bool) -> Result<(), RodioEr
                 return play_seekable_http_bytes(id, &url, bytes.to_vec(), content_type.as_deref());
             }
         }
-        let meta_interval = icy_metaint(response.headers());
-        let reader = StreamReader::new(response, meta_interval, callback.clone());
-        let decoder = build_stream_decoder(reader, content_type.as_deref(), &url)?;
+        // A body without a length is a live stream that may be cut off; ICY
+        // streams also reconnect when the server ends it cleanly.
+        let live = response.content_length().is_none();
+        let icy = has_icy_headers(response.headers());
+        let cues = DecodedCues::default();
+        let failed = Arc::new(AtomicBool::new(false));
+        let decoder = open_stream_decoder(response, &url, Some(&cues), Some(&failed))?;
         let duration = decoder.total_duration();
-        with_player_mut(id, |state| {
-            reset_sink(state);
-            state.current_duration = duration;
-            state.seekable = false;
-            state.sink.append(decoder);
-            Ok(())
-        })
+        let cancelled = http.cancelled.clone();
+        let feed = live.then(|| {
+            Box::new(LiveStreamFeed {
+                url: url.clone(),
+                want_metadata: false,
+                callback: callback.clone(),
+                http,
+                mirrors: None,
+                cues: cues.clone(),
+                icy,
+                failed,
+            }) as Box<dyn SourceFeed>
+        });
+        play_buffered_stream(id, decoder, duration, feed, cancelled, None, Some(cues))
     })();
     if let Err(error) = &result {
         notify_error(&callback, error);
@@ -925,90 +1090,81 @@ pub fn player_play_url(id: u64, url: String, looped: bool) -> Result<(), RodioEr
     result
 }
 
-#[uniffi::export]
-pub fn http_set_allow_invalid_certs(allow: bool) -> Result<(), RodioError> {
-    let mut guard = http_options()
-        .lock()
-        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
-    guard.allow_invalid_certs = allow;
-    Ok(())
-}
-
-#[uniffi::export]
-pub fn http_add_root_cert_pem(pem: String) -> Result<(), RodioError> {
-    let certs = Certificate::from_pem_bundle(pem.as_bytes())?;
-    let mut guard = http_options()
-        .lock()
-        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
-    guard.extra_roots.extend(certs);
-    Ok(())
-}
-
-#[uniffi::export]
-pub fn http_clear_root_certs() -> Result<(), RodioError> {
-    let mut guard = http_options()
-        .lock()
-        .map_err(|_| RodioError::Internal("http options lock failed".to_string()))?;
-    guard.extra_roots.clear();
-    Ok(())
-}
-
-#[uniffi::export]
-pub fn player_play_radio(id: u64, url: String) -> Result<(), RodioError> {
+#[uniffi::export(default(options = None))]
+pub fn player_play_radio(
+    id: u64,
+    url: String,
+    options: Option<HttpRequestOptions>,
+) -> Result<(), RodioError> {
     let callback = player_callback(id)?;
     notify_event(&callback, PlaybackEvent::Connecting);
     let result = (|| {
+        reset_now_playing(id)?;
+        let http = player_http_context(id, options.as_ref())?;
         if is_hls_playlist(&url, None) {
-            return play_hls_stream(id, &url);
+            return play_hls_stream(id, &url, &http);
         }
 
-        let mut response = request_stream(&url, true)?;
+        let mut response = request_stream(&url, true, &http)?;
         let mut content_type = response_content_type(&response);
         let mut final_url = url.clone();
 
         if is_hls_playlist(&final_url, content_type.as_deref()) {
-            return play_hls_stream(id, &final_url);
+            return play_hls_stream(id, &final_url, &http);
         }
 
+        let mut mirrors = None;
         if is_playlist(&url, content_type.as_deref()) {
             let body = response.text()?;
-            let stream_url = resolve_playlist(&url, &body)
-                .ok_or_else(|| RodioError::Playlist("playlist did not contain a stream url".to_string()))?;
-            if is_hls_playlist(&stream_url, None) {
-                return play_hls_stream(id, &stream_url);
+            let stream_urls = resolve_playlist_mirrors(&url, &body);
+            if stream_urls.is_empty() {
+                return Err(RodioError::Playlist(
+                    "playlist did not contain a stream url".to_string(),
+                ));
+            }
+            if is_hls_playlist(&stream_urls[0], None) {
+                return play_hls_stream(id, &stream_urls[0], &http);
             }
-            response = request_stream(&stream_url, true)?;
+            let timeout = with_player(id, |state| Ok(state.mirror_timeout))?;
+            let playlist_mirrors = Mirrors::new(url.clone(), stream_urls, timeout);
+            let (stream_response, stream_url) =
+                playlist_mirrors.connect(true, &http, |error, next| {
+                    notify_mirror_fallback(&callback, error, next)
+                })?;
+            response = stream_response;
             content_type = response_content_type(&response);
             final_url = stream_url;
             if is_hls_playlist(&final_url, content_type.as_deref()) {
-                return play_hls_stream(id, &final_url);
-            }
-        }
-
-        if let Some(callback) = &callback {
-            let headers = response.headers();
-            for (key, header) in [
-                ("icy-name", "icy-name"),
-                ("icy-description", "icy-description"),
-                ("icy-genre", "icy-genre"),
-            ] {
-                if let Some(value) = header_value(headers, header) {
-                    callback.on_metadata(key.to_string(), value);
-                }
+                return play_hls_stream(id, &final_url, &http);
             }
+            mirrors = Some(playlist_mirrors);
         }
 
-        let meta_interval = icy_metaint(response.headers());
-        let reader = StreamReader::new(response, meta_interval, callback.clone());
-        let decoder = build_stream_decoder(reader, content_type.as_deref(), &final_url)?;
+        let headers = response.headers();
+        let station = ["icy-name", "icy-description", "icy-genre", "icy-br", "icy-url"]
+            .into_iter()
+            .filter_map(|header| Some((header.to_string(), header_value(headers, header)?)))
+            .collect();
+        notify_metadata(id, station);
+
+        let icy = has_icy_headers(response.headers());
+        let cues = DecodedCues::default();
+        let failed = Arc::new(AtomicBool::new(false));
+        let decoder = open_stream_decoder(response, &final_url, Some(&cues), Some(&failed))?;
         let duration = decoder.total_duration();
-        with_player_mut(id, |state| {
-            reset_sink(state);
-            state.current_duration = duration;
-            state.seekable = false;
-            state.sink.append(decoder);
-            Ok(())
-        })
+        let cancelled = http.cancelled.clone();
+        let feed = LiveStreamFeed {
+            url: final_url,
+            want_metadata: true,
+            callback: callback.clone(),
+            http,
+            mirrors,
+            cues: cues.clone(),
+            icy,
+            failed,
+        };
+        let feed = Some(Box::new(feed) as Box<dyn SourceFeed>);
+        play_buffered_stream(id, decoder, duration, feed, cancelled, None, Some(cues))
     })();
     if let Err(error) = &result {
         notify_error(&callback, error);
@@ -1018,6 +1174,220 @@ pub fn player_play_radio(id: u64, url: String) -> Result<(), RodioError> {
     result
 }
 
+#[uniffi::export]
+pub fn player_set_reconnect_policy(id: u64, policy: ReconnectPolicy) -> Result<(), RodioError> {
+    with_player_mut(id, |state| {
+        state.reconnect = policy;
+        Ok(())
+    })
+}
+
+/// How long each mirror of a radio playlist may take to answer before the next is tried.
+#[uniffi::export]
+pub fn player_set_mirror_timeout_ms(id: u64, timeout_ms: u64) -> Result<(), RodioError> {
+    if timeout_ms == 0 {
+        return Err(RodioError::InvalidDuration(timeout_ms));
+    }
+    with_player_mut(id, |state| {
+        state.mirror_timeout = Duration::from_millis(timeout_ms);
+        Ok(())
+    })
+}
+
+/// Uses the named network profile for this player's requests; `None` restores the global defaults.
+#[uniffi::export]
+pub fn player_set_network_profile(id: u64, profile: Option<String>) -> Result<(), RodioError> {
+    if let Some(name) = &profile {
+        http_ensure_profile(name)?;
+    }
+    with_player_mut(id, |state| {
+        state.network_profile = profile;
+        Ok(())
+    })
+}
+
+/// Variants of the HLS master playlist being played; empty for other sources.
+#[uniffi::export]
+pub fn player_hls_get_variants(id: u64) -> Result<Vec<HlsVariant>, RodioError> {
+    with_player(id, |state| {
+        Ok(state
+            .hls
+            .as_ref()
+            .map(|hls| hls.session.variants())
+            .unwrap_or_default())
+    })
+}
+
+/// Index of the variant the next HLS segment is fetched from.
+#[uniffi::export]
+pub fn player_hls_get_current_variant(id: u64) -> Result<Option<u32>, RodioError> {
+    with_player(id, |state| {
+        Ok(state.hls.as_ref().and_then(|hls| hls.session.current()))
+    })
+}
+
+/// Pins the current HLS stream to a variant from `player_hls_get_variants`;
+/// `None` returns to automatic selection. Takes effect at the next segment.
+#[uniffi::export]
+pub fn player_hls_select_variant(id: u64, index: Option<u32>) -> Result<(), RodioError> {
+    with_player(id, |state| match (&state.hls, index) {
+        (Some(hls), index) => hls.session.select(index),
+        (None, Some(index)) => Err(RodioError::HlsVariantNotFound(index)),
+        (None, None) => Ok(()),
+    })
+}
+
+/// Caps automatic HLS variant selection, in bits per second, for the current
+/// and future streams of this player; `None` removes the cap.
+#[uniffi::export]
+pub fn player_hls_set_max_bandwidth(id: u64, max_bandwidth: Option<u64>) -> Result<(), RodioError> {
+    with_player_mut(id, |state| {
+        state.hls_max_bandwidth = max_bandwidth;
+        if let Some(hls) = &state.hls {
+            hls.session.set_max_bandwidth(max_bandwidth);
+        }
+        Ok(())
+    })
+}
+
+/// Audio renditions (`EXT-X-MEDIA`) of the HLS master playlist being played;
+/// empty for other sources.
+#[uniffi::export]
+pub fn player_hls_get_renditions(id: u64) -> Result<Vec<HlsRendition>, RodioError> {
+    with_player(id, |state| {
+        Ok(state
+            .hls
+            .as_ref()
+            .map(|hls| hls.renditions.renditions())
+            .unwrap_or_default())
+    })
+}
+
+/// Audio rendition played with the current variant, if it belongs to a group.
+#[uniffi::export]
+pub fn player_hls_get_current_rendition(id: u64) -> Result<Option<HlsRendition>, RodioError> {
+    with_player(id, |state| {
+        Ok(state.hls.as_ref().and_then(|hls| {
+            let variant = hls.session.current()?;
+            hls.renditions.current(variant as usize)
+        }))
+    })
+}
+
+/// Switches the current HLS stream to the audio rendition `name` of `group_id`.
+/// Variants of other audio groups play their rendition of the same name or
+/// language. Playback restarts at the current position, or the current live latency.
+#[uniffi::export]
+pub fn player_hls_select_rendition(
+    id: u64,
+    group_id: String,
+    name: String,
+) -> Result<(), RodioError> {
+    let (hls, position) = with_player(id, |state| {
+        let hls = state.hls.clone().ok_or_else(|| {
+            RodioError::HlsRenditionNotFound(format!("{name} in group {group_id}"))
+        })?;
+        Ok((hls, state.position_offset + state.sink.get_pos()))
+    })?;
+    hls.renditions.select(&group_id, &name)?;
+    if hls.seeker.is_live() {
+        let buffered = hls.session.buffered().unwrap_or_default();
+        let latency = hls.seeker.unfetched_duration() + buffered;
+        let (reader, skip) = hls.seeker.reader_behind_live(latency);
+        return restart_hls_stream(id, reader, skip, None);
+    }
+    let position = position.min(hls.seeker.total_duration());
+    let (reader, segment_start) = hls.seeker.reader_at(position);
+    restart_hls_stream(id, reader, position - segment_start, Some(position))
+}
+
+fn live_hls_seeker(id: u64) -> Result<Arc<HlsSeeker>, RodioError> {
+    with_player(id, |state| {
+        state
+            .hls
+            .as_ref()
+            .map(|hls| hls.seeker.clone())
+            .filter(|seeker| seeker.is_live())
+            .ok_or_else(|| RodioError::Seek("source is not a live hls stream".to_string()))
+    })
+}
+
+/// Window and latency of the live HLS stream being played; `None` for other sources.
+#[uniffi::export]
+pub fn player_hls_get_live_status(id: u64) -> Result<Option<HlsLiveStatus>, RodioError> {
+    with_player(id, |state| {
+        Ok(state
+            .hls
+            .as_ref()
+            .filter(|hls| hls.seeker.is_live())
+            .map(|hls| {
+                let buffered = hls.session.buffered().unwrap_or_default();
+                HlsLiveStatus {
+                    window_ms: duration_to_millis(hls.seeker.total_duration()),
+                    latency_ms: duration_to_millis(hls.seeker.unfetched_duration() + buffered),
+                    partial_segments: hls.seeker.reads_parts(),
+                }
+            }))
+    })
+}
+
+/// Time-shifts a live HLS stream to `behind_live_ms` behind the live edge,
+/// clamped to the playlist window.
+#[uniffi::export]
+pub fn player_hls_seek_live(id: u64, behind_live_ms: u64) -> Result<(), RodioError> {
+    let seeker = live_hls_seeker(id)?;
+    let (reader, skip) = seeker.reader_behind_live(Duration::from_millis(behind_live_ms));
+    restart_hls_stream(id, reader, skip, None)
+}
+
+/// Returns a time-shifted live HLS stream to its default distance from the live edge.
+#[uniffi::export]
+pub fn player_hls_go_live(id: u64) -> Result<(), RodioError> {
+    let seeker = live_hls_seeker(id)?;
+    let (edge_segments, target_latency) = with_player(id, |state| {
+        Ok((state.hls_live_edge_segments, state.hls_target_latency))
+    })?;
+    let reader = seeker.reader_at_live_edge(edge_segments as usize, target_latency);
+    restart_hls_stream(id, reader, Duration::ZERO, None)
+}
+
+/// How many segments before the live edge live HLS and DASH streams start (3 by
+/// default, as the HLS spec recommends; a DASH manifest's `suggestedPresentationDelay`
+/// takes precedence). Applies to streams started afterwards and to `player_hls_go_live`.
+#[uniffi::export]
+pub fn player_hls_set_live_edge_segments(id: u64, segments: u32) -> Result<(), RodioError> {
+    with_player_mut(id, |state| {
+        state.hls_live_edge_segments = segments;
+        Ok(())
+    })
+}
+
+/// How far behind the live edge low-latency HLS streams (`EXT-X-PART`) play;
+/// `None` uses the playlist's `PART-HOLD-BACK`, which is also the closest allowed.
+/// Applies to streams started afterwards and to `player_hls_go_live`.
+/// Encrypted low-latency streams play whole segments, so this has no effect on
+/// them; `HlsLiveStatus::partial_segments` tells whether parts are being read.
+#[uniffi::export]
+pub fn player_hls_set_target_latency_ms(
+    id: u64,
+    latency_ms: Option<u64>,
+) -> Result<(), RodioError> {
+    with_player_mut(id, |state| {
+        state.hls_target_latency = latency_ms.map(Duration::from_millis);
+        Ok(())
+    })
+}
+
+/// How many upcoming HLS and DASH segments download in the background while one
+/// plays (2 by default; 0 disables prefetching). Applies to streams started afterwards.
+#[uniffi::export]
+pub fn player_hls_set_prefetch_segments(id: u64, segments: u32) -> Result<(), RodioError> {
+    with_player_mut(id, |state| {
+        state.hls_prefetch_segments = segments;
+        Ok(())
+    })
+}
+
 #[uniffi::export]
 pub fn player_play(id: u64) -> Result<(), RodioError> {
     let callback = with_player(id, |state| {
@@ -1074,12 +1444,34 @@ pub fn player_is_empty(id: u64) -> Result<bool, RodioError> {
 
 #[uniffi::export]
 pub fn player_get_position_ms(id: u64) -> Result<u64, RodioError> {
-    with_player(id, |state| Ok(duration_to_millis(state.sink.get_pos())))
+    with_player(id, |state| {
+        Ok(duration_to_millis(state.position_offset + state.sink.get_pos()))
+    })
+}
+
+/// Real-world time, in milliseconds since the Unix epoch, of the audio being
+/// played, from the HLS stream's `EXT-X-PROGRAM-DATE-TIME`; `None` when the
+/// source is not dated.
+#[uniffi::export]
+pub fn player_get_wall_clock_ms(id: u64) -> Result<Option<u64>, RodioError> {
+    with_player(id, |state| Ok(state.hls.as_ref().and_then(|hls| hls.dates.now_ms())))
 }
 
 #[uniffi::export]
 pub fn player_seek_position_ms(id: u64, position_ms: u64) -> Result<(), RodioError> {
     let target = Duration::from_millis(position_ms);
+    let seeker = with_player(id, |state| {
+        Ok(state
+            .hls
+            .as_ref()
+            .map(|hls| hls.seeker.clone())
+            .filter(|seeker| !seeker.is_live()))
+    })?;
+    if let Some(seeker) = seeker {
+        let clamped = target.min(seeker.total_duration());
+        let (reader, segment_start) = seeker.reader_at(clamped);
+        return restart_hls_stream(id, reader, clamped - segment_start, Some(clamped));
+    }
     with_player_mut(id, |state| {
         let duration = state
             .current_duration
@@ -1098,6 +1490,12 @@ pub fn player_get_duration_ms(id: u64) -> Result<Option<u64>, RodioError> {
     with_player(id, |state| Ok(state.current_duration.map(duration_to_millis)))
 }
 
+/// The current source's metadata so far, as last passed to `on_now_playing`.
+#[uniffi::export]
+pub fn player_get_now_playing(id: u64) -> Result<NowPlaying, RodioError> {
+    with_player(id, |state| Ok(state.now_playing.clone()))
+}
+
 #[uniffi::export]
 pub fn player_is_seekable(id: u64) -> Result<bool, RodioError> {
     with_player(id, |state| Ok(state.seekable))
@@ -0,0 +1,403 @@
+//! Low-Latency HLS tags, which the playlist parser only keeps as unknown tags:
+//! partial segments (`EXT-X-PART`), preload hints and server control.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::wallclock::parse_date_time;
+
+/// Newest protocol version the playlist parser accepts; later ones only add
+/// tags it ignores anyway.
+const MAX_PARSED_VERSION: u32 = 7;
+
+/// Segment tags that need a URI after them; for the segment still being
+/// produced they have none yet and would fail the parser.
+const SEGMENT_TAGS: [&str; 7] = [
+    "#EXTINF",
+    "#EXT-X-BYTERANGE",
+    "#EXT-X-DISCONTINUITY",
+    "#EXT-X-KEY",
+    "#EXT-X-MAP",
+    "#EXT-X-PROGRAM-DATE-TIME",
+    "#EXT-X-DATERANGE",
+];
+
+/// `EXT-X-SERVER-CONTROL`.
+#[derive(Clone, Default)]
+pub struct ServerControl {
+    pub can_block_reload: bool,
+    pub hold_back: Option<Duration>,
+    pub part_hold_back: Option<Duration>,
+}
+
+/// One `EXT-X-PART` of the segment with media sequence number `sequence`.
+#[derive(Clone)]
+pub struct PartialSegment {
+    pub sequence: usize,
+    pub index: usize,
+    pub duration: Duration,
+    pub uri: String,
+    pub byte_range: Option<Range<u64>>,
+    pub independent: bool,
+    /// `GAP=YES`: the part has no media and is skipped.
+    pub gap: bool,
+    /// Set on the first part of a segment that follows `EXT-X-DISCONTINUITY`.
+    pub discontinuity: bool,
+    /// `EXT-X-PROGRAM-DATE-TIME` of the segment, on its first part.
+    pub date: Option<u64>,
+}
+
+/// `EXT-X-PRELOAD-HINT` for the part the server will publish next.
+#[derive(Clone)]
+pub struct PreloadHint {
+    pub sequence: usize,
+    pub index: usize,
+    pub uri: String,
+}
+
+#[derive(Clone, Default)]
+pub struct LowLatency {
+    pub server_control: ServerControl,
+    pub part_target: Option<Duration>,
+    pub parts: Vec<PartialSegment>,
+    pub preload_hint: Option<PreloadHint>,
+    /// Sequence number of the segment being produced, whose parts are listed
+    /// after the last complete segment.
+    pub next_sequence: usize,
+    /// Whether the latest `EXT-X-KEY` encrypts the media.
+    pub encrypted: bool,
+}
+
+impl LowLatency {
+    /// Whether the playlist lists partial segments to read instead of waiting
+    /// for whole ones. Encrypted parts are not read; such streams play whole segments.
+    pub fn has_parts(&self) -> bool {
+        self.part_target.is_some() && !self.parts.is_empty() && !self.encrypted
+    }
+
+    pub fn part(&self, sequence: usize, index: usize) -> Option<&PartialSegment> {
+        self.parts
+            .iter()
+            .find(|part| part.sequence == sequence && part.index == index)
+    }
+
+    pub fn parts_of(&self, sequence: usize) -> impl Iterator<Item = &PartialSegment> {
+        self.parts
+            .iter()
+            .filter(move |part| part.sequence == sequence)
+    }
+
+    /// How far behind the live edge the server says playback may start.
+    pub fn part_hold_back(&self) -> Option<Duration> {
+        self.server_control
+            .part_hold_back
+            .or_else(|| self.part_target.map(|target| target * 3))
+    }
+
+    /// `_HLS_msn` and `_HLS_part` of the next blocking reload: the part after
+    /// the last one listed, or the next segment when the playlist has no parts.
+    pub fn blocking_request(&self) -> Option<(usize, Option<usize>)> {
+        if !self.server_control.can_block_reload {
+            return None;
+        }
+        if !self.has_parts() {
+            return Some((self.next_sequence, None));
+        }
+        Some(match self.parts.last() {
+            Some(last) if last.sequence == self.next_sequence => {
+                (last.sequence, Some(last.index + 1))
+            }
+            _ => (self.next_sequence, Some(0)),
+        })
+    }
+}
+
+/// Adds the blocking reload query for `request` to a media playlist URL.
+pub fn blocking_reload_url(url: &reqwest::Url, request: (usize, Option<usize>)) -> reqwest::Url {
+    let mut url = url.clone();
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("_HLS_msn", &request.0.to_string());
+        if let Some(part) = request.1 {
+            query.append_pair("_HLS_part", &part.to_string());
+        }
+    }
+    url
+}
+
+/// Reads the Low-Latency HLS tags of a media playlist, and returns the body
+/// the playlist parser accepts: without the tags of the segment still being
+/// produced, and without a protocol version it does not know.
+pub fn parse_low_latency(body: &str) -> (String, LowLatency) {
+    let mut low_latency = LowLatency::default();
+    let mut media_sequence: usize = 0;
+    let mut segments = 0;
+    let mut part_index = 0;
+    let mut discontinuity = false;
+    let mut date = None;
+    // Parts without an offset continue the previous range of the same URI.
+    let mut range_ends: HashMap<String, u64> = HashMap::new();
+
+    let lines: Vec<&str> = body.lines().collect();
+    let last_uri = lines
+        .iter()
+        .rposition(|line| is_uri(line))
+        .map_or(0, |index| index + 1);
+    let mut cleaned = String::with_capacity(body.len());
+
+    for (index, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        let (tag, value) = line.split_once(':').unwrap_or((line, ""));
+        let mut keep = true;
+        match tag {
+            "#EXT-X-VERSION" => {
+                keep = value
+                    .trim()
+                    .parse::<u32>()
+                    .is_ok_and(|version| version <= MAX_PARSED_VERSION);
+            }
+            "#EXT-X-MEDIA-SEQUENCE" => {
+                media_sequence = value.trim().parse().unwrap_or(0);
+            }
+            "#EXT-X-DISCONTINUITY" => discontinuity = true,
+            "#EXT-X-KEY" => {
+                low_latency.encrypted = attributes(value)
+                    .get("METHOD")
+                    .is_some_and(|method| *method != "NONE");
+            }
+            "#EXT-X-PROGRAM-DATE-TIME" => date = parse_date_time(value),
+            "#EXT-X-SERVER-CONTROL" => {
+                let attributes = attributes(value);
+                let control = &mut low_latency.server_control;
+                control.can_block_reload = attributes.get("CAN-BLOCK-RELOAD") == Some(&"YES");
+                control.hold_back = attributes.get("HOLD-BACK").and_then(|v| seconds(v));
+                control.part_hold_back = attributes.get("PART-HOLD-BACK").and_then(|v| seconds(v));
+            }
+            "#EXT-X-PART-INF" => {
+                low_latency.part_target = attributes(value)
+                    .get("PART-TARGET")
+                    .and_then(|v| seconds(v));
+            }
+            "#EXT-X-PART" => {
+                let attributes = attributes(value);
+                let (Some(uri), Some(duration)) = (
+                    attributes.get("URI"),
+                    attributes.get("DURATION").and_then(|v| seconds(v)),
+                ) else {
+                    cleaned.push_str(line);
+                    cleaned.push('\n');
+                    continue;
+                };
+                let byte_range = attributes
+                    .get("BYTERANGE")
+                    .and_then(|range| byte_range(range, range_ends.get(*uri).copied()));
+                if let Some(range) = &byte_range {
+                    range_ends.insert(uri.to_string(), range.end);
+                }
+                low_latency.parts.push(PartialSegment {
+                    sequence: media_sequence.saturating_add(segments),
+                    index: part_index,
+                    duration,
+                    uri: uri.to_string(),
+                    byte_range,
+                    independent: attributes.get("INDEPENDENT") == Some(&"YES"),
+                    gap: attributes.get("GAP") == Some(&"YES"),
+                    discontinuity: part_index == 0 && discontinuity,
+                    date: if part_index == 0 { date } else { None },
+                });
+                part_index += 1;
+            }
+            "#EXT-X-PRELOAD-HINT" => {
+                let attributes = attributes(value);
+                if attributes.get("TYPE") == Some(&"PART")
+                    && !attributes.contains_key("BYTERANGE-START")
+                {
+                    low_latency.preload_hint = attributes.get("URI").map(|uri| PreloadHint {
+                        sequence: media_sequence.saturating_add(segments),
+                        index: part_index,
+                        uri: uri.to_string(),
+                    });
+                }
+            }
+            _ if is_uri(line) => {
+                segments += 1;
+                part_index = 0;
+                discontinuity = false;
+                date = None;
+            }
+            _ => {}
+        }
+        if index >= last_uri && SEGMENT_TAGS.contains(&tag) {
+            keep = false;
+        }
+        if keep {
+            cleaned.push_str(line);
+            cleaned.push('\n');
+        }
+    }
+    low_latency.next_sequence = media_sequence.saturating_add(segments);
+    (cleaned, low_latency)
+}
+
+fn is_uri(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && !line.starts_with('#')
+}
+
+/// Splits an attribute list, keeping commas inside quoted values.
+fn attributes(list: &str) -> HashMap<&str, &str> {
+    let mut attributes = HashMap::new();
+    let mut rest = list.trim();
+    while let Some((name, value)) = rest.split_once('=') {
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                let remainder = quoted.get(end + 1..).unwrap_or("");
+                (&quoted[..end], remainder)
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        attributes.insert(name.trim(), value.trim());
+        rest = remainder.trim_start_matches([',', ' ']);
+    }
+    attributes
+}
+
+fn seconds(value: &str) -> Option<Duration> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+/// `<length>[@<offset>]`, continuing from `previous_end` without an offset.
+fn byte_range(value: &str, previous_end: Option<u64>) -> Option<Range<u64>> {
+    let (length, offset) = match value.split_once('@') {
+        Some((length, offset)) => (length, Some(offset.parse().ok()?)),
+        None => (value, None),
+    };
+    let length: u64 = length.parse().ok()?;
+    let start = offset.or(previous_end).unwrap_or(0);
+    Some(start..start.checked_add(length)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use hls_m3u8::MediaPlaylist;
+
+    use super::*;
+
+    const PLAYLIST: &str = "#EXTM3U
+#EXT-X-VERSION:9
+#EXT-X-TARGETDURATION:4
+#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=3.0,HOLD-BACK=12
+#EXT-X-PART-INF:PART-TARGET=1.0
+#EXT-X-MEDIA-SEQUENCE:100
+#EXT-X-PROGRAM-DATE-TIME:2024-02-29T12:00:00Z
+#EXT-X-PART:DURATION=1.0,URI=\"seg100.mp4\",BYTERANGE=1000@0,INDEPENDENT=YES
+#EXT-X-PART:DURATION=1.0,URI=\"seg100.mp4\",BYTERANGE=500
+#EXTINF:2.0,
+seg100.mp4
+#EXT-X-DISCONTINUITY
+#EXT-X-PROGRAM-DATE-TIME:2024-02-29T12:00:02Z
+#EXT-X-PART:DURATION=1.0,URI=\"part101.0.mp4\",INDEPENDENT=YES
+#EXT-X-PART:DURATION=1.0,URI=\"part101.1.mp4\",GAP=YES
+#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"part101.2.mp4\"
+";
+
+    #[test]
+    fn reads_parts_hints_and_server_control() {
+        let (cleaned, low_latency) = parse_low_latency(PLAYLIST);
+        let control = &low_latency.server_control;
+        assert!(control.can_block_reload);
+        assert_eq!(control.hold_back, Some(Duration::from_secs(12)));
+        assert_eq!(low_latency.part_hold_back(), Some(Duration::from_secs(3)));
+        assert_eq!(low_latency.part_target, Some(Duration::from_secs(1)));
+        assert_eq!(low_latency.next_sequence, 101);
+        assert!(low_latency.has_parts());
+
+        let first = low_latency.part(100, 0).unwrap();
+        assert_eq!(first.byte_range, Some(0..1000));
+        assert_eq!(first.date, Some(1_709_208_000_000));
+        assert!(first.independent && !first.discontinuity);
+        assert_eq!(
+            low_latency.part(100, 1).unwrap().byte_range,
+            Some(1000..1500)
+        );
+        let pending = low_latency.part(101, 0).unwrap();
+        assert!(pending.discontinuity && pending.byte_range.is_none());
+        assert_eq!(pending.date, Some(1_709_208_002_000));
+        assert!(low_latency.part(101, 1).unwrap().gap);
+        assert_eq!(low_latency.parts_of(101).count(), 2);
+
+        let hint = low_latency.preload_hint.as_ref().unwrap();
+        assert_eq!((hint.sequence, hint.index), (101, 2));
+        assert_eq!(low_latency.blocking_request(), Some((101, Some(2))));
+
+        // The pending segment's tags and the unknown version are left out.
+        assert!(!cleaned.contains("#EXT-X-VERSION"));
+        assert!(!cleaned.contains("#EXT-X-DISCONTINUITY"));
+        assert!(!cleaned.contains("12:00:02"));
+        assert!(MediaPlaylist::try_from(cleaned.as_str()).is_ok());
+    }
+
+    #[test]
+    fn blocks_on_the_next_segment_without_parts() {
+        let body = "#EXTM3U\n#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES\n\
+                    #EXT-X-MEDIA-SEQUENCE:7\n#EXTINF:4,\na.ts\n";
+        let (_, low_latency) = parse_low_latency(body);
+        assert!(!low_latency.has_parts());
+        assert_eq!(low_latency.blocking_request(), Some((8, None)));
+
+        let url = reqwest::Url::parse("https://example.com/live.m3u8?token=a").unwrap();
+        assert_eq!(
+            blocking_reload_url(&url, (8, Some(2))).as_str(),
+            "https://example.com/live.m3u8?token=a&_HLS_msn=8&_HLS_part=2"
+        );
+    }
+
+    #[test]
+    fn encrypted_parts_are_not_read() {
+        let body = PLAYLIST.replace(
+            "#EXT-X-MEDIA-SEQUENCE:100\n",
+            "#EXT-X-MEDIA-SEQUENCE:100\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\n",
+        );
+        assert!(!parse_low_latency(&body).1.has_parts());
+    }
+
+    #[test]
+    fn splits_attribute_lists() {
+        let list = attributes(r#"URI="a,b.mp4", DURATION=1.5,CODECS="mp4a.40.2,ac-3",X=""#);
+        assert_eq!(list.get("URI"), Some(&"a,b.mp4"));
+        assert_eq!(list.get("DURATION"), Some(&"1.5"));
+        assert_eq!(list.get("CODECS"), Some(&"mp4a.40.2,ac-3"));
+        assert_eq!(list.get("X"), Some(&""));
+        assert!(attributes("").is_empty());
+    }
+
+    #[test]
+    fn malformed_tags_do_not_panic() {
+        assert_eq!(byte_range("10@18446744073709551615", None), None);
+        assert_eq!(byte_range("18446744073709551615", Some(1)), None);
+        assert_eq!(byte_range("x@1", None), None);
+        assert_eq!(seconds("-1"), None);
+        assert_eq!(seconds("NaN"), None);
+        let body = "#EXT-X-PART:DURATION=1e300,URI=\"a\"
+#EXT-X-PART:DURATION=1,URI=\"a\",BYTERANGE=18446744073709551615@1
+#EXT-X-PART:URI=\"unterminated
+#EXT-X-MEDIA-SEQUENCE:18446744073709551615
+#EXT-X-PART:DURATION=1,URI=\"b\"
+b
+#EXT-X-PART:DURATION=1,URI=\"c\"
+#EXT-X-SERVER-CONTROL:=,==,\"
+#EXT-X-PRELOAD-HINT:TYPE=PART
+#EXT-X-VERSION:x
+";
+        let (_, low_latency) = parse_low_latency(body);
+        assert_eq!(low_latency.parts.len(), 3);
+        for len in 0..PLAYLIST.len() {
+            parse_low_latency(&PLAYLIST[..len]);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Failover across the entries of a radio playlist, which usually list the
+//! same stream on several servers.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use reqwest::blocking::Response;
+
+use crate::error::RodioError;
+use crate::http::{request_stream, HttpContext};
+
+/// How long a mirror may take to answer before the next one is tried.
+pub const DEFAULT_MIRROR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Last mirror that answered, keyed by playlist URL, for as long as the process runs.
+static WORKING_MIRRORS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+/// The stream URLs of one playlist, in playlist order.
+pub struct Mirrors {
+    playlist_url: String,
+    urls: Vec<String>,
+    timeout: Duration,
+}
+
+impl Mirrors {
+    pub fn new(playlist_url: String, urls: Vec<String>, timeout: Duration) -> Self {
+        Self {
+            playlist_url,
+            urls,
+            timeout,
+        }
+    }
+
+    /// Connects to the first mirror that answers, trying each once within the
+    /// timeout: the last one that worked first, then the rest in playlist
+    /// order. `on_fallback` gets each failure and the mirror tried next.
+    /// Returns the response and the URL of the mirror that answered.
+    pub fn connect<F>(
+        &self,
+        want_metadata: bool,
+        http: &HttpContext,
+        mut on_fallback: F,
+    ) -> Result<(Response, String), RodioError>
+    where
+        F: FnMut(&RodioError, &str),
+    {
+        let mut single_attempt = http.clone();
+        single_attempt.reconnect.max_attempts = 0;
+        let order = self.order();
+        let mut last_error = None;
+        for url in order {
+            if let Some(error) = &last_error {
+                on_fallback(error, url);
+            }
+            match connect_within(url, want_metadata, &single_attempt, self.timeout) {
+                Ok(response) => {
+                    remember(&self.playlist_url, url);
+                    return Ok((response, url.to_string()));
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            RodioError::Playlist("playlist did not contain a stream url".to_string())
+        }))
+    }
+
+    fn order(&self) -> Vec<&str> {
+        let working = working_mirror(&self.playlist_url);
+        let mut order: Vec<&str> = self.urls.iter().map(String::as_str).collect();
+        if let Some(index) = order
+            .iter()
+            .position(|url| Some(*url) == working.as_deref())
+        {
+            let url = order.remove(index);
+            order.insert(0, url);
+        }
+        order
+    }
+}
+
+/// Requests `url` on a separate thread so an unresponsive server only costs
+/// `timeout`; the stream itself is read without a deadline.
+fn connect_within(
+    url: &str,
+    want_metadata: bool,
+    http: &HttpContext,
+    timeout: Duration,
+) -> Result<Response, RodioError> {
+    let (sender, receiver) = mpsc::channel();
+    let request_url = url.to_string();
+    let http = http.clone();
+    thread::Builder::new()
+        .name("rodio-kt-mirror".to_string())
+        .spawn(move || {
+            let _ = sender.send(request_stream(&request_url, want_metadata, &http));
+        })?;
+    receiver.recv_timeout(timeout).unwrap_or_else(|_| {
+        Err(RodioError::Http(format!(
+            "{url} did not respond within {} ms",
+            timeout.as_millis()
+        )))
+    })
+}
+
+fn working_mirrors() -> std::sync::MutexGuard<'static, HashMap<String, String>> {
+    WORKING_MIRRORS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+fn working_mirror(playlist_url: &str) -> Option<String> {
+    working_mirrors().get(playlist_url).cloned()
+}
+
+fn remember(playlist_url: &str, url: &str) {
+    working_mirrors().insert(playlist_url.to_string(), url.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// A server that answers every request with a short body.
+    fn answering() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+            }
+        });
+        url
+    }
+
+    /// A server that accepts connections and never answers.
+    fn hanging() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let open: Vec<_> = listener.incoming().collect();
+            drop(open);
+        });
+        url
+    }
+
+    /// A port nothing listens on.
+    fn refusing() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/stream", listener.local_addr().unwrap())
+    }
+
+    fn mirrors(playlist: &str, urls: &[&str]) -> Mirrors {
+        let urls = urls.iter().map(|url| url.to_string()).collect();
+        Mirrors::new(playlist.to_string(), urls, Duration::from_millis(500))
+    }
+
+    /// Connects, returning the mirror that answered and the mirrors fallen back to.
+    fn connect(mirrors: &Mirrors) -> (Result<String, RodioError>, Vec<String>) {
+        let mut fallbacks = Vec::new();
+        let result = mirrors
+            .connect(false, &HttpContext::default(), |_, next| {
+                fallbacks.push(next.to_string())
+            })
+            .map(|(_, url)| url);
+        (result, fallbacks)
+    }
+
+    #[test]
+    fn tries_the_working_mirror_first() {
+        let playlist = "https://example.com/order.pls";
+        let mirrors = mirrors(playlist, &["a", "b", "c"]);
+        assert_eq!(mirrors.order(), ["a", "b", "c"]);
+        remember(playlist, "c");
+        assert_eq!(mirrors.order(), ["c", "a", "b"]);
+        remember(playlist, "gone");
+        assert_eq!(mirrors.order(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn falls_back_in_playlist_order() {
+        let (dead, slow, live) = (refusing(), hanging(), answering());
+        let mirrors = mirrors("https://example.com/failover.pls", &[&dead, &slow, &live]);
+        let (result, fallbacks) = connect(&mirrors);
+        assert_eq!(result.unwrap(), live);
+        assert_eq!(fallbacks, [slow.clone(), live.clone()]);
+
+        // The mirror that answered is tried first from then on.
+        let (result, fallbacks) = connect(&mirrors);
+        assert_eq!(result.unwrap(), live);
+        assert!(fallbacks.is_empty());
+    }
+
+    #[test]
+    fn returns_to_the_primary_when_the_backup_fails() {
+        let playlist = "https://example.com/primary.pls";
+        let (primary, backup) = (answering(), refusing());
+        remember(playlist, &backup);
+        let mirrors = mirrors(playlist, &[&primary, &backup]);
+        let (result, fallbacks) = connect(&mirrors);
+        assert_eq!(result.unwrap(), primary);
+        assert_eq!(fallbacks.len(), 1);
+        assert_eq!(fallbacks[0], primary);
+        assert_eq!(mirrors.order(), [primary.as_str(), backup.as_str()]);
+    }
+
+    #[test]
+    fn reports_the_last_failure() {
+        let (first, last) = (refusing(), hanging());
+        let (result, fallbacks) =
+            connect(&mirrors("https://example.com/dead.pls", &[&first, &last]));
+        let message = result.unwrap_err().to_string();
+        assert!(
+            message.contains("did not respond within 500 ms"),
+            "{message}"
+        );
+        assert_eq!(fallbacks, [last]);
+        let (result, _) = connect(&mirrors("https://example.com/empty.pls", &[]));
+        assert!(matches!(result, Err(RodioError::Playlist(_))));
+    }
+}
@@ -0,0 +1,112 @@
+//! One now-playing model merged from every kind of stream metadata: ICY
+//! response headers and in-band blocks, Ogg comments and HLS timed ID3.
+
+use std::collections::HashMap;
+
+/// What is playing, as far as the stream has said. Fields stay set until the
+/// stream replaces them; a new track clears the track fields it does not name.
+#[derive(Clone, Debug, Default, PartialEq, uniffi::Record)]
+pub struct NowPlaying {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub station_name: Option<String>,
+    /// Station homepage (`icy-url`).
+    pub station_url: Option<String>,
+    pub genre: Option<String>,
+    pub bitrate_kbps: Option<u32>,
+    pub artwork_url: Option<String>,
+    /// The keys and values received for the station and the current track, as
+    /// passed to `on_metadata`.
+    pub raw: HashMap<String, String>,
+}
+
+impl NowPlaying {
+    /// Merges one batch of metadata, e.g. an ICY block or an ID3 tag.
+    /// Returns whether anything changed.
+    pub fn apply(&mut self, entries: &[(String, String)]) -> bool {
+        let before = self.clone();
+        if entries.iter().any(|(key, _)| is_track_key(key)) {
+            self.artist = None;
+            self.title = None;
+            self.album = None;
+            self.artwork_url = None;
+            self.raw.retain(|key, _| is_station_key(key));
+        }
+        for (key, value) in entries {
+            self.raw.insert(key.clone(), value.clone());
+            self.apply_entry(key, value.trim());
+        }
+        *self != before
+    }
+
+    fn apply_entry(&mut self, key: &str, value: &str) {
+        let value = (!value.is_empty()).then(|| value.to_string());
+        match key.to_ascii_uppercase().as_str() {
+            "STREAMTITLE" => {
+                let (artist, title) = split_stream_title(value.as_deref().unwrap_or_default());
+                self.artist = artist;
+                self.title = title;
+            }
+            "STREAMURL" if value.as_deref().is_some_and(is_image_url) => self.artwork_url = value,
+            "ICY-NAME" | "TRSN" => self.station_name = value,
+            "ICY-URL" => self.station_url = value,
+            "ICY-GENRE" | "GENRE" | "TCON" => self.genre = value,
+            "ICY-BR" => self.bitrate_kbps = value.as_deref().and_then(parse_bitrate),
+            "ARTIST" | "TPE1" => self.artist = value,
+            "TITLE" | "TIT2" => self.title = value,
+            "ALBUM" | "TALB" => self.album = value,
+            _ => {}
+        }
+    }
+}
+
+/// Station-wide keys, which outlive the track they arrived with.
+fn is_station_key(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    key.starts_with("ICY-") || key == "TRSN"
+}
+
+fn is_track_key(key: &str) -> bool {
+    matches!(
+        key.to_ascii_uppercase().as_str(),
+        "STREAMTITLE" | "ARTIST" | "TITLE" | "ALBUM" | "TPE1" | "TIT2" | "TALB"
+    )
+}
+
+/// Splits an ICY `StreamTitle` of the usual `Artist - Title` form; anything
+/// else is taken as the title alone.
+fn split_stream_title(stream_title: &str) -> (Option<String>, Option<String>) {
+    let non_empty = |text: &str| {
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    };
+    match stream_title.split_once(" - ") {
+        Some((artist, title)) if non_empty(artist).is_some() && non_empty(title).is_some() => {
+            (non_empty(artist), non_empty(title))
+        }
+        _ => (None, non_empty(stream_title)),
+    }
+}
+
+/// Stations put anything in `StreamUrl`; only links to images are artwork.
+fn is_image_url(url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let path = url.path().to_ascii_lowercase();
+    [".jpg", ".jpeg", ".png", ".webp", ".gif"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+/// `icy-br` is usually `128`, sometimes a list such as `128,128`.
+pub fn parse_bitrate(value: &str) -> Option<u32> {
+    value
+        .split(',')
+        .next()?
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|kbps| *kbps > 0)
+}
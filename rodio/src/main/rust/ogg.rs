@@ -0,0 +1,343 @@
+//! Vorbis and Opus comment headers read out of live Ogg streams, which send a
+//! new one at the start of every track.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use crate::cue::{ByteCues, DecodedCues};
+
+const PAGE_HEADER_LEN: usize = 27;
+const CAPTURE_PATTERN: &[u8] = b"OggS";
+const VORBIS_COMMENT: &[u8] = b"\x03vorbis";
+const OPUS_TAGS: &[u8] = b"OpusTags";
+/// Comment headers carrying embedded cover art can be large; bigger ones are skipped.
+const MAX_COMMENT_PACKET: usize = 1024 * 1024;
+
+/// Passes a stream through unchanged, queueing the comments of every Ogg
+/// comment header in it. Streams that do not start with an Ogg page are not scanned.
+pub struct OggCommentReader<R: Read> {
+    inner: R,
+    cues: Option<ByteCues>,
+    scanner: Option<CommentScanner>,
+    checked: bool,
+    pos: u64,
+}
+
+impl<R: Read> OggCommentReader<R> {
+    pub fn new(inner: R, cues: Option<DecodedCues>) -> Self {
+        Self {
+            inner,
+            scanner: cues.as_ref().map(|_| CommentScanner::default()),
+            cues: cues.map(|cues| ByteCues::new(cues, None)),
+            checked: false,
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for OggCommentReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (Some(scanner), Some(cues)) = (&mut self.scanner, &mut self.cues) else {
+            return self.inner.read(buf);
+        };
+        cues.consumed(self.pos);
+        let read = self.inner.read(buf)?;
+        self.pos += read as u64;
+        if !self.checked && scanner.buffer.len() + read >= CAPTURE_PATTERN.len() {
+            self.checked = true;
+            let mut head = scanner.buffer.clone();
+            head.extend_from_slice(&buf[..read]);
+            if !head.starts_with(CAPTURE_PATTERN) {
+                self.scanner = None;
+                return Ok(read);
+            }
+        }
+        for (offset, entries) in scanner.feed(&buf[..read]) {
+            cues.push(offset, entries);
+        }
+        Ok(read)
+    }
+}
+
+#[derive(Default)]
+struct CommentScanner {
+    buffer: Vec<u8>,
+    /// Stream offset of the first byte of `buffer`.
+    buffer_start: u64,
+    /// Packets in progress, by logical stream serial number.
+    packets: HashMap<u32, Packet>,
+}
+
+#[derive(Default)]
+struct Packet {
+    data: Vec<u8>,
+    skip: bool,
+}
+
+impl CommentScanner {
+    /// Adds stream bytes and returns the comments of every header completed by
+    /// them, with the stream offset of the end of the page that completed it.
+    fn feed(&mut self, bytes: &[u8]) -> Vec<(u64, Vec<(String, String)>)> {
+        self.buffer.extend_from_slice(bytes);
+        let mut comments = Vec::new();
+        loop {
+            if !self.buffer.starts_with(CAPTURE_PATTERN) {
+                // Lost sync: drop everything before the next capture pattern.
+                let next = self
+                    .buffer
+                    .windows(CAPTURE_PATTERN.len())
+                    .position(|window| window == CAPTURE_PATTERN);
+                match next {
+                    Some(start) => self.discard(start),
+                    None => {
+                        let keep = CAPTURE_PATTERN.len() - 1;
+                        self.discard(self.buffer.len().saturating_sub(keep));
+                        break;
+                    }
+                }
+            }
+            if self.buffer.len() < PAGE_HEADER_LEN {
+                break;
+            }
+            let segments = usize::from(self.buffer[26]);
+            let header_len = PAGE_HEADER_LEN + segments;
+            if self.buffer.len() < header_len {
+                break;
+            }
+            let body_len: usize = self.buffer[PAGE_HEADER_LEN..header_len]
+                .iter()
+                .map(|lacing| usize::from(*lacing))
+                .sum();
+            if self.buffer.len() < header_len + body_len {
+                break;
+            }
+            let page: Vec<u8> = self.buffer[..header_len + body_len].to_vec();
+            self.discard(page.len());
+            let end = self.buffer_start;
+            for entries in self.read_page(&page) {
+                comments.push((end, entries));
+            }
+        }
+        comments
+    }
+
+    fn discard(&mut self, len: usize) {
+        self.buffer.drain(..len);
+        self.buffer_start += len as u64;
+    }
+
+    fn read_page(&mut self, page: &[u8]) -> Vec<Vec<(String, String)>> {
+        let mut comments = Vec::new();
+        let continued = page[5] & 0x01 != 0;
+        let serial = u32::from_le_bytes([page[14], page[15], page[16], page[17]]);
+        let segments = usize::from(page[26]);
+        let lacing = &page[PAGE_HEADER_LEN..PAGE_HEADER_LEN + segments];
+        if !continued {
+            self.packets.remove(&serial);
+        }
+        let mut offset = PAGE_HEADER_LEN + segments;
+        for &len in lacing {
+            let segment = &page[offset..offset + usize::from(len)];
+            offset += usize::from(len);
+            let packet = self.packets.entry(serial).or_default();
+            if !packet.skip {
+                packet.data.extend_from_slice(segment);
+                if packet.data.len() >= OPUS_TAGS.len()
+                    && comment_prefix_len(&packet.data).is_none()
+                    || packet.data.len() > MAX_COMMENT_PACKET
+                {
+                    packet.skip = true;
+                    packet.data = Vec::new();
+                }
+            }
+            if len < 255 {
+                if let Some(packet) = self.packets.remove(&serial) {
+                    if !packet.skip {
+                        if let Some(entries) = parse_comment_packet(&packet.data) {
+                            comments.push(entries);
+                        }
+                    }
+                }
+            }
+        }
+        comments
+    }
+}
+
+fn comment_prefix_len(packet: &[u8]) -> Option<usize> {
+    [VORBIS_COMMENT, OPUS_TAGS]
+        .into_iter()
+        .find(|prefix| packet.starts_with(prefix))
+        .map(<[u8]>::len)
+}
+
+/// Comments as `(FIELD, value)` with upper-case field names. Embedded pictures
+/// are skipped.
+fn parse_comment_packet(packet: &[u8]) -> Option<Vec<(String, String)>> {
+    let mut offset = comment_prefix_len(packet)?;
+    let read_u32 = |offset: &mut usize| {
+        let bytes = packet.get(*offset..*offset + 4)?;
+        *offset += 4;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+    let vendor_len = read_u32(&mut offset)?;
+    offset = offset.checked_add(vendor_len)?;
+    let count = read_u32(&mut offset)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let len = read_u32(&mut offset)?;
+        let comment = packet.get(offset..offset.checked_add(len)?)?;
+        offset += len;
+        let comment = String::from_utf8_lossy(comment);
+        let Some((field, value)) = comment.split_once('=') else {
+            continue;
+        };
+        let field = field.to_ascii_uppercase();
+        if matches!(field.as_str(), "METADATA_BLOCK_PICTURE" | "COVERART") || value.is_empty() {
+            continue;
+        }
+        entries.push((field, value.to_string()));
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A comment packet with `prefix` and `comments`.
+    fn comment_packet(prefix: &[u8], comments: &[&str]) -> Vec<u8> {
+        let mut packet = prefix.to_vec();
+        packet.extend_from_slice(&6u32.to_le_bytes());
+        packet.extend_from_slice(b"vendor");
+        packet.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            packet.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            packet.extend_from_slice(comment.as_bytes());
+        }
+        packet
+    }
+
+    /// An Ogg page of `serial` carrying `data`, which ends a packet if `complete`.
+    fn page(serial: u32, continued: bool, data: &[u8], complete: bool) -> Vec<u8> {
+        let mut lacing = vec![255u8; data.len() / 255];
+        if complete {
+            lacing.push((data.len() % 255) as u8);
+        }
+        let mut page = CAPTURE_PATTERN.to_vec();
+        page.extend_from_slice(&[0, u8::from(continued)]);
+        page.extend_from_slice(&[0; 8]);
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&[0; 8]);
+        page.push(lacing.len() as u8);
+        page.extend(lacing);
+        page.extend_from_slice(data);
+        page
+    }
+
+    fn entries(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_comment_fields() {
+        let packet = comment_packet(
+            VORBIS_COMMENT,
+            &[
+                "title=Song",
+                "Artist=Someone",
+                "METADATA_BLOCK_PICTURE=AAAA",
+                "ALBUM=",
+                "no separator",
+            ],
+        );
+        assert_eq!(
+            parse_comment_packet(&packet),
+            Some(entries(&[("TITLE", "Song"), ("ARTIST", "Someone")]))
+        );
+        assert_eq!(parse_comment_packet(b"\x01vorbis"), None);
+    }
+
+    #[test]
+    fn finds_comments_at_the_end_of_their_page() {
+        let identification = page(7, false, b"\x01vorbis\0\0\0\0", true);
+        let comments = page(
+            7,
+            false,
+            &comment_packet(VORBIS_COMMENT, &["TITLE=A"]),
+            true,
+        );
+        let mut stream = identification.clone();
+        stream.extend_from_slice(&comments);
+
+        let mut scanner = CommentScanner::default();
+        let end = stream.len() as u64;
+        assert_eq!(scanner.feed(&stream), [(end, entries(&[("TITLE", "A")]))]);
+
+        // Byte by byte, after garbage that loses sync.
+        let mut scanner = CommentScanner::default();
+        let mut found = Vec::new();
+        for byte in [b"Og\x00junk".as_slice(), &stream].concat() {
+            found.extend(scanner.feed(&[byte]));
+        }
+        assert_eq!(found, [(end + 7, entries(&[("TITLE", "A")]))]);
+    }
+
+    #[test]
+    fn joins_comment_packets_across_pages() {
+        let title = format!("TITLE={}", "x".repeat(400));
+        let packet = comment_packet(OPUS_TAGS, &[&title]);
+        let mut stream = page(1, false, &packet[..255], false);
+        // A packet of another logical stream in between.
+        stream.extend(page(2, false, b"OpusHead", true));
+        stream.extend(page(1, true, &packet[255..], true));
+        let found = CommentScanner::default().feed(&stream);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, stream.len() as u64);
+        assert_eq!(found[0].1[0].1.len(), 400);
+    }
+
+    #[test]
+    fn passes_streams_through_unchanged() {
+        let mut stream = page(1, false, &comment_packet(OPUS_TAGS, &["TITLE=A"]), true);
+        stream.extend_from_slice(&[0x55; 1000]);
+        for data in [stream, b"ID3 not an ogg stream".to_vec()] {
+            let mut out = Vec::new();
+            OggCommentReader::new(Cursor::new(data.clone()), Some(DecodedCues::default()))
+                .read_to_end(&mut out)
+                .unwrap();
+            assert_eq!(out, data);
+        }
+    }
+
+    #[test]
+    fn malformed_pages_do_not_panic() {
+        let packet = comment_packet(VORBIS_COMMENT, &["TITLE=Song", "ARTIST=Someone"]);
+        for len in 0..packet.len() {
+            parse_comment_packet(&packet[..len]);
+        }
+        let mut huge_vendor = packet.clone();
+        huge_vendor[7..11].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse_comment_packet(&huge_vendor), None);
+        let mut huge_count = packet.clone();
+        huge_count[17..21].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse_comment_packet(&huge_count), None);
+
+        let stream = page(3, false, &packet, true);
+        for len in 0..stream.len() {
+            CommentScanner::default().feed(&stream[..len]);
+        }
+        // A continuation with nothing before it, and lacing past the page end.
+        let mut scanner = CommentScanner::default();
+        scanner.feed(&page(3, true, &packet[10..], true));
+        let mut truncated = page(3, false, &packet, true);
+        truncated[27] = 255;
+        truncated.truncate(40);
+        scanner.feed(&truncated);
+    }
+}
@@ -0,0 +1,295 @@
+//! Lock-free PCM buffering between network decoders and the audio thread.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::wrap::Wrap;
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use rodio::source::UniformSourceIterator;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+
+use crate::error::RodioError;
+
+const PCM_BUFFER_MILLIS: u64 = 4000;
+const PCM_PREBUFFER_MILLIS: u64 = 300;
+const PCM_PREBUFFER_TIMEOUT: Duration = Duration::from_secs(10);
+const WORKER_CHUNK_FRAMES: usize = 1024;
+const WORKER_IDLE: Duration = Duration::from_millis(5);
+
+struct PcmShared {
+    finished: AtomicBool,
+    /// Shared with whatever the worker reads from, so its waits end too.
+    cancelled: Arc<AtomicBool>,
+    /// Samples handed to the audio thread, silence excluded.
+    played: AtomicU64,
+    /// Samples the worker has taken from its sources.
+    decoded: AtomicU64,
+}
+
+/// Supplies a replacement source once the worker's current source runs dry,
+/// e.g. after a dropped live stream has been reconnected.
+pub trait SourceFeed: Send {
+    fn next_source(&mut self, cancelled: &AtomicBool) -> Option<Box<dyn Source + Send>>;
+}
+
+/// Audio-thread side of the ring buffer. Never blocks: when the worker falls
+/// behind it plays silence instead of waiting on the network.
+pub struct BufferedSource {
+    consumer: HeapCons<Sample>,
+    shared: Arc<PcmShared>,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    total_duration: Option<Duration>,
+    frame_remaining: ChannelCount,
+    silence_remaining: ChannelCount,
+}
+
+impl BufferedSource {
+    /// Blocks the calling (non-audio) thread until enough audio is buffered to
+    /// start playback without an immediate underrun.
+    pub fn wait_for_prebuffer(&self) {
+        let wanted = millis_to_samples(PCM_PREBUFFER_MILLIS, self.channels, self.sample_rate)
+            .min(self.consumer.capacity().get());
+        let deadline = Instant::now() + PCM_PREBUFFER_TIMEOUT;
+        while self.consumer.occupied_len() < wanted
+            && !self.shared.finished.load(Ordering::Acquire)
+            && Instant::now() < deadline
+        {
+            thread::sleep(WORKER_IDLE);
+        }
+    }
+
+    pub fn clock(&self) -> PlaybackClock {
+        PlaybackClock {
+            shared: self.shared.clone(),
+            samples_per_sec: u64::from(self.sample_rate) * u64::from(self.channels),
+        }
+    }
+
+    pub fn buffer_level(&self) -> BufferLevel {
+        BufferLevel {
+            ring: self.consumer.rb_ref().clone(),
+            samples_per_sec: u64::from(self.sample_rate) * u64::from(self.channels),
+        }
+    }
+}
+
+/// Read-only view of how much decoded audio is waiting in the ring buffer.
+#[derive(Clone)]
+pub struct BufferLevel {
+    ring: Arc<HeapRb<Sample>>,
+    samples_per_sec: u64,
+}
+
+impl BufferLevel {
+    pub fn buffered(&self) -> Duration {
+        let samples = self.ring.occupied_len() as u64;
+        Duration::from_millis(samples * 1000 / self.samples_per_sec.max(1))
+    }
+}
+
+/// How much of the buffered stream has been played so far.
+#[derive(Clone)]
+pub struct PlaybackClock {
+    shared: Arc<PcmShared>,
+    samples_per_sec: u64,
+}
+
+impl PlaybackClock {
+    pub fn played(&self) -> Duration {
+        let samples = self.shared.played.load(Ordering::Relaxed);
+        Duration::from_millis(samples * 1000 / self.samples_per_sec.max(1))
+    }
+
+    /// How much of the stream has been decoded, which runs ahead of
+    /// [`PlaybackClock::played`] by the buffered audio.
+    pub fn decoded(&self) -> Duration {
+        let samples = self.shared.decoded.load(Ordering::Relaxed);
+        Duration::from_millis(samples * 1000 / self.samples_per_sec.max(1))
+    }
+
+    /// True once the source has been dropped by the sink.
+    pub fn is_stopped(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+impl PlaybackClock {
+    /// A clock of one sample per millisecond, moved by [`PlaybackClock::advance_to`].
+    pub fn manual() -> Self {
+        Self {
+            shared: Arc::new(PcmShared {
+                finished: AtomicBool::new(false),
+                cancelled: Arc::new(AtomicBool::new(false)),
+                played: AtomicU64::new(0),
+                decoded: AtomicU64::new(0),
+            }),
+            samples_per_sec: 1000,
+        }
+    }
+
+    pub fn advance_to(&self, decoded: Duration, played: Duration) {
+        let millis = |time: Duration| time.as_millis() as u64;
+        self.shared.decoded.store(millis(decoded), Ordering::Relaxed);
+        self.shared.played.store(millis(played), Ordering::Relaxed);
+    }
+}
+
+impl Iterator for BufferedSource {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.frame_remaining > 0 {
+            self.frame_remaining -= 1;
+            return Some(self.consumer.try_pop().unwrap_or(0.0));
+        }
+        if self.silence_remaining > 0 {
+            self.silence_remaining -= 1;
+            return Some(0.0);
+        }
+        // Only ever start whole frames so an underrun cannot swap channels.
+        let finished = self.shared.finished.load(Ordering::Acquire);
+        let channels = usize::from(self.channels);
+        if self.consumer.occupied_len() >= channels {
+            self.frame_remaining = self.channels - 1;
+            self.shared
+                .played
+                .fetch_add(u64::from(self.channels), Ordering::Relaxed);
+            return self.consumer.try_pop();
+        }
+        if finished {
+            return None;
+        }
+        self.silence_remaining = self.channels - 1;
+        Some(0.0)
+    }
+}
+
+impl Source for BufferedSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+impl Drop for BufferedSource {
+    fn drop(&mut self) {
+        self.shared.cancelled.store(true, Ordering::Release);
+    }
+}
+
+/// Moves `source` onto a dedicated thread that decodes into a ring buffer and
+/// returns the consuming end, ready to be appended to a sink. `cancelled` is
+/// set when that end is dropped.
+pub fn spawn_decoder_worker<S>(
+    source: S,
+    feed: Option<Box<dyn SourceFeed>>,
+    cancelled: Arc<AtomicBool>,
+    name: &str,
+) -> Result<BufferedSource, RodioError>
+where
+    S: Source + Send + 'static,
+{
+    let channels = source.channels().max(1);
+    let sample_rate = source.sample_rate().max(1);
+    let total_duration = source.total_duration();
+    let capacity = millis_to_samples(PCM_BUFFER_MILLIS, channels, sample_rate);
+    let (producer, consumer) = HeapRb::<Sample>::new(capacity).split();
+    let shared = Arc::new(PcmShared {
+        finished: AtomicBool::new(false),
+        cancelled,
+        played: AtomicU64::new(0),
+        decoded: AtomicU64::new(0),
+    });
+
+    let source: Box<dyn Source + Send> = Box::new(source);
+    let worker_shared = shared.clone();
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || run_worker(source, feed, producer, (channels, sample_rate), &worker_shared))
+        .map_err(|err| RodioError::Internal(format!("failed to spawn decoder worker: {err}")))?;
+
+    Ok(BufferedSource {
+        consumer,
+        shared,
+        channels,
+        sample_rate,
+        total_duration,
+        frame_remaining: 0,
+        silence_remaining: 0,
+    })
+}
+
+fn run_worker(
+    source: Box<dyn Source + Send>,
+    mut feed: Option<Box<dyn SourceFeed>>,
+    mut producer: HeapProd<Sample>,
+    (channels, sample_rate): (ChannelCount, SampleRate),
+    shared: &PcmShared,
+) {
+    // Pin the output format so mid-stream format changes never reach the mixer.
+    let mut source = UniformSourceIterator::new(source, channels, sample_rate);
+    let chunk_len = WORKER_CHUNK_FRAMES * usize::from(channels);
+    let mut chunk = Vec::with_capacity(chunk_len);
+    loop {
+        if shared.cancelled.load(Ordering::Acquire) {
+            return;
+        }
+        chunk.clear();
+        // Counted per sample: in-band metadata is stamped mid-chunk, from this thread.
+        chunk.extend(
+            source
+                .by_ref()
+                .take(chunk_len)
+                .inspect(|_| {
+                    shared.decoded.fetch_add(1, Ordering::Relaxed);
+                }),
+        );
+        if chunk.is_empty() {
+            let next = feed
+                .as_mut()
+                .and_then(|feed| feed.next_source(&shared.cancelled));
+            match next {
+                Some(next) => {
+                    source = UniformSourceIterator::new(next, channels, sample_rate);
+                    continue;
+                }
+                None => {
+                    shared.finished.store(true, Ordering::Release);
+                    return;
+                }
+            }
+        }
+        let mut offset = 0;
+        while offset < chunk.len() {
+            if shared.cancelled.load(Ordering::Acquire) {
+                return;
+            }
+            let pushed = producer.push_slice(&chunk[offset..]);
+            offset += pushed;
+            if pushed == 0 {
+                thread::sleep(WORKER_IDLE);
+            }
+        }
+    }
+}
+
+fn millis_to_samples(millis: u64, channels: ChannelCount, sample_rate: SampleRate) -> usize {
+    let samples = u64::from(sample_rate) * u64::from(channels) * millis / 1000;
+    usize::try_from(samples).unwrap_or(usize::MAX).max(usize::from(channels))
+}
@@ -0,0 +1,600 @@
+//! Playlist files: M3U / EXTM3U, PLS, XSPF and ASX.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use roxmltree::{Document, Node};
+
+use crate::error::RodioError;
+use crate::http::{request_stream, HttpContext, HttpRequestOptions};
+use crate::reconnect::ReconnectPolicy;
+
+/// One entry of a playlist, in playlist order.
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct PlaylistEntry {
+    /// URL, or local path for entries of a local playlist, resolved against
+    /// the playlist's own location.
+    pub location: String,
+    pub title: Option<String>,
+    pub duration_ms: Option<u64>,
+    /// Everything else the format records for the entry, such as EXTINF
+    /// attributes (`tvg-id`, `group-title`), XSPF `creator` or ASX `PARAM`s.
+    pub attributes: HashMap<String, String>,
+}
+
+/// What relative entry locations are resolved against.
+pub enum PlaylistBase {
+    Url(reqwest::Url),
+    Dir(PathBuf),
+}
+
+impl PlaylistBase {
+    fn resolve(&self, location: &str) -> Option<String> {
+        let location = location.trim();
+        if location.is_empty() {
+            return None;
+        }
+        // A one-letter scheme is a Windows drive letter, not a URL.
+        if let Ok(url) = reqwest::Url::parse(location) {
+            if url.scheme().len() > 1 {
+                if url.scheme() == "file" {
+                    if let Ok(path) = url.to_file_path() {
+                        return Some(path.display().to_string());
+                    }
+                }
+                return Some(location.to_string());
+            }
+        }
+        match self {
+            Self::Url(base) => base.join(location).ok().map(String::from),
+            Self::Dir(dir) => Some(dir.join(location).display().to_string()),
+        }
+    }
+}
+
+/// Reads the playlist at `url_or_path`: an HTTP(S) URL or a local file.
+#[uniffi::export(default(options = None))]
+pub fn parse_playlist(
+    url_or_path: String,
+    options: Option<HttpRequestOptions>,
+) -> Result<Vec<PlaylistEntry>, RodioError> {
+    let http = HttpContext::new(ReconnectPolicy::default(), None, options.as_ref())?;
+    load_playlist(&url_or_path, &http)
+}
+
+pub fn load_playlist(
+    url_or_path: &str,
+    http: &HttpContext,
+) -> Result<Vec<PlaylistEntry>, RodioError> {
+    if is_http(url_or_path) {
+        let response = request_stream(url_or_path, false, http)?;
+        let base = PlaylistBase::Url(response.url().clone());
+        let body = response.text()?;
+        return Ok(parse_playlist_body(&body, &base));
+    }
+    let path = match reqwest::Url::parse(url_or_path) {
+        Ok(url) if url.scheme() == "file" => url
+            .to_file_path()
+            .map_err(|_| RodioError::InvalidUrl(url_or_path.to_string()))?,
+        _ => PathBuf::from(url_or_path),
+    };
+    let body = fs::read(&path)?;
+    let base = PlaylistBase::Dir(path.parent().unwrap_or(Path::new("")).to_path_buf());
+    Ok(parse_playlist_body(&String::from_utf8_lossy(&body), &base))
+}
+
+/// Parses a playlist of any supported format, told apart by its content.
+pub fn parse_playlist_body(body: &str, base: &PlaylistBase) -> Vec<PlaylistEntry> {
+    let body = body.trim_start_matches('\u{feff}').trim_start();
+    if body.starts_with('<') {
+        return match Document::parse(body) {
+            Ok(document) => {
+                let root = document.root_element();
+                if is_named(root, "asx") {
+                    parse_asx(root, base)
+                } else {
+                    parse_xspf(root, base)
+                }
+            }
+            // ASX files are often not well-formed XML; their references are still usable.
+            Err(_) => parse_asx_refs(body, base),
+        };
+    }
+    let is_pls = body.lines().map(str::trim).any(|line| {
+        line.eq_ignore_ascii_case("[playlist]")
+            || pls_key(line).is_some_and(|(key, _)| key == "file")
+    });
+    if is_pls {
+        parse_pls(body, base)
+    } else {
+        parse_m3u(body, base)
+    }
+}
+
+fn parse_m3u(body: &str, base: &PlaylistBase) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut title = None;
+    let mut duration_ms = None;
+    let mut attributes = HashMap::new();
+    for line in body.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(directive) = line.strip_prefix('#') {
+            let (tag, value) = directive.split_once(':').unwrap_or((directive, ""));
+            match tag {
+                "EXTINF" => {
+                    let info = parse_extinf(value);
+                    duration_ms = info.0;
+                    attributes.extend(info.1);
+                    title = info.2;
+                }
+                "EXTGRP" => {
+                    attributes.insert("group".to_string(), value.trim().to_string());
+                }
+                "EXTALB" => {
+                    attributes.insert("album".to_string(), value.trim().to_string());
+                }
+                "EXTART" => {
+                    attributes.insert("artist".to_string(), value.trim().to_string());
+                }
+                "EXTGENRE" => {
+                    attributes.insert("genre".to_string(), value.trim().to_string());
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if let Some(location) = base.resolve(line) {
+            entries.push(PlaylistEntry {
+                location,
+                title: title.take(),
+                duration_ms: duration_ms.take(),
+                attributes: std::mem::take(&mut attributes),
+            });
+        }
+    }
+    entries
+}
+
+/// `#EXTINF:<seconds> key="value" ...,<title>`: the duration (negative for
+/// none), attributes and title.
+fn parse_extinf(value: &str) -> (Option<u64>, HashMap<String, String>, Option<String>) {
+    let mut quoted = false;
+    let mut comma = None;
+    for (index, ch) in value.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                comma = Some(index);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (head, title) = match comma {
+        Some(index) => (&value[..index], Some(value[index + 1..].trim())),
+        None => (value, None),
+    };
+    let head = head.trim();
+    let (duration, mut rest) = head.split_once(char::is_whitespace).unwrap_or((head, ""));
+    let mut attributes = HashMap::new();
+    while let Some((key, value)) = rest.trim_start().split_once('=') {
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(char::is_whitespace).unwrap_or((value, "")),
+        };
+        attributes.insert(key.trim().to_string(), value.to_string());
+        rest = remainder;
+    }
+    (
+        seconds_to_ms(duration),
+        attributes,
+        title.filter(|title| !title.is_empty()).map(str::to_string),
+    )
+}
+
+fn parse_pls(body: &str, base: &PlaylistBase) -> Vec<PlaylistEntry> {
+    #[derive(Default)]
+    struct PlsEntry {
+        file: Option<String>,
+        title: Option<String>,
+        length: Option<u64>,
+        attributes: HashMap<String, String>,
+    }
+
+    let mut numbered: BTreeMap<u32, PlsEntry> = BTreeMap::new();
+    for line in body.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let Some((name, number)) = pls_key(key) else {
+            continue;
+        };
+        let entry = numbered.entry(number).or_default();
+        let value = value.trim();
+        match name.as_str() {
+            "file" => entry.file = Some(value.to_string()),
+            "title" => entry.title = Some(value.to_string()).filter(|title| !title.is_empty()),
+            "length" => entry.length = seconds_to_ms(value),
+            _ => {
+                entry.attributes.insert(name, value.to_string());
+            }
+        }
+    }
+    numbered
+        .into_values()
+        .filter_map(|entry| {
+            Some(PlaylistEntry {
+                location: base.resolve(&entry.file?)?,
+                title: entry.title,
+                duration_ms: entry.length,
+                attributes: entry.attributes,
+            })
+        })
+        .collect()
+}
+
+/// Splits a PLS key such as `File12` into its lowercase name and number.
+fn pls_key(key: &str) -> Option<(String, u32)> {
+    let key = key.split_once('=').map_or(key, |(key, _)| key).trim();
+    let digits = key.len() - key.trim_end_matches(|ch: char| ch.is_ascii_digit()).len();
+    if digits == 0 || digits == key.len() {
+        return None;
+    }
+    let (name, number) = key.split_at(key.len() - digits);
+    Some((name.to_ascii_lowercase(), number.parse().ok()?))
+}
+
+fn parse_xspf(root: Node, base: &PlaylistBase) -> Vec<PlaylistEntry> {
+    let tracks = root
+        .children()
+        .filter(|node| is_named(*node, "trackList"))
+        .flat_map(|list| list.children().filter(|node| is_named(*node, "track")));
+    let mut entries = Vec::new();
+    for track in tracks {
+        let mut location = None;
+        let mut title = None;
+        let mut duration_ms = None;
+        let mut attributes = HashMap::new();
+        for field in track.children().filter(Node::is_element) {
+            let text = field.text().map(str::trim).unwrap_or_default();
+            match field.tag_name().name() {
+                "location" => {
+                    if location.is_none() {
+                        location = base.resolve(text);
+                    }
+                }
+                "title" => title = Some(text.to_string()).filter(|title| !title.is_empty()),
+                "duration" => duration_ms = text.parse().ok(),
+                "link" | "meta" | "extension" => {}
+                name => {
+                    if !text.is_empty() {
+                        attributes
+                            .entry(name.to_string())
+                            .or_insert_with(|| text.to_string());
+                    }
+                }
+            }
+        }
+        if let Some(location) = location {
+            entries.push(PlaylistEntry {
+                location,
+                title,
+                duration_ms,
+                attributes,
+            });
+        }
+    }
+    entries
+}
+
+fn parse_asx(root: Node, base: &PlaylistBase) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    for node in root.children().filter(Node::is_element) {
+        if is_named(node, "entryref") {
+            if let Some(location) = attribute(node, "href").and_then(|href| base.resolve(href)) {
+                entries.push(PlaylistEntry {
+                    location,
+                    title: None,
+                    duration_ms: None,
+                    attributes: HashMap::new(),
+                });
+            }
+            continue;
+        }
+        if !is_named(node, "entry") {
+            continue;
+        }
+        let mut location = None;
+        let mut title = None;
+        let mut duration_ms = None;
+        let mut attributes = HashMap::new();
+        for field in node.children().filter(Node::is_element) {
+            let name = field.tag_name().name().to_ascii_lowercase();
+            let text = field.text().map(str::trim).unwrap_or_default();
+            match name.as_str() {
+                // References are alternatives for the same entry; the first
+                // HTTP(S) one wins over protocols such as `mms://`.
+                "ref" => {
+                    let href = attribute(field, "href").and_then(|href| base.resolve(href));
+                    let better = match (location.as_deref(), href.as_deref()) {
+                        (None, _) => true,
+                        (Some(current), Some(href)) => !is_http(current) && is_http(href),
+                        (Some(_), None) => false,
+                    };
+                    if better {
+                        location = href;
+                    }
+                }
+                "title" => title = Some(text.to_string()).filter(|title| !title.is_empty()),
+                "duration" => duration_ms = attribute(field, "value").and_then(clock_to_ms),
+                "param" => {
+                    if let (Some(key), Some(value)) =
+                        (attribute(field, "name"), attribute(field, "value"))
+                    {
+                        attributes.insert(key.to_string(), value.to_string());
+                    }
+                }
+                "moreinfo" => {
+                    if let Some(href) = attribute(field, "href") {
+                        attributes.insert(name, href.to_string());
+                    }
+                }
+                _ => {
+                    if !text.is_empty() {
+                        attributes.insert(name, text.to_string());
+                    }
+                }
+            }
+        }
+        if let Some(location) = location {
+            entries.push(PlaylistEntry {
+                location,
+                title,
+                duration_ms,
+                attributes,
+            });
+        }
+    }
+    entries
+}
+
+/// The `href` of every `<ref>` and `<entryref>` of a malformed ASX file.
+fn parse_asx_refs(body: &str, base: &PlaylistBase) -> Vec<PlaylistEntry> {
+    let lower = body.to_ascii_lowercase();
+    let mut entries = Vec::new();
+    let mut position = 0;
+    while let Some(start) = lower[position..].find('<').map(|offset| position + offset) {
+        let end = lower[start..]
+            .find('>')
+            .map_or(lower.len(), |offset| start + offset);
+        position = end;
+        let tag = &lower[start + 1..end];
+        if !tag.starts_with("ref ") && !tag.starts_with("entryref ") {
+            continue;
+        }
+        let Some(href) = tag.find("href").map(|offset| start + 1 + offset + 4) else {
+            continue;
+        };
+        let value = body[href..end]
+            .trim_start()
+            .trim_start_matches('=')
+            .trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value.split_whitespace().next().unwrap_or_default(),
+        };
+        if let Some(location) = base.resolve(value.trim_end_matches('/')) {
+            entries.push(PlaylistEntry {
+                location,
+                title: None,
+                duration_ms: None,
+                attributes: HashMap::new(),
+            });
+        }
+    }
+    entries
+}
+
+fn is_http(location: &str) -> bool {
+    let location = location.to_ascii_lowercase();
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Element names are case-insensitive in ASX and compared exactly elsewhere.
+fn is_named(node: Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name().eq_ignore_ascii_case(name)
+}
+
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|attribute| attribute.name().eq_ignore_ascii_case(name))
+        .map(|attribute| attribute.value())
+}
+
+fn seconds_to_ms(value: &str) -> Option<u64> {
+    let seconds: f64 = value.trim().parse().ok()?;
+    (seconds >= 0.0).then(|| (seconds * 1000.0).round() as u64)
+}
+
+/// `[[hh:]mm:]ss[.fff]`.
+fn clock_to_ms(value: &str) -> Option<u64> {
+    let mut seconds = 0.0;
+    for part in value.trim().split(':') {
+        seconds = seconds * 60.0 + part.trim().parse::<f64>().ok()?;
+    }
+    (seconds >= 0.0).then(|| (seconds * 1000.0).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const M3U: &str = "\u{feff}#EXTM3U
+#EXTINF:-1 tvg-id=\"one.fm\" group-title=\"News, Talk\",One FM
+#EXTGRP:Radio
+http://radio.example.com/one
+# A comment
+#EXTINF:215.5,Artist - Song
+music/song.mp3
+";
+    const PLS: &str = "[playlist]
+NumberOfEntries=2
+File2=http://radio.example.com/two
+Title2=Two
+Length2=-1
+File1=stream.aac
+Title1=
+Length1=60
+Genre1=Jazz
+Version=2
+";
+    const XSPF: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/"><trackList>
+<track><location>song.flac</location><location>other.flac</location><title>Song</title>
+<creator>Artist</creator><duration>215000</duration><meta rel="x">y</meta></track>
+<track><title>No location</title></track>
+</trackList></playlist>"#;
+    const ASX: &str = r#"<ASX version="3.0"><Entry>
+<Ref href="mms://radio.example.com/live"/><REF HREF="http://radio.example.com/live"/>
+<Title>Live</Title><Duration value="00:03:25.5"/><Param name="bitrate" value="128"/>
+<MoreInfo href="http://example.com"/></Entry>
+<EntryRef href="next.asx"/></ASX>"#;
+
+    fn url_base() -> PlaylistBase {
+        PlaylistBase::Url(reqwest::Url::parse("http://example.com/lists/radio.m3u").unwrap())
+    }
+
+    fn locations(entries: &[PlaylistEntry]) -> Vec<&str> {
+        entries
+            .iter()
+            .map(|entry| entry.location.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parses_extended_m3u() {
+        let entries = parse_playlist_body(M3U, &url_base());
+        assert_eq!(
+            locations(&entries),
+            [
+                "http://radio.example.com/one",
+                "http://example.com/lists/music/song.mp3"
+            ]
+        );
+        let radio = &entries[0];
+        assert_eq!(radio.title.as_deref(), Some("One FM"));
+        assert_eq!(radio.duration_ms, None);
+        assert_eq!(radio.attributes["tvg-id"], "one.fm");
+        assert_eq!(radio.attributes["group-title"], "News, Talk");
+        assert_eq!(radio.attributes["group"], "Radio");
+        assert_eq!(entries[1].title.as_deref(), Some("Artist - Song"));
+        assert_eq!(entries[1].duration_ms, Some(215_500));
+        assert!(entries[1].attributes.is_empty());
+    }
+
+    #[test]
+    fn parses_pls_in_entry_order() {
+        let base = PlaylistBase::Dir(PathBuf::from("/music"));
+        let entries = parse_playlist_body(PLS, &base);
+        assert_eq!(
+            locations(&entries),
+            [
+                Path::new("/music")
+                    .join("stream.aac")
+                    .display()
+                    .to_string()
+                    .as_str(),
+                "http://radio.example.com/two"
+            ]
+        );
+        assert_eq!(entries[0].title, None);
+        assert_eq!(entries[0].duration_ms, Some(60_000));
+        assert_eq!(entries[0].attributes["genre"], "Jazz");
+        assert_eq!(entries[1].title.as_deref(), Some("Two"));
+        assert_eq!(entries[1].duration_ms, None);
+        // PLS files without the section header are still recognised.
+        let entries = parse_playlist_body("File1=http://a.example/x\n", &url_base());
+        assert_eq!(locations(&entries), ["http://a.example/x"]);
+    }
+
+    #[test]
+    fn parses_xspf() {
+        let entries = parse_playlist_body(XSPF, &url_base());
+        assert_eq!(locations(&entries), ["http://example.com/lists/song.flac"]);
+        let song = &entries[0];
+        assert_eq!(song.title.as_deref(), Some("Song"));
+        assert_eq!(song.duration_ms, Some(215_000));
+        assert_eq!(song.attributes.len(), 1);
+        assert_eq!(song.attributes["creator"], "Artist");
+    }
+
+    #[test]
+    fn parses_asx_preferring_http_references() {
+        let entries = parse_playlist_body(ASX, &url_base());
+        assert_eq!(
+            locations(&entries),
+            [
+                "http://radio.example.com/live",
+                "http://example.com/lists/next.asx"
+            ]
+        );
+        let live = &entries[0];
+        assert_eq!(live.title.as_deref(), Some("Live"));
+        assert_eq!(live.duration_ms, Some(205_500));
+        assert_eq!(live.attributes["bitrate"], "128");
+        assert_eq!(live.attributes["moreinfo"], "http://example.com");
+    }
+
+    #[test]
+    fn falls_back_to_references_of_malformed_asx() {
+        let body = "<asx version=3><entry><ref href = 'http://a.example/1' ><ref href=http://a.example/2/>\
+                    <title>Bad & unclosed</entry><ENTRYREF HREF=\"next.asx\"";
+        let entries = parse_playlist_body(body, &url_base());
+        assert_eq!(
+            locations(&entries),
+            [
+                "http://a.example/1",
+                "http://a.example/2",
+                "http://example.com/lists/next.asx"
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_durations() {
+        assert_eq!(seconds_to_ms("12.3456"), Some(12_346));
+        assert_eq!(seconds_to_ms("-1"), None);
+        assert_eq!(seconds_to_ms("NaN"), None);
+        assert_eq!(clock_to_ms("01:02:03.5"), Some(3_723_500));
+        assert_eq!(clock_to_ms("45"), Some(45_000));
+        assert_eq!(clock_to_ms("1::2"), None);
+        assert_eq!(pls_key("File12"), Some(("file".to_string(), 12)));
+        assert_eq!(pls_key("12"), None);
+        assert_eq!(pls_key("Version"), None);
+        assert_eq!(pls_key("File99999999999"), None);
+    }
+
+    #[test]
+    fn malformed_playlists_do_not_panic() {
+        let base = url_base();
+        for body in [M3U, PLS, XSPF, ASX, "<ASX><ref href=\"é\u{1F3B5}"] {
+            for (end, _) in body.char_indices() {
+                parse_playlist_body(&body[..end], &base);
+            }
+        }
+        for body in [
+            "",
+            "\u{feff}",
+            "<",
+            "[playlist]\nFile=",
+            "#EXTINF:\"unterminated,x\n",
+            "#EXTINF:1 a=,\nb",
+        ] {
+            parse_playlist_body(body, &base);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Background downloads for HLS: segments ahead of the one being read, and
+//! live media playlists reloaded on their own timer.
+
+use std::collections::VecDeque;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hls_m3u8::MediaPlaylist;
+
+use crate::error::RodioError;
+use crate::hls::{fetch_hls_media_playlist, hls_refresh_delay};
+use crate::http::{request_stream, HttpContext};
+use crate::llhls::{blocking_reload_url, LowLatency};
+
+const DOWNLOAD_CHUNK: usize = 64 * 1024;
+/// How often waits for a playlist reload check whether playback was stopped.
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// A segment body downloaded ahead of time, with how long the download took.
+pub struct FetchedSegment {
+    pub bytes: Vec<u8>,
+    pub elapsed: Duration,
+}
+
+struct PendingSegment {
+    url: reqwest::Url,
+    /// Behind a mutex only so the reader stays `Sync`.
+    result: Mutex<Receiver<Result<FetchedSegment, RodioError>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for PendingSegment {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Keeps up to `depth` upcoming segments downloading in parallel, in playlist order.
+pub struct SegmentPrefetcher {
+    depth: usize,
+    pending: VecDeque<PendingSegment>,
+}
+
+impl SegmentPrefetcher {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Starts downloading `url` unless it is already pending or the queue is full.
+    /// A failed spawn is not an error; the segment is then fetched when it is read.
+    pub fn start(&mut self, url: reqwest::Url, http: &HttpContext) {
+        if self.pending.len() >= self.depth || self.pending.iter().any(|p| p.url == url) {
+            return;
+        }
+        let (sender, result) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker_url = url.clone();
+        let worker_http = http.clone();
+        let worker_cancelled = cancelled.clone();
+        let spawned = thread::Builder::new()
+            .name("rodio-kt-prefetch".to_string())
+            .spawn(move || {
+                let fetched = download(&worker_url, &worker_http, &worker_cancelled);
+                let _ = sender.send(fetched);
+            });
+        if spawned.is_ok() {
+            self.pending.push_back(PendingSegment {
+                url,
+                result: Mutex::new(result),
+                cancelled,
+            });
+        }
+    }
+
+    /// Waits for the prefetched body of `url`. Downloads queued before it are
+    /// dropped; if it was never queued, playback moved elsewhere and the whole
+    /// queue is stale.
+    pub fn take(&mut self, url: &reqwest::Url) -> Option<Result<FetchedSegment, RodioError>> {
+        let Some(position) = self.pending.iter().position(|pending| pending.url == *url) else {
+            self.pending.clear();
+            return None;
+        };
+        self.pending.drain(..position);
+        let pending = self.pending.pop_front()?;
+        let result = pending
+            .result
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .recv()
+            .ok();
+        result
+    }
+}
+
+fn download(
+    url: &reqwest::Url,
+    http: &HttpContext,
+    cancelled: &AtomicBool,
+) -> Result<FetchedSegment, RodioError> {
+    let started = Instant::now();
+    let mut response = request_stream(url.as_str(), false, http)?;
+    let mut bytes = Vec::new();
+    let mut chunk = vec![0u8; DOWNLOAD_CHUNK];
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(RodioError::Internal("hls prefetch cancelled".to_string()));
+        }
+        let read = response.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+    }
+    Ok(FetchedSegment {
+        bytes,
+        elapsed: started.elapsed(),
+    })
+}
+
+type Reload = Result<(MediaPlaylist<'static>, LowLatency), RodioError>;
+
+struct RefreshState {
+    url: reqwest::Url,
+    /// Latest reload of `url` not yet taken by the reader, with its generation.
+    latest: Option<(u64, Reload)>,
+    generation: u64,
+    stopped: bool,
+}
+
+struct RefreshShared {
+    state: Mutex<RefreshState>,
+    changed: Condvar,
+}
+
+impl RefreshShared {
+    fn state(&self) -> MutexGuard<'_, RefreshState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Reloads a live media playlist every target duration, or half of it while the
+/// playlist is unchanged, as the HLS spec asks. Low-latency playlists are reloaded
+/// every part target, or right away when the server holds blocking reloads
+/// (`_HLS_msn`/`_HLS_part`) until the next part exists. Failed reloads are retried
+/// with the reconnect policy's backoff and only reported once it gives up, so
+/// playback carries on from the last playlist meanwhile. The thread stops with
+/// its owner, or once the source's HTTP context is cancelled.
+pub struct PlaylistRefresher {
+    shared: Arc<RefreshShared>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl PlaylistRefresher {
+    pub fn spawn(url: reqwest::Url, http: HttpContext) -> Result<Self, RodioError> {
+        let shared = Arc::new(RefreshShared {
+            state: Mutex::new(RefreshState {
+                url,
+                latest: None,
+                generation: 0,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let worker = shared.clone();
+        let cancelled = http.cancelled.clone();
+        thread::Builder::new()
+            .name("rodio-kt-hls-reload".to_string())
+            .spawn(move || run_refresher(&worker, &http))
+            .map_err(|err| {
+                RodioError::Internal(format!("failed to spawn playlist reload: {err}"))
+            })?;
+        Ok(Self { shared, cancelled })
+    }
+
+    /// Switches to another media playlist, reloading it right away.
+    pub fn set_url(&self, url: &reqwest::Url) {
+        let mut state = self.shared.state();
+        if state.url != *url {
+            state.url = url.clone();
+            state.latest = None;
+            self.shared.changed.notify_all();
+        }
+    }
+
+    /// Waits for a reload newer than generation `seen`, returning it with its
+    /// generation. Fails once playback is stopped.
+    pub fn wait_newer(
+        &self,
+        seen: u64,
+    ) -> Result<(u64, (MediaPlaylist<'static>, LowLatency)), RodioError> {
+        let mut state = self.shared.state();
+        loop {
+            if state
+                .latest
+                .as_ref()
+                .is_some_and(|(generation, _)| *generation > seen)
+            {
+                if let Some((generation, playlist)) = state.latest.take() {
+                    return playlist.map(|playlist| (generation, playlist));
+                }
+            }
+            if self.cancelled.load(Ordering::Acquire) {
+                return Err(RodioError::Internal(
+                    "hls playlist reload cancelled".to_string(),
+                ));
+            }
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, CANCEL_POLL)
+                .map(|(state, _)| state)
+                .unwrap_or_else(|err| err.into_inner().0);
+        }
+    }
+}
+
+impl Drop for PlaylistRefresher {
+    fn drop(&mut self) {
+        self.shared.state().stopped = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+fn run_refresher(shared: &RefreshShared, http: &HttpContext) {
+    let mut previous: Option<(usize, usize, usize)> = None;
+    let mut blocking_request = None;
+    let mut failures = 0;
+    loop {
+        let url = {
+            let state = shared.state();
+            if state.stopped || http.cancelled.load(Ordering::Acquire) {
+                return;
+            }
+            state.url.clone()
+        };
+        let request_url = match blocking_request {
+            Some(request) => blocking_reload_url(&url, request),
+            None => url.clone(),
+        };
+        let result = fetch_hls_media_playlist(&request_url, http);
+        let (delay, publish) = match &result {
+            Ok((playlist, low_latency)) => {
+                failures = 0;
+                let shape = (
+                    playlist.media_sequence,
+                    playlist.segments.values().count(),
+                    low_latency.parts.len(),
+                );
+                let changed = previous != Some(shape);
+                previous = Some(shape);
+                blocking_request = low_latency.blocking_request();
+                let target = match low_latency.part_target {
+                    Some(part_target) if low_latency.has_parts() => part_target,
+                    _ => playlist.target_duration,
+                };
+                if changed && blocking_request.is_some() {
+                    (Duration::ZERO, true)
+                } else if changed {
+                    (target, true)
+                } else {
+                    (hls_refresh_delay(target), true)
+                }
+            }
+            Err(_) => {
+                failures += 1;
+                blocking_request = None;
+                (
+                    http.reconnect.delay(failures - 1),
+                    failures > http.reconnect.max_attempts,
+                )
+            }
+        };
+
+        let mut state = shared.state();
+        if state.url != url {
+            // Switched while loading; fetch the new playlist right away.
+            previous = None;
+            blocking_request = None;
+            continue;
+        }
+        if publish {
+            state.generation += 1;
+            state.latest = Some((state.generation, result));
+            shared.changed.notify_all();
+        }
+
+        let waiting = Instant::now();
+        while !state.stopped && state.url == url && !http.cancelled.load(Ordering::Acquire) {
+            let remaining = delay.saturating_sub(waiting.elapsed());
+            if remaining.is_zero() {
+                break;
+            }
+            state = shared
+                .changed
+                .wait_timeout(state, remaining.min(CANCEL_POLL))
+                .map(|(state, _)| state)
+                .unwrap_or_else(|err| err.into_inner().0);
+        }
+        if state.url != url {
+            previous = None;
+            blocking_request = None;
+        }
+    }
+}
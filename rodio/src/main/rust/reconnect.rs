@@ -0,0 +1,206 @@
+//! Retry and reconnection policy for network streams.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::RodioError;
+use crate::tls::pin_mismatch_host;
+
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// Controls how often and how fast a dropped connection is retried.
+/// `max_attempts` counts retries after the first failure; `0` disables retrying.
+#[derive(Clone, Copy, Debug, uniffi::Record)]
+pub struct ReconnectPolicy {
+    #[uniffi(default = 5)]
+    pub max_attempts: u32,
+    #[uniffi(default = 500)]
+    pub initial_delay_ms: u64,
+    #[uniffi(default = 10000)]
+    pub max_delay_ms: u64,
+    /// Fraction of each delay (0.0 to 1.0) that is randomized.
+    #[uniffi(default = 0.25)]
+    pub jitter: f32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay_ms: 500,
+            max_delay_ms: 10_000,
+            jitter: 0.25,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Backoff before retry number `attempt` (zero-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u64.checked_shl(attempt.min(32)).unwrap_or(u64::MAX);
+        let base = self
+            .initial_delay_ms
+            .saturating_mul(factor)
+            .min(self.max_delay_ms.max(self.initial_delay_ms));
+        let jitter = f64::from(self.jitter.clamp(0.0, 1.0));
+        let scale = 1.0 + jitter * (fastrand::f64() * 2.0 - 1.0);
+        Duration::from_millis((base as f64 * scale).round() as u64)
+    }
+}
+
+pub fn is_retryable_status(status: u16) -> bool {
+    (500..=599).contains(&status)
+}
+
+pub fn is_retryable_reqwest(error: &reqwest::Error) -> bool {
+    if pin_mismatch_host(error).is_some() {
+        return false;
+    }
+    if let Some(status) = error.status() {
+        return is_retryable_status(status.as_u16());
+    }
+    error.is_timeout() || error.is_connect() || error.is_request() || error.is_body()
+}
+
+pub fn is_retryable_error(error: &RodioError) -> bool {
+    match error {
+        RodioError::HttpStatus(status) => is_retryable_status(*status),
+        RodioError::Http(_) | RodioError::Io(_) | RodioError::Decoder(_) => true,
+        _ => false,
+    }
+}
+
+/// Runs `operation`, retrying failures that [`is_retryable_error`] accepts
+/// with the policy's backoff. Gives up with the last error once `cancelled` is set.
+pub fn retry<T, F>(
+    policy: &ReconnectPolicy,
+    cancelled: &AtomicBool,
+    mut operation: F,
+) -> Result<T, RodioError>
+where
+    F: FnMut() -> Result<T, RodioError>,
+{
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Err(error) if attempt < policy.max_attempts && is_retryable_error(&error) => {
+                if !sleep_unless_cancelled(policy.delay(attempt), cancelled) {
+                    return Err(error);
+                }
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Sleeps for `duration`, waking early when `cancelled` is set.
+/// Returns `false` if the wait was cancelled.
+pub fn sleep_unless_cancelled(duration: Duration, cancelled: &AtomicBool) -> bool {
+    let start = Instant::now();
+    loop {
+        if cancelled.load(Ordering::Acquire) {
+            return false;
+        }
+        let remaining = duration.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            return true;
+        }
+        thread::sleep(remaining.min(CANCEL_POLL));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn policy(max_attempts: u32, initial_delay_ms: u64, jitter: f32) -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_attempts,
+            initial_delay_ms,
+            max_delay_ms: 10_000,
+            jitter,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let policy = policy(5, 500, 0.0);
+        let delays: Vec<u64> = (0..7)
+            .map(|attempt| policy.delay(attempt).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 10_000, 10_000]);
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(10));
+        // An initial delay above the cap is kept.
+        let slow = ReconnectPolicy {
+            max_delay_ms: 100,
+            ..policy
+        };
+        assert_eq!(slow.delay(3), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn jitter_stays_within_its_fraction() {
+        let policy = policy(5, 1000, 0.25);
+        for _ in 0..200 {
+            let delay = policy.delay(0).as_millis();
+            assert!((750..=1250).contains(&delay), "{delay}");
+        }
+        let clamped = ReconnectPolicy {
+            jitter: 7.0,
+            ..policy
+        };
+        for _ in 0..200 {
+            assert!(clamped.delay(0) <= Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn retry_gives_up_after_max_attempts() {
+        let calls = Cell::new(0);
+        let not_cancelled = AtomicBool::new(false);
+        let result: Result<(), _> = retry(&policy(3, 1, 0.0), &not_cancelled, || {
+            calls.set(calls.get() + 1);
+            Err(RodioError::HttpStatus(503))
+        });
+        assert!(matches!(result, Err(RodioError::HttpStatus(503))));
+        assert_eq!(calls.get(), 4);
+
+        calls.set(0);
+        let result = retry(&policy(3, 1, 0.0), &not_cancelled, || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(RodioError::HttpStatus(502))
+            } else {
+                Ok(calls.get())
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        calls.set(0);
+        let result: Result<(), _> = retry(&policy(3, 1, 0.0), &not_cancelled, || {
+            calls.set(calls.get() + 1);
+            Err(RodioError::HttpStatus(404))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn retry_stops_when_cancelled() {
+        let cancelled = AtomicBool::new(true);
+        let calls = Cell::new(0);
+        let started = Instant::now();
+        let result: Result<(), _> = retry(&policy(5, 60_000, 0.0), &cancelled, || {
+            calls.set(calls.get() + 1);
+            Err(RodioError::HttpStatus(503))
+        });
+        assert!(matches!(result, Err(RodioError::HttpStatus(503))));
+        assert_eq!(calls.get(), 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(!sleep_unless_cancelled(Duration::MAX, &cancelled));
+    }
+}
@@ -0,0 +1,82 @@
+//! Segmented streams whose decoder has to start over partway: at HLS
+//! discontinuities and DASH period boundaries.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A reader that stops (reads return 0) at each section boundary until the
+/// boundary is taken.
+pub trait SectionedStream: Read + Seek + Send {
+    /// Consumes a stop at a boundary, letting reads continue with the next
+    /// section. Returns false when the stream is not stopped at a boundary.
+    fn take_boundary(&mut self) -> bool;
+
+    /// Drops the rest of the segment being read so that reads continue with
+    /// the next one as a new section, initialization data included. Returns
+    /// false when the stream has ended.
+    fn restart_section(&mut self) -> bool;
+}
+
+/// `Seek` for sectioned streams, which can only report where they are: a
+/// seek to the current position succeeds and any other fails as unsupported.
+pub fn seek_in_place(position: u64, pos: SeekFrom) -> io::Result<u64> {
+    match pos {
+        SeekFrom::Current(0) => Ok(position),
+        SeekFrom::Start(offset) if offset == position => Ok(position),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "segmented streams cannot seek",
+        )),
+    }
+}
+
+/// One section of a shared [`SectionedStream`]: reads end at the next boundary,
+/// where a new decoder takes over with [`StreamSection::next_section`].
+pub struct StreamSection<R> {
+    reader: Arc<Mutex<R>>,
+}
+
+impl<R> Clone for StreamSection<R> {
+    fn clone(&self) -> Self {
+        Self {
+            reader: self.reader.clone(),
+        }
+    }
+}
+
+impl<R: SectionedStream> StreamSection<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: Arc::new(Mutex::new(reader)),
+        }
+    }
+
+    /// The section after the boundary this one stopped at; `None` once the
+    /// stream has ended. A decoder that gave up before the boundary, e.g. on a
+    /// broken segment, is followed by a new section at the next segment rather
+    /// than after reading the rest of its own, which a live stream may never end.
+    pub fn next_section(&self) -> Option<Self> {
+        let mut reader = self.reader();
+        if reader.take_boundary() || reader.restart_section() {
+            Some(self.clone())
+        } else {
+            None
+        }
+    }
+
+    fn reader(&self) -> MutexGuard<'_, R> {
+        self.reader.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<R: SectionedStream> Read for StreamSection<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader().read(buf)
+    }
+}
+
+impl<R: SectionedStream> Seek for StreamSection<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.reader().seek(pos)
+    }
+}
@@ -9,6 +9,10 @@ use std::time::Duration;
 use rodio::{cpal::BufferSize, OutputStream, OutputStreamBuilder, Sink};
 
 use crate::error::RodioError;
+use crate::hls::{HlsPlayback, DEFAULT_LIVE_EDGE_SEGMENTS, DEFAULT_PREFETCH_SEGMENTS};
+use crate::mirror::DEFAULT_MIRROR_TIMEOUT;
+use crate::nowplaying::NowPlaying;
+use crate::reconnect::ReconnectPolicy;
 use crate::PlaybackCallback;
 
 pub struct PlayerState {
@@ -16,6 +20,24 @@ pub struct PlayerState {
     pub callback: Option<Arc<dyn PlaybackCallback>>,
     pub current_duration: Option<Duration>,
     pub seekable: bool,
+    pub reconnect: ReconnectPolicy,
+    pub network_profile: Option<String>,
+    /// How long each mirror of a radio playlist may take to answer.
+    pub mirror_timeout: Duration,
+    /// The HLS stream being played, if any.
+    pub hls: Option<HlsPlayback>,
+    /// Stream time at which the current source started, for sources restarted by a seek.
+    pub position_offset: Duration,
+    /// Bandwidth cap applied to every HLS stream this player starts.
+    pub hls_max_bandwidth: Option<u64>,
+    /// How many segments before the live edge live HLS and DASH streams start.
+    pub hls_live_edge_segments: u32,
+    /// Distance from the live edge of low-latency HLS streams; `None` uses the
+    /// playlist's `PART-HOLD-BACK`.
+    pub hls_target_latency: Option<Duration>,
+    pub hls_prefetch_segments: u32,
+    /// Metadata of the current source, merged from everything it reported.
+    pub now_playing: NowPlaying,
 }
 
 impl PlayerState {
@@ -44,6 +66,16 @@ impl PlayerState {
                 callback: None,
                 current_duration: None,
                 seekable: false,
+                reconnect: ReconnectPolicy::default(),
+                network_profile: None,
+                mirror_timeout: DEFAULT_MIRROR_TIMEOUT,
+                hls: None,
+                position_offset: Duration::ZERO,
+                hls_max_bandwidth: None,
+                hls_live_edge_segments: DEFAULT_LIVE_EDGE_SEGMENTS,
+                hls_target_latency: None,
+                hls_prefetch_segments: DEFAULT_PREFETCH_SEGMENTS,
+                now_playing: NowPlaying::default(),
             },
             stream,
         )
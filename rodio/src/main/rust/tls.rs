@@ -0,0 +1,358 @@
+//! TLS configuration for client identities (mutual TLS) and public key pinning.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use p12_keystore::{KeyStore, Pkcs12ImportPolicy};
+use ring::digest::{digest, SHA256};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, OtherError, RootCertStore,
+    SignatureScheme,
+};
+
+use crate::error::RodioError;
+
+/// SHA-256 digest of a DER-encoded SubjectPublicKeyInfo.
+pub type SpkiPin = [u8; 32];
+
+/// Certificate chain and private key presented to servers that request a client certificate.
+pub struct ClientIdentity {
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+}
+
+impl Clone for ClientIdentity {
+    fn clone(&self) -> Self {
+        Self {
+            certs: self.certs.clone(),
+            key: self.key.clone_key(),
+        }
+    }
+}
+
+impl ClientIdentity {
+    /// Parses a PEM bundle holding the certificate chain (leaf first) and its private key.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, RodioError> {
+        let certs = parse_certificates_pem(pem)?;
+        let key = PrivateKeyDer::from_pem_slice(pem)
+            .map_err(|err| RodioError::Tls(format!("invalid client key: {err}")))?;
+        Self::new(certs, key)
+    }
+
+    /// Parses a PKCS#12 archive and uses its first private key with a matching certificate.
+    pub fn from_pkcs12(der: &[u8], password: &str) -> Result<Self, RodioError> {
+        let store = KeyStore::from_pkcs12(der, password, Pkcs12ImportPolicy::Strict)
+            .map_err(|err| RodioError::Tls(format!("invalid PKCS#12 archive: {err}")))?;
+        let (_, chain) = store.private_key_chain().ok_or_else(|| {
+            RodioError::Tls("PKCS#12 archive has no private key with a certificate".to_string())
+        })?;
+        let certs = chain
+            .certs()
+            .iter()
+            .map(|cert| CertificateDer::from(cert.as_der().to_vec()))
+            .collect();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(chain.key().as_der().to_vec()));
+        Self::new(certs, key)
+    }
+
+    fn new(
+        certs: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, RodioError> {
+        if certs.is_empty() {
+            return Err(RodioError::Tls(
+                "client identity has no certificate".to_string(),
+            ));
+        }
+        rustls::crypto::ring::sign::any_supported_type(&key)
+            .map_err(|err| RodioError::Tls(format!("unsupported client key: {err}")))?;
+        Ok(Self { certs, key })
+    }
+}
+
+pub fn parse_certificates_pem(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, RodioError> {
+    let certs = CertificateDer::pem_slice_iter(pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| RodioError::Tls(format!("invalid certificate: {err}")))?;
+    if certs.is_empty() {
+        return Err(RodioError::Tls("no certificate found in PEM".to_string()));
+    }
+    Ok(certs)
+}
+
+/// Accepts `sha256//<base64>` (curl style), plain base64 or 64 hex digits.
+pub fn parse_spki_pin(pin: &str) -> Result<SpkiPin, RodioError> {
+    let value = pin.trim();
+    let value = value.strip_prefix("sha256//").unwrap_or(value);
+    let bytes = if value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        (0..value.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&value[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+    } else {
+        BASE64.decode(value).ok()
+    };
+    bytes
+        .and_then(|bytes| SpkiPin::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| RodioError::Tls(format!("invalid sha256 pin: {pin}")))
+}
+
+/// Pins are looked up by lowercase host name or IP literal, without port or brackets.
+pub fn normalize_pin_host(host: &str) -> String {
+    host.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
+/// Builds the rustls configuration used when a client identity or pins are configured,
+/// since reqwest's own TLS options cannot express either with rustls.
+pub fn client_config(
+    allow_invalid_certs: bool,
+    extra_roots: &[CertificateDer<'static>],
+    identity: Option<&ClientIdentity>,
+    pins: &HashMap<String, Vec<SpkiPin>>,
+) -> Result<ClientConfig, RodioError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let inner = if allow_invalid_certs {
+        None
+    } else {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+        roots.add_parsable_certificates(extra_roots.iter().cloned());
+        let verifier =
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .map_err(|err| RodioError::Tls(err.to_string()))?;
+        Some(verifier)
+    };
+    let verifier = PinningVerifier {
+        inner,
+        pins: pins.clone(),
+        provider: provider.clone(),
+    };
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| RodioError::Tls(err.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    match identity {
+        Some(identity) => builder
+            .with_client_auth_cert(identity.certs.clone(), identity.key.clone_key())
+            .map_err(|err| RodioError::Tls(format!("invalid client identity: {err}"))),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Runs the regular chain verification (unless invalid certificates are allowed),
+/// then requires a pinned host to present at least one pinned key in its chain.
+#[derive(Debug)]
+struct PinningVerifier {
+    inner: Option<Arc<WebPkiServerVerifier>>,
+    pins: HashMap<String, Vec<SpkiPin>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(inner) = &self.inner {
+            inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+        let host = match server_name {
+            ServerName::DnsName(name) => normalize_pin_host(name.as_ref()),
+            ServerName::IpAddress(ip) => std::net::IpAddr::from(*ip).to_string(),
+            _ => return Ok(ServerCertVerified::assertion()),
+        };
+        let Some(pins) = self.pins.get(&host) else {
+            return Ok(ServerCertVerified::assertion());
+        };
+        let matched = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(spki_sha256)
+            .any(|hash| pins.contains(&hash));
+        if matched {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                OtherError(Arc::new(PinMismatch { host })),
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn spki_sha256(cert: &CertificateDer<'_>) -> Option<SpkiPin> {
+    let cert = webpki::EndEntityCert::try_from(cert).ok()?;
+    let spki = cert.subject_public_key_info();
+    SpkiPin::try_from(digest(&SHA256, spki.as_ref()).as_ref()).ok()
+}
+
+#[derive(Debug)]
+struct PinMismatch {
+    host: String,
+}
+
+impl fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "certificate for {} matches no pinned key", self.host)
+    }
+}
+
+impl Error for PinMismatch {}
+
+/// Finds a pin failure anywhere in an error chain, including errors that
+/// hyper wrapped in `io::Error` during the handshake.
+pub fn pin_mismatch_host(error: &(dyn Error + 'static)) -> Option<String> {
+    let mut current = Some(error);
+    while let Some(err) = current {
+        if let Some(mismatch) = err.downcast_ref::<PinMismatch>() {
+            return Some(mismatch.host.clone());
+        }
+        if let Some(rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(other)))) =
+            err.downcast_ref::<rustls::Error>()
+        {
+            if let Some(mismatch) = other.downcast_ref::<PinMismatch>() {
+                return Some(mismatch.host.clone());
+            }
+        }
+        if let Some(inner) = err.downcast_ref::<io::Error>().and_then(io::Error::get_ref) {
+            if let Some(host) = pin_mismatch_host(inner) {
+                return Some(host);
+            }
+        }
+        current = err.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIN: SpkiPin = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31,
+    ];
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn parses_pins_in_every_accepted_form() {
+        let base64 = BASE64.encode(PIN);
+        for pin in [
+            format!("sha256//{base64}"),
+            base64.clone(),
+            format!("  {base64}\n"),
+            hex(&PIN),
+            hex(&PIN).to_ascii_uppercase(),
+            format!("sha256//{}", hex(&PIN)),
+        ] {
+            assert_eq!(parse_spki_pin(&pin).unwrap(), PIN, "{pin}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_pins() {
+        let base64 = BASE64.encode(PIN);
+        for pin in [
+            String::new(),
+            "sha256//".to_string(),
+            format!("sha256/{base64}"),
+            format!("sha1//{base64}"),
+            format!("sha256//{}", base64.trim_end_matches('=')),
+            "not base64!".to_string(),
+            BASE64.encode(&PIN[..31]),
+            BASE64.encode([PIN.as_slice(), &[32]].concat()),
+            hex(&PIN[..31]),
+            format!("{}0", hex(&PIN)),
+            format!("{}g", &hex(&PIN)[..63]),
+        ] {
+            assert!(
+                matches!(parse_spki_pin(&pin), Err(RodioError::Tls(_))),
+                "{pin}"
+            );
+        }
+    }
+
+    #[test]
+    fn normalizes_pin_hosts() {
+        assert_eq!(
+            normalize_pin_host(" Radio.Example.COM. "),
+            "radio.example.com"
+        );
+        assert_eq!(normalize_pin_host("[2001:DB8::1]"), "2001:db8::1");
+        assert_eq!(normalize_pin_host("192.0.2.1"), "192.0.2.1");
+    }
+
+    #[test]
+    fn finds_pin_mismatches_in_wrapped_errors() {
+        let mismatch = || PinMismatch {
+            host: "example.com".to_string(),
+        };
+        let rustls_error = rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(
+            Arc::new(mismatch()),
+        )));
+        let wrapped = io::Error::other(rustls_error);
+        assert_eq!(pin_mismatch_host(&wrapped).as_deref(), Some("example.com"));
+        assert_eq!(
+            pin_mismatch_host(&mismatch()).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(pin_mismatch_host(&io::Error::other("reset")), None);
+    }
+}
@@ -0,0 +1,480 @@
+//! MPEG-2 Transport Stream demuxing for HLS segments.
+
+use std::io::{self, Read};
+use std::time::Duration;
+
+use crate::cue::SegmentCues;
+use crate::id3;
+
+const PACKET_LEN: usize = 188;
+/// Bytes needed to recognise a transport stream: one packet and the next sync byte.
+pub const PROBE_LEN: usize = PACKET_LEN + 1;
+const SYNC_BYTE: u8 = 0x47;
+const PAT_PID: u16 = 0x0000;
+const PES_HEADER_LEN: usize = 9;
+
+/// Elementary stream types the decoder can take once demuxed.
+const STREAM_TYPE_MPEG1_AUDIO: u8 = 0x03;
+const STREAM_TYPE_MPEG2_AUDIO: u8 = 0x04;
+const STREAM_TYPE_ADTS_AAC: u8 = 0x0F;
+/// ADTS AAC with SAMPLE-AES encrypted frames.
+const STREAM_TYPE_SAMPLE_AES_AAC: u8 = 0xCF;
+/// Timed metadata carried in PES packets, ID3 for HLS.
+const STREAM_TYPE_METADATA: u8 = 0x15;
+/// PTS values wrap at 33 bits.
+const PTS_MASK: u64 = (1 << 33) - 1;
+
+/// Transport streams start with a sync byte that repeats every packet.
+pub fn is_ts(data: &[u8]) -> bool {
+    data.len() >= PACKET_LEN
+        && data[0] == SYNC_BYTE
+        && data.get(PACKET_LEN).is_none_or(|&byte| byte == SYNC_BYTE)
+}
+
+/// Yields the payload of the first audio elementary stream announced in the
+/// program map table, reassembled from its PES packets. ID3 tags from a
+/// metadata stream are queued at their offset from the segment's first audio sample.
+pub struct TsDemuxer<R> {
+    inner: R,
+    packet: [u8; PACKET_LEN],
+    pmt_pid: Option<u16>,
+    audio_pid: Option<u16>,
+    id3_pid: Option<u16>,
+    /// PES packet being reassembled for the audio stream.
+    pes: Vec<u8>,
+    id3_pes: Vec<u8>,
+    first_audio_pts: Option<u64>,
+    /// Tags seen before the first audio timestamp, with their own timestamps.
+    pending_id3: Vec<(Option<u64>, Vec<u8>)>,
+    cues: SegmentCues,
+    out: Vec<u8>,
+    out_pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> TsDemuxer<R> {
+    pub fn new(inner: R, cues: SegmentCues) -> Self {
+        Self {
+            inner,
+            packet: [0; PACKET_LEN],
+            pmt_pid: None,
+            audio_pid: None,
+            id3_pid: None,
+            pes: Vec::new(),
+            id3_pes: Vec::new(),
+            first_audio_pts: None,
+            pending_id3: Vec::new(),
+            cues,
+            out: Vec::new(),
+            out_pos: 0,
+            eof: false,
+        }
+    }
+
+    /// Reads the next packet, skipping garbage until a sync byte. Returns false at EOF.
+    fn read_packet(&mut self) -> io::Result<bool> {
+        let mut filled = 0;
+        loop {
+            while filled < PACKET_LEN {
+                let read = self.inner.read(&mut self.packet[filled..])?;
+                if read == 0 {
+                    // A truncated trailing packet is dropped.
+                    return Ok(false);
+                }
+                filled += read;
+            }
+            match self.packet.iter().position(|&byte| byte == SYNC_BYTE) {
+                Some(0) => return Ok(true),
+                Some(offset) => {
+                    self.packet.copy_within(offset.., 0);
+                    filled = PACKET_LEN - offset;
+                }
+                None => filled = 0,
+            }
+        }
+    }
+
+    fn handle_packet(&mut self) -> io::Result<()> {
+        let packet = self.packet;
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let unit_start = packet[1] & 0x40 != 0;
+        let adaptation = (packet[3] >> 4) & 0x03;
+        if adaptation & 0x01 == 0 {
+            return Ok(());
+        }
+        let mut offset = 4;
+        if adaptation & 0x02 != 0 {
+            offset += 1 + usize::from(packet[4]);
+        }
+        if offset >= PACKET_LEN {
+            return Ok(());
+        }
+        let payload = &packet[offset..];
+
+        if Some(pid) == self.audio_pid {
+            if unit_start {
+                self.flush_pes();
+            }
+            // Continuation data before the first unit start belongs to a cut-off packet.
+            if unit_start || !self.pes.is_empty() {
+                self.pes.extend_from_slice(payload);
+            }
+        } else if Some(pid) == self.id3_pid {
+            if unit_start {
+                self.flush_id3();
+            }
+            if unit_start || !self.id3_pes.is_empty() {
+                self.id3_pes.extend_from_slice(payload);
+            }
+        } else if pid == PAT_PID && unit_start {
+            if let Some(section) = psi_section(payload) {
+                self.pmt_pid = parse_pat(section);
+            }
+        } else if Some(pid) == self.pmt_pid && unit_start && self.audio_pid.is_none() {
+            if let Some(section) = psi_section(payload) {
+                let (audio_pid, id3_pid) = parse_pmt(section)?;
+                self.audio_pid = Some(audio_pid);
+                self.id3_pid = id3_pid;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the payload of the PES packet collected so far to the output.
+    fn flush_pes(&mut self) {
+        let pes = std::mem::take(&mut self.pes);
+        let Some((pts, payload)) = pes_payload(&pes) else {
+            return;
+        };
+        self.out.extend_from_slice(payload);
+        if self.first_audio_pts.is_none() && pts.is_some() {
+            self.first_audio_pts = pts;
+            for (pts, tag) in std::mem::take(&mut self.pending_id3) {
+                self.queue_id3(pts, &tag);
+            }
+        }
+    }
+
+    fn flush_id3(&mut self) {
+        let pes = std::mem::take(&mut self.id3_pes);
+        let Some((pts, payload)) = pes_payload(&pes) else {
+            return;
+        };
+        if self.first_audio_pts.is_some() {
+            self.queue_id3(pts, payload);
+        } else {
+            self.pending_id3.push((pts, payload.to_vec()));
+        }
+    }
+
+    fn queue_id3(&self, pts: Option<u64>, payload: &[u8]) {
+        let offset = match (pts, self.first_audio_pts) {
+            (Some(pts), Some(base)) => pts_offset(pts, base),
+            _ => Duration::ZERO,
+        };
+        let mut rest = payload;
+        while let Some(len) = id3::tag_len(rest) {
+            self.cues.push(offset, id3::parse_tag(&rest[..len]));
+            rest = &rest[len..];
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        while self.out_pos == self.out.len() && !self.eof {
+            self.out.clear();
+            self.out_pos = 0;
+            if self.read_packet()? {
+                self.handle_packet()?;
+            } else {
+                self.eof = true;
+                self.flush_pes();
+                self.flush_id3();
+                for (_, tag) in std::mem::take(&mut self.pending_id3) {
+                    self.queue_id3(None, &tag);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for TsDemuxer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill()?;
+        let available = &self.out[self.out_pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.out_pos += len;
+        Ok(len)
+    }
+}
+
+/// Timestamp and payload of a complete PES packet.
+fn pes_payload(pes: &[u8]) -> Option<(Option<u64>, &[u8])> {
+    if pes.len() < PES_HEADER_LEN || pes[..3] != [0x00, 0x00, 0x01] {
+        return None;
+    }
+    let header_len = usize::from(pes[8]);
+    let start = PES_HEADER_LEN + header_len;
+    let declared = usize::from(u16::from_be_bytes([pes[4], pes[5]]));
+    // A zero length means "unbounded"; otherwise drop the packet padding.
+    let end = if declared == 0 {
+        pes.len()
+    } else {
+        (6 + declared).min(pes.len())
+    };
+    let pts = (pes[7] & 0x80 != 0 && header_len >= 5)
+        .then(|| pes.get(9..14))
+        .flatten()
+        .map(|b| {
+            (u64::from(b[0] >> 1 & 0x07) << 30)
+                | (u64::from(b[1]) << 22)
+                | (u64::from(b[2] >> 1) << 15)
+                | (u64::from(b[3]) << 7)
+                | u64::from(b[4] >> 1)
+        });
+    Some((pts, pes.get(start..end).unwrap_or_default()))
+}
+
+/// Time from `base` to `pts` on the 90 kHz clock; timestamps before `base` map to zero.
+fn pts_offset(pts: u64, base: u64) -> Duration {
+    let diff = pts.wrapping_sub(base) & PTS_MASK;
+    if diff > PTS_MASK / 2 {
+        return Duration::ZERO;
+    }
+    Duration::from_micros(diff * 100 / 9)
+}
+
+/// Skips the pointer field and returns the section, bounded by its length.
+fn psi_section(payload: &[u8]) -> Option<&[u8]> {
+    let pointer = usize::from(*payload.first()?);
+    let section = payload.get(1 + pointer..)?;
+    if section.len() < 3 {
+        return None;
+    }
+    let length = (usize::from(section[1] & 0x0F) << 8) | usize::from(section[2]);
+    section.get(..(3 + length).min(section.len()))
+}
+
+/// PID of the first program's map table.
+fn parse_pat(section: &[u8]) -> Option<u16> {
+    if section.first() != Some(&0x00) {
+        return None;
+    }
+    // Program entries follow the 8-byte header and precede the 4-byte CRC.
+    let entries = section.get(8..section.len().checked_sub(4)?)?;
+    entries
+        .chunks_exact(4)
+        .find(|entry| u16::from_be_bytes([entry[0], entry[1]]) != 0)
+        .map(|entry| (u16::from(entry[2] & 0x1F) << 8) | u16::from(entry[3]))
+}
+
+/// PIDs of the first elementary stream the decoder understands and of the
+/// first timed metadata stream.
+fn parse_pmt(section: &[u8]) -> io::Result<(u16, Option<u16>)> {
+    let unsupported = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no supported audio stream in MPEG-TS segment",
+        )
+    };
+    if section.first() != Some(&0x02) || section.len() < 16 {
+        return Err(unsupported());
+    }
+    let program_info_len = (usize::from(section[10] & 0x0F) << 8) | usize::from(section[11]);
+    let end = section.len() - 4;
+    let mut offset = 12 + program_info_len;
+    let mut audio_pid = None;
+    let mut id3_pid = None;
+    while offset + 5 <= end {
+        let stream_type = section[offset];
+        let pid = (u16::from(section[offset + 1] & 0x1F) << 8) | u16::from(section[offset + 2]);
+        let info_len =
+            (usize::from(section[offset + 3] & 0x0F) << 8) | usize::from(section[offset + 4]);
+        match stream_type {
+            STREAM_TYPE_MPEG1_AUDIO
+            | STREAM_TYPE_MPEG2_AUDIO
+            | STREAM_TYPE_ADTS_AAC
+            | STREAM_TYPE_SAMPLE_AES_AAC => {
+                audio_pid.get_or_insert(pid);
+            }
+            STREAM_TYPE_METADATA => {
+                id3_pid.get_or_insert(pid);
+            }
+            _ => {}
+        }
+        offset += 5 + info_len;
+    }
+    audio_pid.map(|pid| (pid, id3_pid)).ok_or_else(unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::cue::MetadataCues;
+
+    const PMT_PID: u16 = 0x100;
+    const AUDIO_PID: u16 = 0x101;
+    const ID3_PID: u16 = 0x102;
+
+    /// One transport packet, padded with an adaptation field.
+    fn packet(pid: u16, unit_start: bool, payload: &[u8]) -> Vec<u8> {
+        let start = if unit_start { 0x40 } else { 0 };
+        let mut packet = vec![SYNC_BYTE, start | (pid >> 8) as u8, pid as u8];
+        let stuffing = PACKET_LEN - 4 - payload.len();
+        if stuffing == 0 {
+            packet.push(0x10);
+        } else {
+            packet.push(0x30);
+            packet.push((stuffing - 1) as u8);
+            if stuffing > 1 {
+                packet.push(0x00);
+                packet.resize(packet.len() + stuffing - 2, 0xFF);
+            }
+        }
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// A PSI section behind its pointer field, with a dummy CRC.
+    fn psi(table_id: u8, body: &[u8]) -> Vec<u8> {
+        let length = body.len() + 4;
+        let mut section = vec![0, table_id, 0xB0 | (length >> 8) as u8, length as u8];
+        section.extend_from_slice(body);
+        section.extend_from_slice(&[0; 4]);
+        section
+    }
+
+    fn pat() -> Vec<u8> {
+        let pmt = [0xE0 | (PMT_PID >> 8) as u8, PMT_PID as u8];
+        psi(0x00, &[0, 1, 0xC1, 0, 0, 0, 1, pmt[0], pmt[1]])
+    }
+
+    fn pmt(streams: &[(u8, u16)]) -> Vec<u8> {
+        let mut body = vec![0, 1, 0xC1, 0, 0, 0xE1, 0x00, 0xF0, 0x00];
+        for (stream_type, pid) in streams {
+            body.extend_from_slice(&[*stream_type, 0xE0 | (pid >> 8) as u8, *pid as u8, 0xF0, 0]);
+        }
+        psi(0x02, &body)
+    }
+
+    fn pes(stream_id: u8, pts: u64, payload: &[u8]) -> Vec<u8> {
+        let length = 3 + 5 + payload.len();
+        let mut pes = vec![
+            0,
+            0,
+            1,
+            stream_id,
+            (length >> 8) as u8,
+            length as u8,
+            0x80,
+            0x80,
+            5,
+        ];
+        pes.extend_from_slice(&[
+            0x21 | ((pts >> 29) & 0x0E) as u8,
+            (pts >> 22) as u8,
+            ((pts >> 14) & 0xFE) as u8 | 1,
+            (pts >> 7) as u8,
+            ((pts << 1) & 0xFE) as u8 | 1,
+        ]);
+        pes.extend_from_slice(payload);
+        pes
+    }
+
+    fn demux(data: Vec<u8>) -> io::Result<Vec<u8>> {
+        let cues = SegmentCues {
+            cues: Arc::new(MetadataCues::default()),
+            start: Duration::ZERO,
+        };
+        let mut out = Vec::new();
+        TsDemuxer::new(Cursor::new(data), cues).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    fn segment() -> Vec<u8> {
+        let audio = pes(0xC0, 90_000, &[0xAA; 300]);
+        let mut data = packet(PAT_PID, true, &pat());
+        data.extend(packet(
+            PMT_PID,
+            true,
+            &pmt(&[
+                (0x1B, 0x1FF),
+                (STREAM_TYPE_ADTS_AAC, AUDIO_PID),
+                (STREAM_TYPE_METADATA, ID3_PID),
+            ]),
+        ));
+        data.extend(packet(
+            ID3_PID,
+            true,
+            &pes(0xBD, 180_000, b"ID3\x04\0\0\0\0\0\0"),
+        ));
+        data.extend(packet(AUDIO_PID, true, &audio[..184]));
+        data.extend(packet(AUDIO_PID, false, &audio[184..]));
+        data.extend(packet(AUDIO_PID, true, &pes(0xC0, 93_000, &[0xBB; 20])));
+        data
+    }
+
+    #[test]
+    fn recognises_transport_streams() {
+        let data = segment();
+        assert!(is_ts(&data[..PROBE_LEN]));
+        assert!(!is_ts(&data[1..PROBE_LEN + 1]));
+        assert!(!is_ts(b"ID3\x04"));
+    }
+
+    #[test]
+    fn demuxes_the_audio_stream_from_the_program_map() {
+        let out = demux(segment());
+        let mut expected = vec![0xAA; 300];
+        expected.extend([0xBB; 20]);
+        assert_eq!(out.unwrap(), expected);
+    }
+
+    #[test]
+    fn skips_garbage_before_a_sync_byte() {
+        let mut data = vec![0x00, 0x12, 0x34];
+        data.extend(segment());
+        assert_eq!(demux(data).unwrap().len(), 320);
+    }
+
+    #[test]
+    fn rejects_programs_without_supported_audio() {
+        let mut data = packet(PAT_PID, true, &pat());
+        data.extend(packet(PMT_PID, true, &pmt(&[(0x1B, 0x1FF)])));
+        let error = demux(data).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reads_pes_timestamps() {
+        let pes = pes(0xC0, PTS_MASK - 5, b"xy");
+        assert_eq!(pes_payload(&pes), Some((Some(PTS_MASK - 5), &b"xy"[..])));
+        assert_eq!(pes_payload(&pes[..4]), None);
+        assert_eq!(pts_offset(4, PTS_MASK - 5), Duration::from_micros(1000 / 9));
+        assert_eq!(pts_offset(0, 90_000), Duration::ZERO);
+    }
+
+    #[test]
+    fn malformed_segments_do_not_panic() {
+        let data = segment();
+        for len in (0..data.len()).step_by(7) {
+            let _ = demux(data[..len].to_vec());
+        }
+        // Corrupt one byte of every packet in turn.
+        for index in 0..PACKET_LEN {
+            let mut corrupt = data.clone();
+            for packet in corrupt.chunks_mut(PACKET_LEN) {
+                packet[index] ^= 0xFF;
+            }
+            let _ = demux(corrupt);
+        }
+        assert_eq!(psi_section(&[]), None);
+        assert_eq!(psi_section(&[200, 0]), None);
+        assert_eq!(parse_pat(&[0x00, 0xB0, 0x01]), None);
+        assert!(parse_pmt(&[0x02; 8]).is_err());
+    }
+}
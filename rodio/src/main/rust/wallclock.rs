@@ -0,0 +1,181 @@
+//! Real-world time of HLS playback, from `EXT-X-PROGRAM-DATE-TIME`.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::pcm::PlaybackClock;
+
+/// Dates of segments that have been played long ago are dropped oldest first.
+const MAX_DATES: usize = 64;
+
+/// Program dates queued by stream time, measured like [`crate::cue::MetadataCues`].
+/// A `None` date marks a discontinuity the playlist left undated, after which
+/// the earlier dates no longer apply.
+#[derive(Default)]
+pub struct ProgramDates {
+    dates: Mutex<VecDeque<(Duration, Option<u64>)>>,
+    /// Clock of the decoded stream and the stream time at which it started.
+    clock: Mutex<Option<(PlaybackClock, Duration)>>,
+}
+
+impl ProgramDates {
+    /// Records that the segment starting at stream time `at` began at `epoch_ms`.
+    pub fn push(&self, at: Duration, epoch_ms: Option<u64>) {
+        let mut dates = self.dates.lock().unwrap_or_else(|err| err.into_inner());
+        if dates.len() >= MAX_DATES {
+            dates.pop_front();
+        }
+        let index = dates.partition_point(|(time, _)| *time <= at);
+        dates.insert(index, (at, epoch_ms));
+    }
+
+    /// Follows the playback of a (re)started decoder, which begins `start` into the stream.
+    pub fn attach_clock(&self, clock: PlaybackClock, start: Duration) {
+        *self.clock.lock().unwrap_or_else(|err| err.into_inner()) = Some((clock, start));
+    }
+
+    /// Milliseconds since the Unix epoch of the audio being played, extrapolated
+    /// from the last dated segment at or before it.
+    pub fn now_ms(&self) -> Option<u64> {
+        let played = {
+            let clock = self.clock.lock().unwrap_or_else(|err| err.into_inner());
+            let (clock, start) = clock.as_ref()?;
+            *start + clock.played()
+        };
+        let dates = self.dates.lock().unwrap_or_else(|err| err.into_inner());
+        let index = dates.partition_point(|(time, _)| *time <= played);
+        let (at, epoch_ms) = dates.get(index.checked_sub(1)?)?;
+        let elapsed = u64::try_from((played - *at).as_millis()).unwrap_or(u64::MAX);
+        epoch_ms.map(|epoch_ms| epoch_ms.saturating_add(elapsed))
+    }
+}
+
+/// Parses an ISO 8601 date-time as used by `EXT-X-PROGRAM-DATE-TIME`,
+/// `YYYY-MM-DDThh:mm:ss[.fff](Z|±hh[:mm])`, into milliseconds since the Unix
+/// epoch. Dates before 1970 are rejected.
+pub fn parse_date_time(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = parse_digits(date_parts.next()?, 4)?;
+    let month: u32 = parse_digits(date_parts.next()?, 2)?;
+    let day: u32 = parse_digits(date_parts.next()?, 2)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (clock, offset_secs) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        (clock, 0)
+    } else {
+        let sign_at = time.rfind(['+', '-'])?;
+        let (clock, offset) = time.split_at(sign_at);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let offset = offset[1..].replace(':', "");
+        let hours: i64 = parse_digits(offset.get(..2)?, 2)?;
+        let minutes: i64 = match offset.get(2..) {
+            Some("") | None => 0,
+            Some(minutes) => parse_digits(minutes, 2)?,
+        };
+        (clock, sign * (hours * 3600 + minutes * 60))
+    };
+
+    let (whole, fraction) = clock.split_once(['.', ',']).unwrap_or((clock, ""));
+    let mut clock_parts = whole.splitn(3, ':');
+    let hour: i64 = parse_digits(clock_parts.next()?, 2)?;
+    let minute: i64 = parse_digits(clock_parts.next()?, 2)?;
+    let second: i64 = parse_digits(clock_parts.next()?, 2)?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let millis = fraction
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(3)
+        .fold(0, |millis, digit| millis * 10 + i64::from(digit - b'0'));
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second - offset_secs;
+    u64::try_from(secs * 1000 + millis).ok()
+}
+
+fn parse_digits<T: std::str::FromStr>(text: &str, len: usize) -> Option<T> {
+    if text.len() != len || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+/// Days from 1970-01-01 to a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_days_across_leap_years_and_eras() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+        assert_eq!(days_from_civil(2100, 3, 1), 47_541);
+        assert_eq!(days_from_civil(1600, 1, 1), -135_140);
+    }
+
+    #[test]
+    fn parses_program_date_times() {
+        let expected = Some(1_709_210_096_789);
+        assert_eq!(parse_date_time("2024-02-29T12:34:56.789Z"), expected);
+        assert_eq!(parse_date_time(" 2024-02-29t12:34:56.789z "), expected);
+        assert_eq!(parse_date_time("2024-02-29T14:34:56.789+02:00"), expected);
+        assert_eq!(parse_date_time("2024-02-29T09:04:56.789-0330"), expected);
+        assert_eq!(parse_date_time("2024-02-29T12:34:56.7891234Z"), expected);
+        assert_eq!(
+            parse_date_time("2024-02-29T12:34:56,7Z"),
+            Some(1_709_210_096_700)
+        );
+        assert_eq!(parse_date_time("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_date_time("1970-01-01T01:00:00+01"), Some(0));
+    }
+
+    #[test]
+    fn rejects_malformed_date_times() {
+        for value in [
+            "",
+            "T",
+            "2024-02-29",
+            "2024-02-29T12:34:56",
+            "2024-13-01T00:00:00Z",
+            "2024-00-01T00:00:00Z",
+            "2024-01-32T00:00:00Z",
+            "2024-1-01T00:00:00Z",
+            "2024-01-01T24:00:00Z",
+            "2024-01-01T12:00:00.5aZ",
+            "2024-01-01T12:00:00+",
+            "2024-01-01T12:00:00+1",
+            "1969-12-31T23:59:59Z",
+            "２０２４-01-01T12:00:00Z",
+            "2024-01-01T12:00:00+０１:00",
+        ] {
+            assert_eq!(parse_date_time(value), None, "{value}");
+        }
+        let valid = "2024-02-29T14:34:56.789+02:00";
+        for len in 0..valid.len() {
+            parse_date_time(&valid[..len]);
+        }
+    }
+}
@@ -44,6 +44,7 @@ private class PlaybackObserver : PlaybackCallback {
     val events = Channel<PlaybackEvent>(Channel.UNLIMITED)
     val errors = Channel<String>(Channel.UNLIMITED)
     val metadata = Channel<Pair<String, String>>(Channel.UNLIMITED)
+    val nowPlaying = Channel<NowPlaying>(Channel.UNLIMITED)
 
     override fun onEvent(event: PlaybackEvent) {
         events.trySend(event)
@@ -53,6 +54,10 @@ private class PlaybackObserver : PlaybackCallback {
         metadata.trySend(key to value)
     }
 
+    override fun onNowPlaying(nowPlaying: NowPlaying) {
+        this.nowPlaying.trySend(nowPlaying)
+    }
+
     override fun onError(message: String) {
         errors.trySend(message)
     }
@@ -93,6 +98,8 @@ class HttpPlaybackIntegrationTest {
                     // Drain any metadata events; these static files are expected to emit none.
                     val metadataPairs = observer.metadata.drain()
                     assertTrue(metadataPairs.isEmpty(), "Expected no metadata for $source but got $metadataPairs")
+                    val nowPlayingUpdates = observer.nowPlaying.drain()
+                    assertTrue(nowPlayingUpdates.isEmpty(), "Expected no now-playing updates for $source but got $nowPlayingUpdates")
                 }
                 is PlaybackOutcome.Error -> fail("Playback error for $source: ${outcome.message}")
                 PlaybackOutcome.Timeout -> fail("Timeout while waiting for playback start for $source")
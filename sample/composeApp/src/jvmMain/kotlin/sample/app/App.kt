@@ -37,6 +37,7 @@ import androidx.compose.ui.graphics.Color
 import androidx.compose.ui.text.TextStyle
 import androidx.compose.ui.unit.dp
 import io.github.kdroidfilter.rodio.RodioPlayer
+import io.github.kdroidfilter.rodio.native.NowPlaying
 import io.github.kdroidfilter.rodio.native.PlaybackCallback
 import io.github.kdroidfilter.rodio.native.PlaybackEvent
 import io.github.kdroidfilter.souvlaki.MediaControls
@@ -61,6 +62,7 @@ fun App() {
     var filePath by remember { mutableStateOf("") }
     var streamUrl by remember { mutableStateOf("https://broadcast.adpronet.com/radio/6060/radio.mp3") }
     var playbackEvent by remember { mutableStateOf(PlaybackEvent.STOPPED) }
+    var currentTrack by remember { mutableStateOf<NowPlaying?>(null) }
     var positionMs by remember { mutableStateOf(0L) }
     var durationMs by remember { mutableStateOf<Long?>(null) }
     var userSeekMs by remember { mutableStateOf<Long?>(null) }
@@ -89,6 +91,10 @@ fun App() {
                 // No-op for minimal player.
             }
 
+            override fun onNowPlaying(nowPlaying: NowPlaying) {
+                scope.launch { currentTrack = nowPlaying }
+            }
+
             override fun onError(message: String) {
                 println("Playback error: $message")
                 scope.launch { playbackEvent = PlaybackEvent.STOPPED }
@@ -125,6 +131,9 @@ fun App() {
         PlaybackEvent.PLAYING -> "Playing"
         PlaybackEvent.PAUSED -> "Paused"
         PlaybackEvent.STOPPED -> "Stopped"
+        PlaybackEvent.RECONNECTING -> "Reconnecting"
+        PlaybackEvent.RECONNECTED -> "Reconnected"
+        PlaybackEvent.MIRROR_FALLBACK -> "Trying next mirror"
     }
     val progress = durationMs
         ?.takeIf { it > 0L }
@@ -422,6 +431,11 @@ fun App() {
             Spacer(modifier = Modifier.height(12.dp))
 
             BasicText("Status: $statusLabel", style = TextStyle(color = Color.Black))
+            val nowPlayingLabel = currentTrack
+                ?.let { listOfNotNull(it.artist, it.title).joinToString(" - ").ifEmpty { it.stationName } }
+            if (nowPlayingLabel != null && playbackEvent != PlaybackEvent.STOPPED) {
+                BasicText("Now playing: $nowPlayingLabel", style = TextStyle(color = Color.Gray))
+            }
             Spacer(modifier = Modifier.height(8.dp))
             val totalDuration = durationMs
             val durationLabel = totalDuration?.let { formatTime(it) } ?: "--:--"